@@ -14,13 +14,38 @@ typedef struct {
     float score;
 } CMatchResult;
 
+// Face result with bounding box (in the coordinates of the frame passed in)
+typedef struct {
+    char* name;
+    float score;
+    int x;
+    int y;
+    int w;
+    int h;
+} CFaceResult;
+
+// Error codes returned by facerecognition_last_error_code()
+#define FACERUST_OK 0
+#define FACERUST_ERR_INVALID_ARGUMENT 1
+// Worth retrying after a short backoff (I/O, detection failure, database still loading)
+#define FACERUST_ERR_TRANSIENT 2
+#define FACERUST_ERR_TERMINAL 3
+
+// Code of the last error on the calling thread (FACERUST_OK after a successful call)
+int facerecognition_last_error_code();
+
+// Message of the last error on the calling thread, or NULL; valid until the next call
+const char* facerecognition_last_error_message();
+
 // Create a new FaceRecognition instance
 CFaceRecognition* facerecognition_create();
 
 // Load persons database from directory
 int facerecognition_load_persons_db(CFaceRecognition* face_rec, const char* db_path);
 
-// Run face recognition on OpenCV Mat data
+// Run face recognition on OpenCV Mat data.
+// mat_data (rows * cols * channels bytes) is borrowed, not copied: it must stay valid
+// and unmodified until the call returns; it is not used afterwards.
 CMatchResult facerecognition_run_one_face_opencv_mat(
     CFaceRecognition* face_rec,
     const unsigned char* mat_data,
@@ -30,9 +55,62 @@ CMatchResult facerecognition_run_one_face_opencv_mat(
     float threshold
 );
 
+// Same, but copies mat_data first; the buffer is only read during the copy
+CMatchResult facerecognition_run_one_face_opencv_mat_copy(
+    CFaceRecognition* face_rec,
+    const unsigned char* mat_data,
+    int rows,
+    int cols,
+    int channels,
+    float threshold
+);
+
+// Like facerecognition_run_one_face_opencv_mat, plus the box of the best face
+// (all zero with name "Unknown" when no face was found)
+CFaceResult facerecognition_run_best_face_opencv_mat(
+    CFaceRecognition* face_rec,
+    const unsigned char* mat_data,
+    int rows,
+    int cols,
+    int channels,
+    float threshold
+);
+
+// Recognize all faces; returns an array of *out_count results (NULL if none or on
+// error), freed with facerecognition_free_face_results
+CFaceResult* facerecognition_run_opencv_mat(
+    CFaceRecognition* face_rec,
+    const unsigned char* mat_data,
+    int rows,
+    int cols,
+    int channels,
+    float threshold,
+    int* out_count
+);
+
+// Run face recognition on an image file; errors return "unknown" (see last error)
+CMatchResult facerecognition_recognize_file(
+    CFaceRecognition* face_rec,
+    const char* image_path,
+    float threshold
+);
+
+// Recognize all images in a directory and write a JSON report to out_json_path.
+// Returns the number of images processed, or -1 on error.
+int facerecognition_recognize_dir(
+    CFaceRecognition* face_rec,
+    const char* dir_path,
+    const char* out_json_path,
+    float threshold
+);
+
 // Free memory allocated for match result
 void facerecognition_free_match_result(CMatchResult* result);
 
+// Free memory allocated for face results
+void facerecognition_free_face_result(CFaceResult* result);
+void facerecognition_free_face_results(CFaceResult* results, int count);
+
 // Destroy FaceRecognition instance
 void facerecognition_destroy(CFaceRecognition* face_rec);
 
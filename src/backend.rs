@@ -0,0 +1,63 @@
+//! Model abstraction used by `FaceRecognition`.
+//!
+//! The OpenCV `FaceDetectorYN` / `FaceRecognizerSF` models implement these traits; other
+//! implementations (such as the scripted ones in `mock`) can be plugged in through
+//! `FaceRecognition::with_backends`.
+
+#[cfg(feature = "umat")]
+use opencv::core::{AccessFlag, UMat};
+use opencv::{
+    core::{Mat, Ptr, Size},
+    objdetect::{FaceDetectorYN, FaceRecognizerSF},
+    prelude::*,
+};
+
+/// Face detector producing YuNet-style rows (see `FaceRecognition::detect_raw`)
+pub trait Detector: Send {
+    fn set_input_size(&mut self, size: Size) -> opencv::Result<()>;
+    fn detect(&mut self, frame: &Mat, faces: &mut Mat) -> opencv::Result<()>;
+
+    /// Detect on a `UMat`; the default maps it to host memory and calls `detect`
+    #[cfg(feature = "umat")]
+    fn detect_umat(&mut self, frame: &UMat, faces: &mut Mat) -> opencv::Result<()> {
+        let frame = frame.get_mat(AccessFlag::ACCESS_READ)?;
+        self.detect(&frame, faces)
+    }
+}
+
+/// Face recognizer producing one embedding row per aligned face
+pub trait Recognizer: Send {
+    fn align_crop(&self, frame: &Mat, face: &Mat, aligned: &mut Mat) -> opencv::Result<()>;
+    fn feature(&mut self, aligned: &Mat, feature: &mut Mat) -> opencv::Result<()>;
+    /// Similarity of two embeddings; `dis_type` is a `FaceRecognizerSF_DisType` value
+    fn match_(&self, feature1: &Mat, feature2: &Mat, dis_type: i32) -> opencv::Result<f64>;
+}
+
+impl Detector for Ptr<FaceDetectorYN> {
+    fn set_input_size(&mut self, size: Size) -> opencv::Result<()> {
+        FaceDetectorYNTrait::set_input_size(self, size)
+    }
+
+    fn detect(&mut self, frame: &Mat, faces: &mut Mat) -> opencv::Result<()> {
+        FaceDetectorYNTrait::detect(self, frame, faces).map(|_| ())
+    }
+
+    #[cfg(feature = "umat")]
+    fn detect_umat(&mut self, frame: &UMat, faces: &mut Mat) -> opencv::Result<()> {
+        FaceDetectorYNTrait::detect(self, frame, faces).map(|_| ())
+    }
+}
+
+impl Recognizer for Ptr<FaceRecognizerSF> {
+    fn align_crop(&self, frame: &Mat, face: &Mat, aligned: &mut Mat) -> opencv::Result<()> {
+        FaceRecognizerSFTraitConst::align_crop(self, frame, face, aligned)
+    }
+
+    fn feature(&mut self, aligned: &Mat, feature: &mut Mat) -> opencv::Result<()> {
+        FaceRecognizerSFTrait::feature(self, aligned, feature)
+    }
+
+    fn match_(&self, feature1: &Mat, feature2: &Mat, dis_type: i32) -> opencv::Result<f64> {
+        FaceRecognizerSFTraitConst::match_(self, feature1, feature2, dis_type)
+    }
+}
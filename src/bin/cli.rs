@@ -1,20 +1,24 @@
 use clap::{Arg, Command};
-use facerust::FaceRecognition;
+use facerust::{
+    image_files, watcher::FolderWatcher, FaceRecognition, MatchResult, ResultsCsvWriter,
+};
 use opencv::{
     imgcodecs::{imread, imwrite, IMREAD_COLOR},
     prelude::*,
 };
-use std::path::Path;
-use tokio::time::{sleep, Duration};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::sync::{atomic::AtomicBool, Arc};
+use std::time::SystemTime;
+use tokio::time::{interval, sleep, Duration};
 use tracing::{info, warn, Level};
 
+/// Models every mode loads, relative to the working directory
+const DETECTION_MODEL: &str = "models/face_detection_yunet_2023mar.onnx";
+const RECOGNITION_MODEL: &str = "models/face_recognition_sface_2021dec.onnx";
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::fmt()
-        .with_max_level(Level::DEBUG)
-        .init();
-
     let matches = Command::new("Face Recognition CLI Tool")
         .version("1.0")
         .author("Your Name")
@@ -35,6 +39,77 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                 .help("Path to the faces database")
                 .default_value("/app/media/db"),
         )
+        .arg(
+            Arg::new("score-precision")
+                .long("score-precision")
+                .value_name("DIGITS")
+                .help("Number of decimals for printed match scores")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("2"),
+        )
+        .arg(
+            Arg::new("score-percent")
+                .long("score-percent")
+                .help("Print match scores as percentages")
+                .action(clap::ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("watch-input")
+                .long("watch-input")
+                .value_name("DIR")
+                .help("Keep running and recognize every image that appears in DIR"),
+        )
+        .arg(
+            Arg::new("output-dir")
+                .long("output-dir")
+                .value_name("DIR")
+                .help("Where --watch-input moves processed images and writes results")
+                .default_value("./media/processed"),
+        )
+        .arg(
+            Arg::new("probe-embedding")
+                .long("probe-embedding")
+                .value_name("FILE")
+                .help("Match a raw embedding (JSON array of floats) instead of an image"),
+        )
+        .arg(
+            Arg::new("top-k")
+                .long("top-k")
+                .value_name("K")
+                .help("Number of matches printed for --probe-embedding, or listed per face by --batch")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("5"),
+        )
+        .arg(
+            Arg::new("batch")
+                .long("batch")
+                .value_name("DIR")
+                .help("Recognize every image in DIR and write the per-face results to --csv"),
+        )
+        .arg(
+            Arg::new("csv")
+                .long("csv")
+                .value_name("FILE")
+                .help("CSV file written by --batch")
+                .default_value("./media/results.csv"),
+        )
+        .arg(
+            Arg::new("threshold")
+                .long("threshold")
+                .value_name("SCORE")
+                .help("Minimum score to accept a match")
+                .value_parser(clap::value_parser!(f32))
+                .default_value("0.4"),
+        )
+        .arg(
+            Arg::new("stdin")
+                .long("stdin")
+                .help(
+                    "Stay resident and recognize length-prefixed frames from stdin, writing \
+                     one JSON line per frame to stdout (see stdin_run for the format)",
+                )
+                .action(clap::ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("test-mode")
                 .short('t')
@@ -44,12 +119,37 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         )
         .get_matches();
 
+    // In --stdin mode stdout carries the results, so logs go to stderr
+    let stdin_mode = matches.get_flag("stdin");
+    let subscriber = tracing_subscriber::fmt().with_max_level(Level::DEBUG);
+    if stdin_mode {
+        subscriber.with_writer(std::io::stderr).init();
+    } else {
+        subscriber.init();
+    }
+
     let image_path = matches.get_one::<String>("image").unwrap();
     let db_path = matches.get_one::<String>("db").unwrap();
     let test_mode = matches.get_flag("test-mode");
+    let watch_input = matches.get_one::<String>("watch-input");
+    let output_dir = matches.get_one::<String>("output-dir").unwrap();
+    let probe_embedding = matches.get_one::<String>("probe-embedding");
+    let top_k = *matches.get_one::<usize>("top-k").unwrap();
+    let batch_dir = matches.get_one::<String>("batch");
+    let csv_path = matches.get_one::<String>("csv").unwrap();
+    let threshold = *matches.get_one::<f32>("threshold").unwrap();
+    let score_format = ScoreFormat {
+        precision: *matches.get_one::<usize>("score-precision").unwrap(),
+        as_percent: matches.get_flag("score-percent"),
+    };
 
     // Check if files exist
-    if !Path::new(image_path).exists() {
+    if watch_input.is_none()
+        && probe_embedding.is_none()
+        && batch_dir.is_none()
+        && !stdin_mode
+        && !Path::new(image_path).exists()
+    {
         eprintln!("Error: Image file does not exist: {image_path}");
         std::process::exit(1);
     }
@@ -59,17 +159,51 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         std::process::exit(1);
     }
 
-    if test_mode {
-        test_mode_run(image_path, db_path).await?;
+    if stdin_mode {
+        stdin_run(db_path, threshold).await?;
+    } else if let Some(batch_dir) = batch_dir {
+        batch_run(batch_dir, csv_path, db_path, top_k, threshold).await?;
+    } else if let Some(embedding_path) = probe_embedding {
+        probe_embedding_run(embedding_path, db_path, top_k, score_format).await?;
+    } else if let Some(input_dir) = watch_input {
+        watch_input_run(input_dir, output_dir, db_path, threshold, score_format).await?;
+    } else if test_mode {
+        test_mode_run(image_path, db_path, threshold).await?;
     } else {
-        simple_run(image_path, db_path).await?;
+        simple_run(image_path, db_path, threshold, score_format).await?;
     }
 
     Ok(())
 }
 
+/// How match scores are printed
+#[derive(Debug, Clone, Copy)]
+struct ScoreFormat {
+    precision: usize,
+    as_percent: bool,
+}
+
+impl ScoreFormat {
+    fn format(&self, result: &MatchResult) -> String {
+        if result.is_unknown() {
+            result.name.clone()
+        } else {
+            format!(
+                "{} ({})",
+                result.name,
+                result.format_score(self.precision, self.as_percent)
+            )
+        }
+    }
+}
+
 /// Simple face recognition run on one image
-async fn simple_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+async fn simple_run(
+    image_path: &str,
+    db_path: &str,
+    threshold: f32,
+    score_format: ScoreFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
     info!("Running simple face recognition...");
 
     // Load image
@@ -79,11 +213,8 @@ async fn simple_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn std::
     }
 
     // Initialize face recognition
-    let mut face_recognition = FaceRecognition::new(
-        Some("models/face_detection_yunet_2023mar.onnx"),
-        Some("models/face_recognition_sface_2021dec.onnx"),
-        Some(1000),
-    )?;
+    let mut face_recognition =
+        FaceRecognition::new(Some(DETECTION_MODEL), Some(RECOGNITION_MODEL), Some(1000))?;
 
     // Load database
     face_recognition
@@ -91,10 +222,10 @@ async fn simple_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn std::
         .await?;
 
     // Run face recognition
-    let results = face_recognition.run(&mut frame, 0.4, true).await?;
+    let results = face_recognition.run(&mut frame, threshold, true).await?;
 
     for (i, result) in results.iter().enumerate() {
-        info!("Face {}: {}", i + 1, result.to_string());
+        info!("Face {}: {}", i + 1, score_format.format(result));
     }
 
     // Save result
@@ -108,17 +239,367 @@ async fn simple_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn std::
     Ok(())
 }
 
+/// Largest frame accepted by `stdin_run`, to reject corrupt length prefixes
+const MAX_STDIN_FRAME_BYTES: usize = 64 * 1024 * 1024;
+
+/// One line of `stdin_run` output
+#[derive(serde::Serialize)]
+struct FrameResult {
+    frame: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    matches: Option<Vec<MatchResult>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Subprocess mode for callers in other languages: recognize frames read from stdin until
+/// it is closed, accepting matches above `threshold`.
+///
+/// Each frame is a 4-byte little-endian unsigned length followed by that many bytes of an
+/// encoded image (JPEG, PNG or anything else `imdecode` reads). For every frame one line
+/// of JSON is written to stdout and flushed, in order:
+/// `{"frame":0,"matches":[{"name":"Alice","score":0.61,"confidence":0.61}]}`, or
+/// `{"frame":1,"error":"Invalid image"}` if that frame failed. Frames are numbered from 0.
+/// A length above 64 MiB or a truncated frame ends the stream with an error; logs go to
+/// stderr.
+async fn stdin_run(db_path: &str, threshold: f32) -> Result<(), Box<dyn std::error::Error>> {
+    use std::io::Write;
+    use tokio::io::AsyncReadExt;
+
+    let mut face_recognition =
+        FaceRecognition::new(Some(DETECTION_MODEL), Some(RECOGNITION_MODEL), Some(1000))?;
+    face_recognition
+        .load_persons_db(db_path, false, false)
+        .await?;
+    info!("Reading frames from stdin");
+
+    let mut stdin = tokio::io::stdin();
+    let mut stdout = std::io::stdout().lock();
+    let mut buf = Vec::new();
+    for frame in 0.. {
+        let mut len = [0u8; 4];
+        match stdin.read_exact(&mut len).await {
+            Ok(_) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::UnexpectedEof => break,
+            Err(e) => return Err(e.into()),
+        }
+        let len = u32::from_le_bytes(len) as usize;
+        if len > MAX_STDIN_FRAME_BYTES {
+            return Err(format!("frame {frame}: length {len} exceeds the limit").into());
+        }
+        buf.resize(len, 0);
+        stdin.read_exact(&mut buf).await?;
+
+        let result = match face_recognition.run_from_bytes(&buf, threshold).await {
+            Ok(matches) => FrameResult {
+                frame,
+                matches: Some(matches),
+                error: None,
+            },
+            Err(e) => FrameResult {
+                frame,
+                matches: None,
+                error: Some(e.to_string()),
+            },
+        };
+        serde_json::to_writer(&mut stdout, &result)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+
+    info!("Stdin closed, exiting");
+    Ok(())
+}
+
+/// Print the database persons closest to an embedding stored as a JSON array
+async fn probe_embedding_run(
+    embedding_path: &str,
+    db_path: &str,
+    top_k: usize,
+    score_format: ScoreFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let file = std::fs::File::open(embedding_path)?;
+    let embedding: Vec<f32> = serde_json::from_reader(std::io::BufReader::new(file))?;
+    info!(
+        "Loaded {}-dimensional embedding from {}",
+        embedding.len(),
+        embedding_path
+    );
+
+    let mut face_recognition =
+        FaceRecognition::new(Some(DETECTION_MODEL), Some(RECOGNITION_MODEL), Some(1000))?;
+    face_recognition
+        .load_persons_db(db_path, false, false)
+        .await?;
+
+    let matches = face_recognition.find_top_matches(&embedding, top_k).await?;
+    if matches.is_empty() {
+        info!("Database is empty");
+    }
+    for (rank, result) in matches.iter().enumerate() {
+        info!("#{}: {}", rank + 1, score_format.format(result));
+    }
+
+    Ok(())
+}
+
+/// Recognize every image in `batch_dir` and write one CSV row per face (image path, face
+/// index, box, best match and the `top_k` candidates) to `csv_path`, accepting matches
+/// above `threshold`. Images that cannot be read or recognized are logged and left out.
+async fn batch_run(
+    batch_dir: &str,
+    csv_path: &str,
+    db_path: &str,
+    top_k: usize,
+    threshold: f32,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut face_recognition =
+        FaceRecognition::new(Some(DETECTION_MODEL), Some(RECOGNITION_MODEL), Some(1000))?;
+    face_recognition
+        .load_persons_db(db_path, false, false)
+        .await?;
+
+    if let Some(parent) = Path::new(csv_path).parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = std::io::BufWriter::new(std::fs::File::create(csv_path)?);
+    let mut csv = ResultsCsvWriter::new(file, top_k)?;
+
+    let images = image_files(Path::new(batch_dir))?;
+    for image_path in &images {
+        let frame = match imread(&image_path.to_string_lossy(), IMREAD_COLOR) {
+            Ok(frame) if !frame.empty() => frame,
+            Ok(_) => {
+                warn!("{}: could not load image", image_path.display());
+                continue;
+            }
+            Err(e) => {
+                warn!("{}: could not load image: {}", image_path.display(), e);
+                continue;
+            }
+        };
+        match face_recognition.run_ranked_faces(&frame, threshold).await {
+            Ok(faces) => csv.write_image(image_path, &faces)?,
+            Err(e) => warn!("{}: {}", image_path.display(), e),
+        }
+    }
+    csv.into_inner()?;
+    info!("Wrote results of {} images to {}", images.len(), csv_path);
+
+    Ok(())
+}
+
+/// Attempts after which an input image that keeps failing is moved to `failed`
+const MAX_INPUT_ATTEMPTS: u32 = 3;
+
+/// Daemon mode: recognize every image that appears in `input_dir` while keeping the
+/// database watcher running, accepting matches above `threshold`. Images are picked up
+/// once their size and modification time stayed the same between two scans, so files
+/// still being copied are left alone. Each processed image is moved to `output_dir` next
+/// to an annotated `<name>_result.jpg`;
+/// unreadable images, and images failing `MAX_INPUT_ATTEMPTS` times, go to
+/// `output_dir/failed`. Names already taken there get a `-1`, `-2`, ... suffix.
+async fn watch_input_run(
+    input_dir: &str,
+    output_dir: &str,
+    db_path: &str,
+    threshold: f32,
+    score_format: ScoreFormat,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let input_dir = Path::new(input_dir);
+    if !input_dir.is_dir() {
+        return Err(format!("Input directory does not exist: {}", input_dir.display()).into());
+    }
+    std::fs::create_dir_all(Path::new(output_dir).join("failed"))?;
+    // The input watcher is recursive, so results must not be written into or below it;
+    // canonical paths see through `./`, `..` and symlinks
+    let input_dir = &input_dir.canonicalize()?;
+    let output_dir = &Path::new(output_dir).canonicalize()?;
+    if output_dir.starts_with(input_dir) || input_dir.starts_with(output_dir) {
+        return Err(
+            "--output-dir and the watched input directory must not be the same or nested".into(),
+        );
+    }
+
+    let mut face_recognition =
+        FaceRecognition::new(Some(DETECTION_MODEL), Some(RECOGNITION_MODEL), Some(1000))?;
+    face_recognition
+        .load_persons_db(db_path, false, false)
+        .await?;
+    face_recognition.start_watching(2).await?;
+
+    // Input changes only wake the loop up; the directory is rescanned each time, and
+    // periodically to pick up files that fell into the watcher's debounce window
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut input_watcher = FolderWatcher::new()?;
+    input_watcher.start_watching(input_dir)?;
+    input_watcher.spawn_change_loop(Arc::new(AtomicBool::new(false)), move || {
+        let _ = tx.send(());
+    })?;
+
+    info!(
+        "Watching {} for images, results go to {} (Ctrl+C to stop)",
+        input_dir.display(),
+        output_dir.display()
+    );
+    let mut rescan = interval(Duration::from_secs(5));
+    // Size and modification time of each waiting image at the previous scan
+    let mut last_seen: HashMap<PathBuf, (u64, Option<SystemTime>)> = HashMap::new();
+    let mut attempts: HashMap<PathBuf, u32> = HashMap::new();
+    loop {
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => break,
+            _ = rx.recv() => {}
+            _ = rescan.tick() => {}
+        }
+
+        let mut seen = HashMap::new();
+        for image_path in image_files(input_dir)? {
+            let Ok(metadata) = std::fs::metadata(&image_path) else {
+                continue;
+            };
+            let signature = (metadata.len(), metadata.modified().ok());
+            let stable = last_seen.get(&image_path) == Some(&signature);
+            seen.insert(image_path.clone(), signature);
+            if !stable {
+                continue;
+            }
+
+            match process_input_image(
+                &mut face_recognition,
+                &image_path,
+                output_dir,
+                threshold,
+                score_format,
+            )
+            .await
+            {
+                Ok(InputOutcome::Processed) => {
+                    seen.remove(&image_path);
+                    attempts.remove(&image_path);
+                }
+                Ok(InputOutcome::Unreadable) => {
+                    warn!(
+                        "{}: could not load image, moved to {}",
+                        image_path.display(),
+                        output_dir.join("failed").display()
+                    );
+                    seen.remove(&image_path);
+                    attempts.remove(&image_path);
+                }
+                Err(e) => {
+                    let failed = attempts.entry(image_path.clone()).or_insert(0);
+                    *failed += 1;
+                    warn!(
+                        "Failed to process {} (attempt {} of {}): {}",
+                        image_path.display(),
+                        failed,
+                        MAX_INPUT_ATTEMPTS,
+                        e
+                    );
+                    if *failed >= MAX_INPUT_ATTEMPTS {
+                        attempts.remove(&image_path);
+                        seen.remove(&image_path);
+                        if let Err(e) = move_into(&image_path, &output_dir.join("failed")) {
+                            warn!("Cannot move {} aside: {}", image_path.display(), e);
+                        }
+                    }
+                }
+            }
+        }
+        last_seen = seen;
+        attempts.retain(|path, _| last_seen.contains_key(path));
+    }
+
+    input_watcher.stop_watching();
+    face_recognition.stop_watching().await;
+    info!("Stopped watching {}", input_dir.display());
+    Ok(())
+}
+
+/// What became of an input image handed to `process_input_image`
+enum InputOutcome {
+    /// Recognized and moved to the output directory
+    Processed,
+    /// Not decodable, moved to `failed` right away since retrying cannot help
+    Unreadable,
+}
+
+async fn process_input_image(
+    face_recognition: &mut FaceRecognition,
+    image_path: &Path,
+    output_dir: &Path,
+    threshold: f32,
+    score_format: ScoreFormat,
+) -> Result<InputOutcome, Box<dyn std::error::Error>> {
+    let mut frame = imread(&image_path.to_string_lossy(), IMREAD_COLOR)?;
+    if frame.empty() {
+        move_into(image_path, &output_dir.join("failed"))?;
+        return Ok(InputOutcome::Unreadable);
+    }
+
+    let results = face_recognition.run(&mut frame, threshold, true).await?;
+    let names: Vec<String> = results.iter().map(|r| score_format.format(r)).collect();
+    info!("{}: {}", image_path.display(), names.join(", "));
+
+    // Name the result after the image's final name, which may carry a suffix
+    let destination = unique_destination(output_dir, image_path)?;
+    let stem = destination
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let result_path = output_dir.join(format!("{stem}_result.jpg"));
+    imwrite(
+        &result_path.to_string_lossy(),
+        &frame,
+        &opencv::core::Vector::new(),
+    )?;
+    std::fs::rename(image_path, destination)?;
+    Ok(InputOutcome::Processed)
+}
+
+/// Move `path` into `dir` without replacing a file of the same name there
+fn move_into(path: &Path, dir: &Path) -> std::io::Result<PathBuf> {
+    let destination = unique_destination(dir, path)?;
+    std::fs::rename(path, &destination)?;
+    Ok(destination)
+}
+
+/// Path in `dir` for the file `path`: its own name if free, otherwise the first free
+/// name with a `-1`, `-2`, ... suffix before the extension
+fn unique_destination(dir: &Path, path: &Path) -> std::io::Result<PathBuf> {
+    let file_name = path.file_name().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "Invalid image path")
+    })?;
+    let mut candidate = dir.join(file_name);
+    let stem = path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    let mut suffix = 1;
+    while candidate.exists() {
+        candidate = dir.join(format!("{stem}-{suffix}{extension}"));
+        suffix += 1;
+    }
+    Ok(candidate)
+}
+
 /// Test mode to verify database update mechanism
-async fn test_mode_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn std::error::Error>> {
+async fn test_mode_run(
+    image_path: &str,
+    db_path: &str,
+    threshold: f32,
+) -> Result<(), Box<dyn std::error::Error>> {
     info!("=== Face Recognition Async Database Test ===");
 
     // Initialize face recognition
     info!("1. Initializing FaceRecognition...");
-    let mut face_recognition = FaceRecognition::new(
-        Some("models/face_detection_yunet_2023mar.onnx"),
-        Some("models/face_recognition_sface_2021dec.onnx"),
-        Some(1000),
-    )?;
+    let mut face_recognition =
+        FaceRecognition::new(Some(DETECTION_MODEL), Some(RECOGNITION_MODEL), Some(1000))?;
 
     // Load the initial database
     info!("2. Loading initial persons database from: {}", db_path);
@@ -139,7 +620,7 @@ async fn test_mode_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn st
 
     info!("5. Running face recognition on test image...");
     let result = face_recognition
-        .run_one_face(frame.clone(), 0.4, false)
+        .run_one_face(frame.clone(), threshold, false)
         .await?;
     info!("Found name: {}", result.to_string());
 
@@ -184,7 +665,9 @@ async fn test_mode_run(image_path: &str, db_path: &str) -> Result<(), Box<dyn st
 
     // Run face recognition again to show it's still working
     info!("9. Running face recognition again after database reload...");
-    let result = face_recognition.run_one_face(frame, 0.4, false).await?;
+    let result = face_recognition
+        .run_one_face(frame, threshold, false)
+        .await?;
     info!("Found name: {}", result.name);
 
     // Clean up the test file
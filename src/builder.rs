@@ -0,0 +1,175 @@
+use crate::{FaceRecognition, FaceRecognitionError, Result};
+use opencv::core::Size;
+
+/// Settings describing the face recognition model, for SFace-compatible replacements
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecognizerConfig {
+    /// Number of values in one embedding produced by the model (SFace: 128).
+    /// Checked by `FaceRecognition::warmup`.
+    pub expected_feature_dim: i32,
+}
+
+impl Default for RecognizerConfig {
+    fn default() -> Self {
+        Self {
+            expected_feature_dim: 128,
+        }
+    }
+}
+
+/// OpenCV DNN backend/target pair used to run one of the models
+/// (`opencv::dnn::DNN_BACKEND_*` / `opencv::dnn::DNN_TARGET_*`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ComputeBackend {
+    pub backend: i32,
+    pub target: i32,
+}
+
+impl ComputeBackend {
+    pub fn new(backend: i32, target: i32) -> Self {
+        Self { backend, target }
+    }
+}
+
+impl Default for ComputeBackend {
+    fn default() -> Self {
+        Self {
+            backend: opencv::dnn::DNN_BACKEND_OPENCV,
+            target: opencv::dnn::DNN_TARGET_CPU,
+        }
+    }
+}
+
+/// Builder for `FaceRecognition` exposing settings that `FaceRecognition::new` does not
+#[derive(Debug, Clone, Default)]
+pub struct FaceRecognitionBuilder {
+    pub(crate) fd_model_path: Option<String>,
+    pub(crate) fr_model_path: Option<String>,
+    pub(crate) max_size: Option<i32>,
+    pub(crate) recognizer: RecognizerConfig,
+    pub(crate) detector_backend: ComputeBackend,
+    pub(crate) recognizer_backend: ComputeBackend,
+    pub(crate) detector_input_size: Option<Size>,
+}
+
+impl FaceRecognitionBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn detector_model(mut self, path: impl Into<String>) -> Self {
+        self.fd_model_path = Some(path.into());
+        self
+    }
+
+    pub fn recognizer_model(mut self, path: impl Into<String>) -> Self {
+        self.fr_model_path = Some(path.into());
+        self
+    }
+
+    pub fn max_size(mut self, size: i32) -> Self {
+        self.max_size = Some(size);
+        self
+    }
+
+    pub fn recognizer_config(mut self, config: RecognizerConfig) -> Self {
+        self.recognizer = config;
+        self
+    }
+
+    pub fn expected_feature_dim(mut self, dim: i32) -> Self {
+        self.recognizer.expected_feature_dim = dim;
+        self
+    }
+
+    /// Run both models on the same backend/target
+    pub fn backend(mut self, backend: ComputeBackend) -> Self {
+        self.detector_backend = backend;
+        self.recognizer_backend = backend;
+        self
+    }
+
+    /// Backend/target for the YuNet face detector only
+    pub fn detector_backend(mut self, backend: ComputeBackend) -> Self {
+        self.detector_backend = backend;
+        self
+    }
+
+    /// Backend/target for the SFace recognizer only
+    pub fn recognizer_backend(mut self, backend: ComputeBackend) -> Self {
+        self.recognizer_backend = backend;
+        self
+    }
+
+    /// Create the detector for the expected operating resolution (the size of the frames
+    /// after `max_size` downscaling) instead of 400x400. For fixed-resolution streams this
+    /// avoids reshaping the network on the first frame; other sizes still work.
+    pub fn detector_input_size(mut self, size: Size) -> Self {
+        self.detector_input_size = Some(size);
+        self
+    }
+
+    /// Check the settings before any model is loaded: a positive `expected_feature_dim`,
+    /// a `max_size` of at least 0 (0 disables downscaling) and a positive
+    /// `detector_input_size`
+    fn validate(&self) -> Result<()> {
+        if self.recognizer.expected_feature_dim <= 0 {
+            return Err(FaceRecognitionError::InvalidConfig(format!(
+                "expected_feature_dim must be positive, got {}",
+                self.recognizer.expected_feature_dim
+            )));
+        }
+        if let Some(size) = self.max_size.filter(|&size| size < 0) {
+            return Err(FaceRecognitionError::InvalidConfig(format!(
+                "max_size must not be negative, got {}",
+                size
+            )));
+        }
+        if let Some(size) = self
+            .detector_input_size
+            .filter(|size| size.width <= 0 || size.height <= 0)
+        {
+            return Err(FaceRecognitionError::InvalidConfig(format!(
+                "detector_input_size must be positive, got {}x{}",
+                size.width, size.height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Validate the settings, then load both models.
+    /// Fails with `FaceRecognitionError::InvalidConfig` before touching the model files.
+    pub fn build(self) -> Result<FaceRecognition> {
+        self.validate()?;
+        FaceRecognition::from_builder(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn build_rejects_invalid_config_before_loading_models() {
+        let missing = "/nonexistent/model.onnx";
+        let builder = FaceRecognitionBuilder::new()
+            .detector_model(missing)
+            .recognizer_model(missing);
+
+        let invalid = [
+            builder.clone().expected_feature_dim(0),
+            builder.clone().max_size(-1),
+            builder.clone().detector_input_size(Size::new(0, 240)),
+        ];
+        for config in invalid {
+            assert!(matches!(
+                config.build(),
+                Err(FaceRecognitionError::InvalidConfig(_))
+            ));
+        }
+        assert!(matches!(
+            builder.max_size(0).build(),
+            Err(FaceRecognitionError::ModelNotFound(_))
+        ));
+    }
+}
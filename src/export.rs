@@ -0,0 +1,137 @@
+//! CSV export of recognition results for review in a spreadsheet.
+//!
+//! `ResultsCsvWriter` writes one row per detected face with its image, index, bounding
+//! box, accepted match and the `top_k` best candidates. Fields are quoted as in RFC 4180
+//! when they contain a comma, quote or line break, so any person name or path is safe.
+
+use crate::{RankedFace, Result};
+use std::io::Write;
+use std::path::Path;
+
+/// Writes `RankedFace`s as CSV rows, header first
+pub struct ResultsCsvWriter<W: Write> {
+    writer: W,
+    top_k: usize,
+}
+
+impl<W: Write> ResultsCsvWriter<W> {
+    /// Write the header to `writer`. Every row has `top_k` name/score column pairs; faces
+    /// with fewer candidates leave the rest empty.
+    pub fn new(mut writer: W, top_k: usize) -> Result<Self> {
+        let mut header: Vec<String> = [
+            "image",
+            "face_index",
+            "x",
+            "y",
+            "width",
+            "height",
+            "best_name",
+            "best_score",
+        ]
+        .iter()
+        .map(|column| column.to_string())
+        .collect();
+        for rank in 1..=top_k {
+            header.push(format!("top{rank}_name"));
+            header.push(format!("top{rank}_score"));
+        }
+        write_record(&mut writer, &header)?;
+        Ok(Self { writer, top_k })
+    }
+
+    /// Write one row per face of `image`. An image without faces still gets a row with
+    /// only the image column filled, so it shows up in the review.
+    pub fn write_image(&mut self, image: &Path, faces: &[RankedFace]) -> Result<()> {
+        let image = image.to_string_lossy().into_owned();
+        if faces.is_empty() {
+            let mut record = vec![image];
+            record.resize(8 + 2 * self.top_k, String::new());
+            return write_record(&mut self.writer, &record);
+        }
+
+        for face in faces {
+            let mut record = vec![
+                image.clone(),
+                face.index.to_string(),
+                face.bbox.x.to_string(),
+                face.bbox.y.to_string(),
+                face.bbox.width.to_string(),
+                face.bbox.height.to_string(),
+                face.ranking.best_match.name.clone(),
+                format!("{:.4}", face.ranking.best_match.score),
+            ];
+            for rank in 0..self.top_k {
+                match face.ranking.results.get(rank) {
+                    Some(candidate) => {
+                        record.push(candidate.name.clone());
+                        record.push(format!("{:.4}", candidate.score));
+                    }
+                    None => record.extend([String::new(), String::new()]),
+                }
+            }
+            write_record(&mut self.writer, &record)?;
+        }
+        Ok(())
+    }
+
+    /// Flush and return the underlying writer
+    pub fn into_inner(mut self) -> Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+fn write_record<W: Write>(writer: &mut W, fields: &[String]) -> Result<()> {
+    let line: Vec<String> = fields.iter().map(|field| csv_field(field)).collect();
+    writeln!(writer, "{}", line.join(","))?;
+    Ok(())
+}
+
+/// `field` quoted if it contains a separator, quote or line break, with quotes doubled
+fn csv_field(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{MatchResult, MatchResults};
+    use opencv::core::Rect2i;
+
+    #[test]
+    fn quotes_fields_that_need_it() {
+        assert_eq!(csv_field("Alice"), "Alice");
+        assert_eq!(csv_field(""), "");
+        assert_eq!(csv_field("Doe, John"), "\"Doe, John\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+        assert_eq!(csv_field("a\nb"), "\"a\nb\"");
+        assert_eq!(csv_field("a\r\nb"), "\"a\r\nb\"");
+    }
+
+    #[test]
+    fn writes_only_the_fixed_columns_without_top_k() {
+        let best = MatchResult::new("Doe, \"JD\"".to_string(), 0.5);
+        let face = RankedFace {
+            index: 0,
+            bbox: Rect2i::new(1, 2, 3, 4),
+            ranking: MatchResults {
+                results: vec![best.clone()],
+                best_match: best,
+            },
+        };
+        let mut csv = ResultsCsvWriter::new(Vec::new(), 0).unwrap();
+        csv.write_image(Path::new("dir/a,b.jpg"), &[]).unwrap();
+        csv.write_image(Path::new("c.jpg"), &[face]).unwrap();
+        let output = String::from_utf8(csv.into_inner().unwrap()).unwrap();
+        assert_eq!(
+            output,
+            "image,face_index,x,y,width,height,best_name,best_score\n\
+             \"dir/a,b.jpg\",,,,,,,\n\
+             c.jpg,0,1,2,3,4,\"Doe, \"\"JD\"\"\",0.5000\n"
+        );
+    }
+}
@@ -1,597 +1,5951 @@
-use crate::types::{DbLoadStatus, DetectedFace, MatchResult, MatchResults};
+//! The `FaceRecognition` pipeline: detection, embedding, matching and the persons
+//! database.
+//!
+//! # Determinism
+//!
+//! No step is stochastic, so there is no seed to set: equal inputs, settings and models
+//! give equal outputs. Database loading reads person folders and images in sorted path
+//! order, which also fixes feature sequence numbers and feature cap eviction. Matching
+//! breaks equal scores by person name (see `TieBreak`), and ranked results are sorted by
+//! score, then name. Tiles are visited row by row, and non-maximum suppression,
+//! duplicate grouping and test-time augmentation follow detector order.
+
+use crate::backend::{Detector, Recognizer};
+use crate::builder::{FaceRecognitionBuilder, RecognizerConfig};
+use crate::logging::{debug, error, info, warn};
+use crate::store::{FeatureStore, InMemoryStore, StoreEntry};
+use crate::types::{
+    cosine_similarity, feature_centroid, feature_to_vec, normalize_person_name, person_name_key,
+    rect_iou, vec_to_feature, ColorNorm, ConfidenceMapping, CropPadding, CrossPersonDuplicate,
+    DatabaseExport, DatabaseSnapshot, DbLoadStatus, DetectedFace, DuplicateDetection,
+    EnrollmentNormalization, ExplainEntry, FaceResult, FaceSort, FeatureCapPolicy,
+    FeaturePrecision, FusionWeight, InvalidFeaturePolicy, LoadReport, MatchResult, MatchResults,
+    Metrics, MissingDatabasePolicy, NameCollisionPolicy, PrepareStage, QuantizedFeature,
+    RankedFace, RecognitionMode, ReembedReport, ReferenceAggregation, RejectedFace, ReloadEvent,
+    SimilarityMetric, SnapshotFeature, StoredFeature, ThresholdPoint, ThresholdSuggestion,
+    TieBreak, TileConfig, TrackId, VisualizeNaming, DATABASE_EXPORT_VERSION,
+};
 use crate::watcher::{get_latest_mod_time, FolderWatcher};
 use crate::{FaceRecognitionError, Result};
+#[cfg(feature = "umat")]
+use opencv::core::{AccessFlag, UMat};
 use opencv::{
-    core::{Mat, Point, Ptr, Rect2i, Scalar, Size},
-    imgcodecs::{imread, imwrite, IMREAD_COLOR},
+    core::{Mat, Point, Rect2i, Scalar, Size, CV_8UC3},
+    imgcodecs::{imdecode, imread, imwrite, IMREAD_COLOR},
     imgproc::{get_text_size, put_text, rectangle, FONT_HERSHEY_SIMPLEX, LINE_8},
     objdetect::{FaceDetectorYN, FaceRecognizerSF},
     prelude::*,
+    videoio::{VideoCapture, VideoWriter, CAP_ANY, CAP_PROP_FPS},
 };
-use std::collections::HashMap;
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
-use std::sync::{atomic::AtomicBool, Arc, Mutex};
-use std::time::SystemTime;
-use tokio::sync::RwLock;
-use tracing::{debug, error, info, warn};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime};
+use tokio::sync::{broadcast, Notify, RwLock};
+use tokio_util::sync::CancellationToken;
 
 pub struct FaceRecognition {
-    detector: Ptr<FaceDetectorYN>,
-    face_recognizer: Ptr<FaceRecognizerSF>,
+    detector: Box<dyn Detector>,
+    face_recognizer: Box<dyn Recognizer>,
+    primary_fusion: FusionWeight,
+    ensemble: Vec<EnsembleMember>,
+    recognizer_config: RecognizerConfig,
     max_size: i32,
     db_load_status: Arc<RwLock<DbLoadStatus>>,
-    features_map: Arc<RwLock<HashMap<String, Vec<Mat>>>>,
+    features_map: Arc<RwLock<Box<dyn FeatureStore>>>,
     db_path: Arc<RwLock<Option<PathBuf>>>,
     last_mod_time: Arc<RwLock<SystemTime>>,
     watcher: Arc<Mutex<Option<FolderWatcher>>>,
+    watch_task: Arc<Mutex<Option<tokio::task::JoinHandle<()>>>>,
     watcher_running: Arc<AtomicBool>,
+    watch_paused: Arc<AtomicBool>,
+    reload_pending: Arc<AtomicBool>,
+    reload_events: broadcast::Sender<ReloadEvent>,
+    background_loader: Mutex<Option<BackgroundLoader>>,
+    counters: Counters,
+    name_collision_policy: NameCollisionPolicy,
+    min_enroll_eye_distance: f32,
+    min_probe_eye_distance: f32,
+    last_load_report: LoadReport,
+    match_log_throttle: LogThrottle,
+    crop_margin: f32,
+    crop_padding: CropPadding,
+    /// Thresholds keyed by `person_name_key`
+    person_thresholds: HashMap<String, f32>,
+    /// `person_name_key`s of the persons that never match
+    denylist: HashSet<String>,
+    preprocess: Option<PreprocessFn>,
+    max_decode_pixels: u64,
+    color_norm: ColorNorm,
+    motion_skip_threshold: f64,
+    identity_colors: bool,
+    track_capture_time: bool,
+    max_total_features: usize,
+    feature_cap_policy: FeatureCapPolicy,
+    post_nms_iou: Option<f32>,
+    face_sort: FaceSort,
+    recognition_mode: RecognitionMode,
+    unknown_label: String,
+    unverified_label: String,
+    unverified_band: f32,
+    tie_break: TieBreak,
+    person_filter: Option<Vec<String>>,
+    reference_aggregation: ReferenceAggregation,
+    detect_cross_person_duplicates: bool,
+    leave_one_out: bool,
+    missing_database_policy: MissingDatabasePolicy,
+    invalid_feature_policy: InvalidFeaturePolicy,
+    missing_database_warned: bool,
+    empty_database_warned: bool,
+    tta: bool,
+    flip_augment: bool,
+    keep_aligned: bool,
+    /// Set while embedding enrollment images, see `extract_enrollment_features`
+    enrolling: bool,
+    try_upside_down: bool,
+    fullres_retry: bool,
+    #[cfg(feature = "parallel")]
+    parallel_match_min_features: usize,
+    eye_line_correction: bool,
+    visualize_naming: VisualizeNaming,
+    tiling: Option<TileConfig>,
+    full_resolution_crops: bool,
+    enrollment_normalization: EnrollmentNormalization,
+    quantize_features: bool,
+    feature_precision: FeaturePrecision,
+    detection_size_ceiling: i32,
+    strict_single_face: bool,
+    confidence_mapping: ConfidenceMapping,
+    force_detection_size_ceiling: bool,
+    /// Input size the detector is currently configured for, if known
+    detector_input_size: Option<Size>,
+}
+
+/// Second instance running watcher reloads, see `FaceRecognition::set_background_loader`
+enum BackgroundLoader {
+    /// Waiting for `start_watching`
+    Idle(Box<FaceRecognition>),
+    /// Reloading on every wake-up until `stop_watching`, then handing the loader back
+    Running {
+        wake: Arc<Notify>,
+        task: tokio::task::JoinHandle<Box<FaceRecognition>>,
+    },
+}
+
+/// Atomic backing store of `Metrics`
+#[derive(Debug, Default)]
+struct Counters {
+    frames_processed: AtomicU64,
+    faces_detected: AtomicU64,
+    matches_accepted: AtomicU64,
+    reloads: AtomicU64,
+    faces_too_small: AtomicU64,
+}
+
+/// Additional recognizer whose scores are fused with the primary recognizer's
+struct EnsembleMember {
+    recognizer: Box<dyn Recognizer>,
+    fusion: FusionWeight,
+}
+
+/// Image preprocessing hook, see `FaceRecognition::set_preprocess`
+pub type PreprocessFn = Box<dyn Fn(&mut Mat) -> opencv::Result<()> + Send + Sync>;
+
+/// Rate limit for the per-face log lines written by `run`
+#[derive(Debug, Clone)]
+struct LogThrottle {
+    every_n: u64,
+    min_interval: Duration,
+    calls: u64,
+    last_logged: Option<Instant>,
+}
+
+impl LogThrottle {
+    fn new(every_n: u64, min_interval: Duration) -> Self {
+        Self {
+            every_n: every_n.max(1),
+            min_interval,
+            calls: 0,
+            last_logged: None,
+        }
+    }
+
+    fn should_log(&mut self) -> bool {
+        self.calls += 1;
+        if (self.calls - 1) % self.every_n != 0 {
+            return false;
+        }
+
+        let now = Instant::now();
+        if let Some(last) = self.last_logged {
+            if now.duration_since(last) < self.min_interval {
+                return false;
+            }
+        }
+        self.last_logged = Some(now);
+        true
+    }
 }
 
 const SCORE_THRESHOLD: f32 = 0.5; // Lowered from 0.7 for better face detection
 const NMS_THRESHOLD: f32 = 0.3;
 const TOP_K: i32 = 5000;
+/// Thresholds evaluated by `suggest_threshold`: 0.00, 0.01, ..., 1.00
+const THRESHOLD_SWEEP_STEPS: u32 = 100;
+/// Number of candidates listed by `explain`
+const EXPLAIN_CANDIDATES: usize = 5;
+/// Resolution at which `frame_motion` compares frames
+const MOTION_SIGNATURE_SIZE: Size = Size::new(64, 64);
+/// Extension of the sidecar files holding the weight of an enrollment image
+const WEIGHT_SIDECAR_EXTENSION: &str = "weight";
+/// Cosine similarity from which two stored embeddings count as the same image
+const IDENTICAL_EMBEDDING_SIMILARITY: f32 = 0.9999;
+/// Best detection score below which `try_upside_down` retries on the rotated frame
+const UPSIDE_DOWN_RETRY_SCORE: f32 = 0.7;
+/// Input size of SFace, the size `align_crop` produces
+const RECOGNIZER_INPUT_SIZE: Size = Size::new(112, 112);
+const FD_MODEL_FILE: &str = "face_detection_yunet_2023mar.onnx";
+const FR_MODEL_FILE: &str = "face_recognition_sface_2021dec.onnx";
+/// Longest frame side above which detection warns (or downscales, if forced)
+const DEFAULT_DETECTION_SIZE_CEILING: i32 = 4096;
+/// Longer side of the face crops written by enrollment normalization
+const NORMALIZED_ENROLLMENT_SIZE: i32 = 256;
+/// Box IoU above which a detection continues a track in `best_shots`
+const TRACK_IOU_THRESHOLD: f32 = 0.3;
+/// Frames a track survives without a matching detection in `best_shots`
+const TRACK_MAX_MISSED_FRAMES: usize = 10;
+/// Inter-ocular distance in pixels from which `shot_quality` no longer rewards size
+const BEST_SHOT_FULL_EYE_DISTANCE: f32 = 60.0;
+/// Reload events buffered per subscriber; slower subscribers miss the oldest
+const RELOAD_EVENT_CAPACITY: usize = 16;
+/// How long `stop_watching` waits for the watcher's event loop to end
+const WATCH_TASK_JOIN_TIMEOUT: Duration = Duration::from_secs(5);
+/// Environment variable naming a directory searched for model files
+pub const MODEL_DIR_ENV_VAR: &str = "FACERUST_MODEL_DIR";
+/// Older name of `MODEL_DIR_ENV_VAR`, still honored
+pub const MODELS_ENV_VAR: &str = "FACERUST_MODELS";
 
-impl FaceRecognition {
-    pub fn new(
-        fd_model_path: Option<&str>,
-        fr_model_path: Option<&str>,
-        max_size: Option<i32>,
-    ) -> Result<Self> {
-        let fd_path = fd_model_path.unwrap_or("./models/face_detection_yunet_2023mar.onnx");
-        let fr_path = fr_model_path.unwrap_or("./models/face_recognition_sface_2021dec.onnx");
+/// Find a model file.
+///
+/// Tried in order: the explicit path, `$FACERUST_MODEL_DIR` (or `$FACERUST_MODELS`), the
+/// platform config directory (e.g. `~/.config/facerust/models` on Linux), `./models` and
+/// `models/` next to the executable. The error lists every absolute path that was tried.
+fn resolve_model_path(explicit: Option<&str>, file_name: &str) -> Result<PathBuf> {
+    let lookup_name = explicit
+        .and_then(|path| Path::new(path).file_name())
+        .map(|n| n.to_os_string())
+        .unwrap_or_else(|| file_name.into());
 
-        if !Path::new(fd_path).exists() {
-            return Err(FaceRecognitionError::ModelNotFound(fd_path.to_string()));
-        }
-        if !Path::new(fr_path).exists() {
-            return Err(FaceRecognitionError::ModelNotFound(fr_path.to_string()));
+    let mut candidates: Vec<PathBuf> = explicit.map(PathBuf::from).into_iter().collect();
+    for var in [MODEL_DIR_ENV_VAR, MODELS_ENV_VAR] {
+        if let Some(dir) = std::env::var_os(var) {
+            candidates.push(PathBuf::from(dir).join(&lookup_name));
         }
+    }
+    if let Some(dirs) = directories::ProjectDirs::from("", "", "facerust") {
+        candidates.push(dirs.config_dir().join("models").join(&lookup_name));
+    }
+    candidates.push(Path::new("./models").join(&lookup_name));
+    if let Some(exe_dir) = std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(Path::to_path_buf))
+    {
+        candidates.push(exe_dir.join("models").join(&lookup_name));
+    }
 
-        debug!("Initializing face detection model: {}", fd_path);
-        let detector = FaceDetectorYN::create(
-            fd_path,
-            "",
-            Size::new(400, 400), // Match C++ default size
-            SCORE_THRESHOLD,
-            NMS_THRESHOLD,
-            TOP_K,
-            opencv::dnn::DNN_BACKEND_OPENCV,
-            opencv::dnn::DNN_TARGET_CPU,
-        )?;
+    if let Some(found) = candidates.iter().find(|c| c.is_file()) {
+        return Ok(found.clone());
+    }
 
-        debug!("Initializing face recognition model: {}", fr_path);
-        let face_recognizer = FaceRecognizerSF::create(
-            fr_path,
-            "",
-            opencv::dnn::DNN_BACKEND_OPENCV,
-            opencv::dnn::DNN_TARGET_CPU,
-        )?;
+    let tried = candidates
+        .iter()
+        .map(|c| std::path::absolute(c).unwrap_or_else(|_| c.clone()))
+        .map(|c| format!("  {}", c.display()))
+        .collect::<Vec<_>>()
+        .join("\n");
+    Err(FaceRecognitionError::ModelNotFound(format!(
+        "{}\nTried:\n{}\nHint: run `cargo build` in the facerust repository to download the \
+         models into ./models, run from the directory containing `models/`, or set {}",
+        lookup_name.to_string_lossy(),
+        tried,
+        MODEL_DIR_ENV_VAR
+    )))
+}
 
-        Ok(Self {
-            detector,
-            face_recognizer,
-            max_size: max_size.unwrap_or(600),
-            db_load_status: Arc::new(RwLock::new(DbLoadStatus::NotLoaded)),
-            features_map: Arc::new(RwLock::new(HashMap::new())),
-            db_path: Arc::new(RwLock::new(None)),
-            last_mod_time: Arc::new(RwLock::new(SystemTime::UNIX_EPOCH)),
-            watcher: Arc::new(Mutex::new(None)),
-            watcher_running: Arc::new(AtomicBool::new(false)),
-        })
+/// Quality of a face as an enrollment shot in 0..1: the detection score, scaled down for
+/// faces turned sideways (nose off the eyes' midpoint) and for small faces
+fn shot_quality(face: &DetectedFace) -> Result<f32> {
+    let [right_eye, left_eye, nose, ..] = face.landmarks_original()?;
+    let eye_distance = face.inter_ocular_distance()?;
+    if eye_distance <= 0.0 {
+        return Ok(0.0);
     }
+    let eye_center_x = (right_eye.x + left_eye.x) / 2.0;
+    let frontality = (1.0 - 2.0 * (nose.x - eye_center_x).abs() / eye_distance).clamp(0.0, 1.0);
+    let size = (eye_distance / BEST_SHOT_FULL_EYE_DISTANCE).min(1.0);
+    Ok(face.score()? * frontality * size)
+}
 
-    pub fn set_max_size(&mut self, size: i32) {
-        self.max_size = size;
+/// Reorder `faces` by `sort`; faces with equal keys keep their detector order
+fn sort_detected_faces(faces: &mut Vec<DetectedFace>, sort: FaceSort) -> Result<()> {
+    if sort == FaceSort::Detector || faces.len() < 2 {
+        return Ok(());
     }
+    let mut keyed = Vec::with_capacity(faces.len());
+    for face in faces.drain(..) {
+        let bbox = face.bbox_scaled(face.original_size)?;
+        let score = face.score()?;
+        keyed.push((bbox, score, face));
+    }
+    keyed.sort_by(|(a, a_score, _), (b, b_score, _)| match sort {
+        FaceSort::Detector => std::cmp::Ordering::Equal,
+        FaceSort::LeftToRight => (a.x, a.y).cmp(&(b.x, b.y)),
+        FaceSort::TopToBottom => (a.y, a.x).cmp(&(b.y, b.x)),
+        FaceSort::LargestFirst => (b.width * b.height).cmp(&(a.width * a.height)),
+        FaceSort::ScoreDesc => b_score.total_cmp(a_score),
+    });
+    faces.extend(keyed.into_iter().map(|(_, _, face)| face));
+    Ok(())
+}
 
-    pub async fn get_db_path(&self) -> Option<PathBuf> {
-        self.db_path.read().await.clone()
+/// Greedy non-maximum suppression over detected faces, keeping the highest scores.
+/// Surviving faces keep their detector order.
+fn suppress_overlapping_faces(
+    faces: Vec<DetectedFace>,
+    iou_threshold: f32,
+) -> Result<Vec<DetectedFace>> {
+    let mut candidates = Vec::with_capacity(faces.len());
+    for (index, face) in faces.iter().enumerate() {
+        candidates.push((index, face.bbox_original()?, face.score()?));
     }
+    candidates.sort_by(|a, b| b.2.total_cmp(&a.2));
 
-    pub async fn set_db_path<P: AsRef<Path>>(&self, path: P) {
-        let mut db_status = self.db_load_status.write().await;
-        *db_status = DbLoadStatus::NotLoaded;
-        drop(db_status);
+    let mut kept: Vec<(usize, Rect2i)> = Vec::new();
+    for (index, bbox, _) in candidates {
+        if kept
+            .iter()
+            .all(|(_, other)| rect_iou(bbox, *other) <= iou_threshold)
+        {
+            kept.push((index, bbox));
+        }
+    }
 
-        let mut db_path = self.db_path.write().await;
-        *db_path = Some(path.as_ref().to_path_buf());
+    let suppressed = faces.len() - kept.len();
+    if suppressed > 0 {
+        debug!(
+            "Post-detection NMS removed {} overlapping faces",
+            suppressed
+        );
     }
+    let mut keep = vec![false; faces.len()];
+    for (index, _) in kept {
+        keep[index] = true;
+    }
+    Ok(faces
+        .into_iter()
+        .zip(keep)
+        .filter_map(|(face, keep)| keep.then_some(face))
+        .collect())
+}
 
-    pub async fn load_persons_db<P: AsRef<Path>>(
-        &mut self,
-        persondb_folder: P,
-        force: bool,
-        visualize: bool,
-    ) -> Result<()> {
-        let path = persondb_folder.as_ref().to_path_buf();
+/// JSON manifest forms accepted by `load_persons_db_manifest`
+#[derive(serde::Deserialize)]
+#[serde(untagged)]
+enum JsonManifest {
+    Map(std::collections::BTreeMap<String, String>),
+    List(Vec<JsonManifestEntry>),
+}
 
-        // Check if we need to load
-        let current_path = self.db_path.read().await.clone();
-        let current_status = *self.db_load_status.read().await;
+#[derive(serde::Deserialize)]
+struct JsonManifestEntry {
+    file: String,
+    person: String,
+}
 
-        if current_path.is_none() || current_path.as_ref() != Some(&path) {
-            info!("Loading persons database from: {}", path.display());
-            let mut db_status = self.db_load_status.write().await;
-            *db_status = DbLoadStatus::NotLoaded;
-            drop(db_status);
+/// Read a `.json` or CSV manifest into `(file, person)` pairs, in file order
+fn read_manifest(path: &Path) -> Result<Vec<(String, String)>> {
+    let invalid = |message: String| {
+        FaceRecognitionError::InvalidManifest(format!("{}: {}", path.display(), message))
+    };
+    let content = std::fs::read_to_string(path)?;
 
-            let mut db_path = self.db_path.write().await;
-            *db_path = Some(path.clone());
-        } else if current_status == DbLoadStatus::Loaded && !force {
-            debug!("PersonsDB already loaded, skipping");
-            return Ok(());
+    let is_json = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| e.eq_ignore_ascii_case("json"));
+    if is_json {
+        let manifest: JsonManifest =
+            serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))?;
+        return Ok(match manifest {
+            JsonManifest::Map(map) => map.into_iter().collect(),
+            JsonManifest::List(list) => list.into_iter().map(|e| (e.file, e.person)).collect(),
+        });
+    }
+
+    let mut entries = Vec::new();
+    for (line_no, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (file, person) = line
+            .split_once(',')
+            .ok_or_else(|| invalid(format!("line {}: expected `file,person`", line_no + 1)))?;
+        let (file, person) = (file.trim(), person.trim());
+        if entries.is_empty()
+            && (file.eq_ignore_ascii_case("file") || file.eq_ignore_ascii_case("filename"))
+        {
+            continue; // Header
         }
+        if file.is_empty() || person.is_empty() {
+            return Err(invalid(format!(
+                "line {}: empty file or person",
+                line_no + 1
+            )));
+        }
+        entries.push((file.to_string(), person.to_string()));
+    }
+    Ok(entries)
+}
 
-        // Set loading status
-        let mut db_status = self.db_load_status.write().await;
-        *db_status = DbLoadStatus::Loading;
-        drop(db_status);
+/// Whether `path` has one of the image extensions processed in bulk (jpg, jpeg, png, bmp)
+pub(crate) fn has_image_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| matches!(ext.to_lowercase().as_str(), "jpg" | "jpeg" | "png" | "bmp"))
+}
 
-        info!("Loading persons database from: {}", path.display());
+/// Image files (jpg, jpeg, png, bmp) directly inside `dir`, sorted by path, as processed
+/// by the bulk calls such as `FaceRecognition::recognize_dir_iter`
+pub fn image_files(dir: &Path) -> std::io::Result<Vec<PathBuf>> {
+    let mut files: Vec<PathBuf> = std::fs::read_dir(dir)?
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && has_image_extension(path))
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Embeddings of different persons with a cosine similarity of at least
+/// `IDENTICAL_EMBEDDING_SIMILARITY`, logged as warnings
+fn find_cross_person_duplicates(
+    features: &HashMap<String, Vec<StoredFeature>>,
+) -> Result<Vec<CrossPersonDuplicate>> {
+    let mut names: Vec<&String> = features.keys().collect();
+    names.sort();
+    let mut entries = Vec::new();
+    for name in names {
+        for stored in &features[name] {
+            entries.push((
+                name,
+                stored.source.as_ref(),
+                feature_to_vec(&stored.feature_mat()?)?,
+            ));
+        }
+    }
 
-        // Clear existing features
-        let mut features = self.features_map.write().await;
-        features.clear();
-        drop(features);
+    let mut duplicates = Vec::new();
+    for (i, (first, first_source, a)) in entries.iter().enumerate() {
+        for (second, second_source, b) in &entries[i + 1..] {
+            if first != second && cosine_similarity(a, b) >= IDENTICAL_EMBEDDING_SIMILARITY {
+                warn!(
+                    "Identical embeddings stored for {} ({:?}) and {} ({:?})",
+                    first, first_source, second, second_source
+                );
+                duplicates.push(CrossPersonDuplicate {
+                    first: (*first).clone(),
+                    first_source: first_source.cloned(),
+                    second: (*second).clone(),
+                    second_source: second_source.cloned(),
+                });
+            }
+        }
+    }
+    Ok(duplicates)
+}
 
-        // Iterate over directories
-        for entry in std::fs::read_dir(&path)? {
-            let entry = entry?;
-            let person_path = entry.path();
+/// Box color of `name` for identity colors: a hue taken from a stable (FNV-1a) hash of
+/// the name, at fixed saturation and brightness, as BGR
+fn identity_color(name: &str) -> Scalar {
+    let hash = name.bytes().fold(0xcbf2_9ce4_8422_2325_u64, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    });
+    let hue = (hash % 360) as f64 / 60.0;
+    let (saturation, value) = (0.8, 255.0);
+    let chroma = value * saturation;
+    let x = chroma * (1.0 - (hue % 2.0 - 1.0).abs());
+    let (r, g, b) = match hue as u32 {
+        0 => (chroma, x, 0.0),
+        1 => (x, chroma, 0.0),
+        2 => (0.0, chroma, x),
+        3 => (0.0, x, chroma),
+        4 => (x, 0.0, chroma),
+        _ => (chroma, 0.0, x),
+    };
+    let min = value - chroma;
+    Scalar::new(b + min, g + min, r + min, 0.0)
+}
 
-            if person_path.is_dir() {
-                let person_name = person_path
-                    .file_name()
-                    .and_then(|n| n.to_str())
-                    .unwrap_or("unknown")
-                    .to_string();
+/// Mean absolute difference of the gray levels (0-255) of two frames, compared at a
+/// small fixed resolution so the check costs far less than detection. Used by
+/// `FaceRecognition::set_motion_skip_threshold` to find static video frames.
+pub fn frame_motion(a: &Mat, b: &Mat) -> Result<f64> {
+    let signature = |frame: &Mat| -> Result<Mat> {
+        let mut gray = Mat::default();
+        if frame.channels() == 1 {
+            gray = frame.try_clone()?;
+        } else {
+            opencv::imgproc::cvt_color_def(frame, &mut gray, opencv::imgproc::COLOR_BGR2GRAY)?;
+        }
+        let mut small = Mat::default();
+        opencv::imgproc::resize(
+            &gray,
+            &mut small,
+            MOTION_SIGNATURE_SIZE,
+            0.0,
+            0.0,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        Ok(small)
+    };
+    let mut diff = Mat::default();
+    opencv::core::absdiff(&signature(a)?, &signature(b)?, &mut diff)?;
+    Ok(opencv::core::mean_def(&diff)?[0])
+}
 
-                debug!("Loading person: {}", person_name);
-                let mut person_features = Vec::new();
+/// The parts of a persons database that `FaceRecognition::share_database` shares, read
+/// directly by `FaceRecognitionPool` so status queries never wait for a busy worker
+pub(crate) struct SharedDatabase {
+    pub(crate) status: Arc<RwLock<DbLoadStatus>>,
+    pub(crate) features: Arc<RwLock<Box<dyn FeatureStore>>>,
+    pub(crate) reload_events: broadcast::Sender<ReloadEvent>,
+}
 
-                // Load images from person directory
-                for img_entry in std::fs::read_dir(&person_path)? {
-                    let img_entry = img_entry?;
-                    let img_path = img_entry.path();
+/// Names of all persons in `store`, sorted alphabetically
+pub(crate) fn sorted_person_names(store: &dyn FeatureStore) -> Vec<String> {
+    let mut names: Vec<String> = store.iter().map(|(name, _)| name.into_owned()).collect();
+    names.sort();
+    names
+}
 
-                    if !img_path.is_dir() {
-                        let filename = img_path.file_name().and_then(|n| n.to_str()).unwrap_or("");
+/// Copy of `frame` at `size`, cloned if it already has that size
+fn resized_to(frame: &Mat, size: Size) -> Result<Mat> {
+    if frame.size()? == size {
+        return Ok(frame.try_clone()?);
+    }
+    let mut resized = Mat::default();
+    opencv::imgproc::resize(
+        frame,
+        &mut resized,
+        size,
+        0.0,
+        0.0,
+        opencv::imgproc::INTER_LINEAR,
+    )?;
+    Ok(resized)
+}
 
-                        // Skip visualize files
-                        if filename.contains("_visualize") {
-                            continue;
-                        }
+/// Extra reference holding the mirrored-crop embedding of `stored`, see
+/// `FaceRecognition::set_flip_augment`
+fn mirrored_reference(stored: &StoredFeature, feature: Mat, sequence: u64) -> StoredFeature {
+    StoredFeature {
+        feature,
+        sequence,
+        quantized: None,
+        half: None,
+        mirrored: true,
+        ..stored.clone()
+    }
+}
 
-                        debug!(
-                            "Loading image: {} for person {}",
-                            img_path.display(),
-                            person_name
-                        );
+/// Keep the `items` whose person (`person_of`) is named in `filter`, recording filter names
+/// without any item in `report`; everything is kept without a filter
+fn filter_persons<T>(
+    items: Vec<T>,
+    filter: Option<&[String]>,
+    person_of: impl Fn(&T) -> String,
+    report: &mut LoadReport,
+) -> Vec<T> {
+    let Some(filter) = filter else {
+        return items;
+    };
+    let wanted: std::collections::HashSet<String> =
+        filter.iter().map(|name| person_name_key(name)).collect();
+    let kept: Vec<T> = items
+        .into_iter()
+        .filter(|item| wanted.contains(&person_name_key(&person_of(item))))
+        .collect();
+    let found: std::collections::HashSet<String> = kept
+        .iter()
+        .map(|item| person_name_key(&person_of(item)))
+        .collect();
+    for name in filter {
+        if !found.contains(&person_name_key(name)) {
+            warn!("Person {} of the person filter was not found", name);
+            report.persons_not_found.push(name.clone());
+        }
+    }
+    kept
+}
 
-                        let img = imread(img_path.to_str().unwrap(), IMREAD_COLOR)?;
-                        if img.empty() {
-                            error!("Cannot read image: {}", img_path.display());
-                            continue;
-                        }
+/// When `path` was taken: EXIF `DateTimeOriginal` if present, else the file modification
+/// time
+fn capture_time(path: &Path) -> Option<SystemTime> {
+    exif_capture_time(path).or_else(|| std::fs::metadata(path).and_then(|m| m.modified()).ok())
+}
 
-                        // Extract features from all detected faces
-                        let detected_faces = self.extract_features(img.clone()).await?;
-                        for detected_face in detected_faces {
-                            person_features.push(detected_face.feature.try_clone()?);
-                        }
+/// EXIF `DateTimeOriginal` of `path`, taken as UTC
+fn exif_capture_time(path: &Path) -> Option<SystemTime> {
+    let file = std::fs::File::open(path).ok()?;
+    let exif = exif::Reader::new()
+        .read_from_container(&mut std::io::BufReader::new(file))
+        .ok()?;
+    let field = exif.get_field(exif::Tag::DateTimeOriginal, exif::In::PRIMARY)?;
+    let exif::Value::Ascii(values) = &field.value else {
+        return None;
+    };
+    let time = exif::DateTime::from_ascii(values.first()?).ok()?;
+    let secs = civil_to_unix_secs(
+        time.year.into(),
+        time.month.into(),
+        time.day.into(),
+        time.hour.into(),
+        time.minute.into(),
+        time.second.into(),
+    );
+    let secs = u64::try_from(secs).ok()?;
+    Some(SystemTime::UNIX_EPOCH + Duration::from_secs(secs))
+}
 
-                        // Create visualized version if requested
-                        if visualize {
-                            let stem = img_path
-                                .file_stem()
-                                .and_then(|s| s.to_str())
-                                .unwrap_or("image");
-                            let extension = img_path
-                                .extension()
-                                .and_then(|e| e.to_str())
-                                .unwrap_or("jpg");
-                            let visualize_path =
-                                person_path.join(format!("{stem}_visualize.{extension}"));
-
-                            let mut vis_img = img.clone();
-                            let faces = self.extract_features(vis_img.clone()).await?;
-                            for face in faces {
-                                if let Ok(bbox) = face.bbox_scaled(vis_img.size()?) {
-                                    self.visualize_face(&mut vis_img, bbox)?;
-                                }
-                            }
+/// Seconds since the Unix epoch of a UTC civil date and time (proleptic Gregorian
+/// calendar)
+fn civil_to_unix_secs(year: i64, month: i64, day: i64, hour: i64, minute: i64, second: i64) -> i64 {
+    // Days since 1970-01-01, counting years from March so leap days come last
+    let year = year - i64::from(month <= 2);
+    let era = year.div_euclid(400);
+    let year_of_era = year - era * 400;
+    let day_of_year = (153 * ((month + 9) % 12) + 2) / 5 + day - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    let days = era * 146_097 + day_of_era - 719_468;
 
-                            let _ = imwrite(
-                                visualize_path.to_str().unwrap(),
-                                &vis_img,
-                                &opencv::core::Vector::new(),
-                            );
-                        }
-                    }
-                }
+    days * 86_400 + hour * 3_600 + minute * 60 + second
+}
 
-                // Store features for this person
-                let mut features_map = self.features_map.write().await;
-                features_map.insert(person_name, person_features);
-            }
+/// Weight of the references from `image`, read from a `<stem>.weight` sidecar holding a
+/// single number; 1.0 without a readable sidecar
+fn read_weight_sidecar(image: &Path) -> f32 {
+    let sidecar = image.with_extension(WEIGHT_SIDECAR_EXTENSION);
+    let Ok(contents) = std::fs::read_to_string(&sidecar) else {
+        return 1.0;
+    };
+    match contents.trim().parse::<f32>() {
+        Ok(weight) if weight >= 0.0 => weight,
+        _ => {
+            warn!("Ignoring invalid weight in {}", sidecar.display());
+            1.0
         }
+    }
+}
 
-        // Set loaded status
-        let mut db_status = self.db_load_status.write().await;
-        *db_status = DbLoadStatus::Loaded;
+/// Image dimensions declared in the header of an encoded PNG, JPEG, BMP, GIF, WebP or TIFF
+/// image, without decoding it; `None` for other formats or malformed headers
+fn encoded_image_size(bytes: &[u8]) -> Option<(u64, u64)> {
+    let be16 = |at: usize| -> Option<u64> {
+        Some(u16::from_be_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u64)
+    };
+    let le16 = |at: usize| -> Option<u64> {
+        Some(u16::from_le_bytes(bytes.get(at..at + 2)?.try_into().ok()?) as u64)
+    };
+    let be32 = |at: usize| -> Option<u64> {
+        Some(u32::from_be_bytes(bytes.get(at..at + 4)?.try_into().ok()?) as u64)
+    };
+    let le24 = |at: usize| -> Option<u64> {
+        let b = bytes.get(at..at + 3)?;
+        Some(b[0] as u64 | (b[1] as u64) << 8 | (b[2] as u64) << 16)
+    };
+    let le32 = |at: usize| -> Option<u64> {
+        Some(i32::from_le_bytes(bytes.get(at..at + 4)?.try_into().ok()?).unsigned_abs() as u64)
+    };
 
-        info!("Database loading completed");
-        Ok(())
+    if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        return Some((be32(16)?, be32(20)?));
     }
-
-    pub async fn start_watching(&self, _check_interval_seconds: u64) -> Result<()> {
-        let db_path = {
-            let path_guard = self.db_path.read().await;
-            path_guard
-                .clone()
-                .ok_or(FaceRecognitionError::DatabaseNotLoaded)?
+    if bytes.starts_with(b"BM") {
+        return Some((le32(18)?, le32(22)?));
+    }
+    if bytes.starts_with(b"GIF8") {
+        return Some((le16(6)?, le16(8)?));
+    }
+    if bytes.starts_with(b"RIFF") && bytes.get(8..12) == Some(b"WEBP") {
+        return match bytes.get(12..16)? {
+            b"VP8X" => Some((le24(24)? + 1, le24(27)? + 1)),
+            // Lossy: 3-byte frame tag and start code, then 14-bit width and height
+            b"VP8 " if bytes.get(23..26) == Some(&[0x9D, 0x01, 0x2A]) => {
+                Some((le16(26)? & 0x3FFF, le16(28)? & 0x3FFF))
+            }
+            // Lossless: signature byte, then width - 1 and height - 1 in 14 bits each
+            b"VP8L" if bytes.get(20) == Some(&0x2F) => {
+                let bits = le32(21)?;
+                Some(((bits & 0x3FFF) + 1, ((bits >> 14) & 0x3FFF) + 1))
+            }
+            _ => None,
         };
-
-        if self
-            .watcher_running
-            .load(std::sync::atomic::Ordering::Relaxed)
-        {
-            debug!("Watcher already running");
-            return Ok(());
+    }
+    if bytes.starts_with(b"II*\0") || bytes.starts_with(b"MM\0*") {
+        return tiff_image_size(bytes);
+    }
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        // Walk the JPEG segments up to the first start-of-frame marker
+        let mut pos = 2;
+        loop {
+            if *bytes.get(pos)? != 0xFF {
+                return None;
+            }
+            let marker = *bytes.get(pos + 1)?;
+            match marker {
+                0xFF => pos += 1, // Fill byte
+                0x01 | 0xD0..=0xD7 => pos += 2,
+                0xC0..=0xCF if !matches!(marker, 0xC4 | 0xC8 | 0xCC) => {
+                    return Some((be16(pos + 7)?, be16(pos + 5)?));
+                }
+                0xDA | 0xD9 => return None,
+                _ => pos += 2 + be16(pos + 2)? as usize,
+            }
         }
+    }
+    None
+}
 
-        // Update last modification time
-        let latest_mod_time = get_latest_mod_time(&db_path)?;
-        let mut last_mod = self.last_mod_time.write().await;
-        *last_mod = latest_mod_time;
-        drop(last_mod);
-
-        // Start file watcher
-        let mut watcher_guard = self.watcher.lock().unwrap();
-        let mut watcher = FolderWatcher::new()?;
-        watcher.start_watching(&db_path)?;
+/// Width and height from the first image file directory of a TIFF file
+fn tiff_image_size(bytes: &[u8]) -> Option<(u64, u64)> {
+    let big_endian = bytes.starts_with(b"MM");
+    let read = |at: usize, len: usize| -> Option<u64> {
+        let field = bytes.get(at..at + len)?;
+        let fold = |acc: u64, b: &u8| acc << 8 | *b as u64;
+        Some(if big_endian {
+            field.iter().fold(0, fold)
+        } else {
+            field.iter().rev().fold(0, fold)
+        })
+    };
 
-        // Store watcher before moving it
-        *watcher_guard = Some(watcher);
-        drop(watcher_guard);
-        self.watcher_running
-            .store(true, std::sync::atomic::Ordering::Relaxed);
+    let ifd = read(4, 4)? as usize;
+    let entries = read(ifd, 2)? as usize;
+    let (mut width, mut height) = (None, None);
+    for entry in (0..entries).map(|i| ifd + 2 + 12 * i) {
+        let value = match read(entry + 2, 2)? {
+            3 => read(entry + 8, 2)?, // SHORT
+            4 => read(entry + 8, 4)?, // LONG
+            _ => continue,
+        };
+        match read(entry, 2)? {
+            256 => width = Some(value),
+            257 => height = Some(value),
+            _ => {}
+        }
+        if let (Some(width), Some(height)) = (width, height) {
+            return Some((width, height));
+        }
+    }
+    None
+}
 
-        info!("Started watching database folder: {}", db_path.display());
-        Ok(())
+/// Apply `norm` to a BGR frame in place; other frame types are left unchanged
+fn normalize_colors(frame: &mut Mat, norm: ColorNorm) -> Result<()> {
+    if frame.typ() != CV_8UC3 {
+        return Ok(());
     }
 
-    pub async fn stop_watching(&self) {
-        let mut watcher_guard = self.watcher.lock().unwrap();
-        if let Some(mut watcher) = watcher_guard.take() {
-            watcher.stop_watching();
+    let mut normalized = Mat::default();
+    match norm {
+        ColorNorm::Off => return Ok(()),
+        ColorNorm::Equalize => {
+            let mut ycrcb = Mat::default();
+            opencv::imgproc::cvt_color_def(frame, &mut ycrcb, opencv::imgproc::COLOR_BGR2YCrCb)?;
+            let mut channels = opencv::core::Vector::<Mat>::new();
+            opencv::core::split(&ycrcb, &mut channels)?;
+            let mut clahe = opencv::imgproc::create_clahe(2.0, Size::new(8, 8))?;
+            let mut luminance = Mat::default();
+            clahe.apply(&channels.get(0)?, &mut luminance)?;
+            channels.set(0, luminance)?;
+            opencv::core::merge(&channels, &mut ycrcb)?;
+            opencv::imgproc::cvt_color_def(
+                &ycrcb,
+                &mut normalized,
+                opencv::imgproc::COLOR_YCrCb2BGR,
+            )?;
+        }
+        ColorNorm::GrayWorld => {
+            let means = opencv::core::mean_def(frame)?;
+            let gray = (means[0] + means[1] + means[2]) / 3.0;
+            let mut channels = opencv::core::Vector::<Mat>::new();
+            opencv::core::split(frame, &mut channels)?;
+            let mut balanced = opencv::core::Vector::<Mat>::new();
+            for (i, channel) in channels.iter().enumerate() {
+                let gain = if means[i] > 0.0 { gray / means[i] } else { 1.0 };
+                let mut scaled = Mat::default();
+                channel.convert_to(&mut scaled, -1, gain, 0.0)?;
+                balanced.push(scaled);
+            }
+            opencv::core::merge(&balanced, &mut normalized)?;
         }
-        self.watcher_running
-            .store(false, std::sync::atomic::Ordering::Relaxed);
-        info!("Stopped watching database folder");
     }
+    *frame = normalized;
+    Ok(())
+}
 
-    async fn extract_features(&mut self, mut frame: Mat) -> Result<Vec<DetectedFace>> {
-        if frame.empty() {
-            return Err(FaceRecognitionError::InvalidImage);
-        }
+/// Highest detection score in a YuNet output, 0 when nothing was detected
+fn best_detection_score(faces: &Mat) -> Result<f32> {
+    let mut best = 0.0f32;
+    for i in 0..faces.rows() {
+        best = best.max(*faces.at_2d::<f32>(i, 14)?);
+    }
+    Ok(best)
+}
 
-        let original_size = frame.size()?;
-        self.resize_frame(&mut frame, true)?;
+/// Map a detection row from a frame of `from` size to one of `to` size
+fn scale_face_row(face_row: &Mat, from: Size, to: Size) -> Result<Mat> {
+    let scale_x = to.width as f32 / from.width as f32;
+    let scale_y = to.height as f32 / from.height as f32;
+    let mut row = face_row.try_clone()?;
+    for col in 0..14 {
+        let scale = if col % 2 == 0 { scale_x } else { scale_y };
+        *row.at_2d_mut::<f32>(0, col)? *= scale;
+    }
+    Ok(row)
+}
 
-        debug!("Frame size: {}x{}", frame.cols(), frame.rows());
+/// Convert `stored` to int8 storage with `quantize`, else to the storage `precision`
+fn compact_feature(
+    stored: &mut StoredFeature,
+    quantize: bool,
+    precision: FeaturePrecision,
+) -> Result<()> {
+    if quantize {
+        stored.quantize()?;
+    } else if precision == FeaturePrecision::F16 {
+        stored.to_half()?;
+    }
+    Ok(())
+}
 
-        // Set detector input size to match the resized frame (like C++ version)
-        let frame_size = frame.size()?;
-        self.detector.set_input_size(frame_size)?;
+/// Ensemble embeddings of a stored feature as plain vectors for snapshots and exports; an
+/// empty vector where a recognizer failed
+fn ensemble_to_vecs(ensemble_features: &[Mat]) -> opencv::Result<Vec<Vec<f32>>> {
+    ensemble_features.iter().map(feature_to_vec).collect()
+}
 
-        // Detect faces directly on the resized frame
-        let mut faces = Mat::default();
-        match self.detector.detect(&frame, &mut faces) {
-            Ok(_) => {}
-            Err(e) => {
-                error!("Face detection failed: {}", e);
-                return Err(FaceRecognitionError::DetectionFailed);
+/// Inverse of `ensemble_to_vecs`
+fn ensemble_from_vecs(vectors: &[Vec<f32>]) -> opencv::Result<Vec<Mat>> {
+    vectors
+        .iter()
+        .map(|values| {
+            if values.is_empty() {
+                Ok(Mat::default())
+            } else {
+                vec_to_feature(values)
             }
-        }
+        })
+        .collect()
+}
 
-        debug!("Found {} faces", faces.rows());
+/// Fail with `DatabaseModelMismatch` if `store` holds features of another size than
+/// `model_dim`. Meant for when nothing in the store could be scored against a probe, so
+/// mixed databases still match with the features that fit.
+fn check_database_dim(store: &dyn FeatureStore, model_dim: usize) -> Result<()> {
+    let database_dim = store.iter().find_map(|(_, features)| {
+        features
+            .iter()
+            .map(StoredFeature::dim)
+            .find(|&dim| dim != 0 && dim != model_dim)
+    });
+    match database_dim {
+        Some(database_dim) => Err(FaceRecognitionError::DatabaseModelMismatch {
+            database_dim,
+            model_dim,
+        }),
+        None => Ok(()),
+    }
+}
 
-        if faces.rows() <= 0 {
-            warn!("Cannot find any faces");
-            return Ok(Vec::new());
-        }
+/// Why `stored` cannot be matched: it has no values, or values that are not finite
+fn feature_defect(stored: &StoredFeature) -> Option<String> {
+    if stored.dim() == 0 {
+        return Some("no values".to_string());
+    }
+    let finite = stored
+        .feature_mat()
+        .and_then(|mat| Ok(mat.data_typed::<f32>()?.iter().all(|v| v.is_finite())));
+    match finite {
+        Ok(true) => None,
+        Ok(false) => Some("non-finite values".to_string()),
+        Err(e) => Some(format!("unreadable values ({e})")),
+    }
+}
 
-        let mut detected_faces = Vec::new();
-        for i in 0..faces.rows() {
-            let face_row = faces.row(i)?;
+/// Stored features of `persons` that cannot be matched, as `(person, index, reason)`
+/// sorted by person and index: those with a `feature_defect` and those of another size
+/// than most features, which come from another recognizer
+fn find_invalid_features<'a, I>(persons: I) -> Vec<(String, usize, String)>
+where
+    I: Iterator<Item = (&'a str, &'a [StoredFeature])> + Clone,
+{
+    let mut dim_counts: HashMap<usize, usize> = HashMap::new();
+    for (_, features) in persons.clone() {
+        for stored in features {
+            *dim_counts.entry(stored.dim()).or_default() += 1;
+        }
+    }
+    let common_dim = dim_counts
+        .into_iter()
+        .filter(|&(dim, _)| dim > 0)
+        .max_by_key(|&(dim, count)| (count, dim))
+        .map(|(dim, _)| dim);
+
+    let mut invalid = Vec::new();
+    for (person, features) in persons {
+        for (index, stored) in features.iter().enumerate() {
+            let defect = feature_defect(stored).or_else(|| {
+                common_dim
+                    .filter(|&dim| stored.dim() != dim)
+                    .map(|dim| format!("{} values, expected {}", stored.dim(), dim))
+            });
+            if let Some(reason) = defect {
+                invalid.push((person.to_string(), index, reason));
+            }
+        }
+    }
+    invalid.sort();
+    invalid
+}
+
+/// Remove the features listed by `find_invalid_features` from `features`
+fn remove_features(
+    features: &mut HashMap<String, Vec<StoredFeature>>,
+    invalid: &[(String, usize, String)],
+) {
+    // Back to front, so the remaining indices of a person stay valid
+    for (person, index, _) in invalid.iter().rev() {
+        if let Some(person_features) = features.get_mut(person) {
+            person_features.remove(*index);
+        }
+    }
+}
+
+/// `compact_feature` for all features
+fn compact_features(
+    features: &mut HashMap<String, Vec<StoredFeature>>,
+    quantize: bool,
+    precision: FeaturePrecision,
+) -> Result<()> {
+    for stored in features.values_mut().flatten() {
+        compact_feature(stored, quantize, precision)?;
+    }
+    Ok(())
+}
+
+/// Drop features until at most `max` remain, picking victims according to `policy`.
+/// Returns the number of features dropped.
+fn enforce_feature_cap(
+    features: &mut HashMap<String, Vec<StoredFeature>>,
+    max: usize,
+    policy: FeatureCapPolicy,
+) -> usize {
+    let total: usize = features.values().map(Vec::len).sum();
+    if total <= max {
+        return 0;
+    }
+
+    // Every feature as (person, index, sequence, quality), sorted once into eviction order
+    let names: Vec<String> = features.keys().cloned().collect();
+    let mut order: Vec<(usize, usize, u64, f32)> = Vec::with_capacity(total);
+    for (person, name) in names.iter().enumerate() {
+        for (index, stored) in features[name].iter().enumerate() {
+            order.push((person, index, stored.sequence, stored.quality));
+        }
+    }
+    order.sort_by(|a, b| match policy {
+        FeatureCapPolicy::RejectNew => b.2.cmp(&a.2),
+        FeatureCapPolicy::EvictOldest => a.2.cmp(&b.2),
+        FeatureCapPolicy::EvictLowestQuality => a.3.total_cmp(&b.3).then(a.2.cmp(&b.2)),
+    });
+
+    let evicted = total - max;
+    let mut victims = vec![Vec::new(); names.len()];
+    for &(person, index, _, _) in &order[..evicted] {
+        victims[person].push(index);
+    }
+    for (name, mut indices) in names.iter().zip(victims) {
+        if indices.is_empty() {
+            continue;
+        }
+        indices.sort_unstable();
+        if let Some(list) = features.get_mut(name) {
+            let mut index = 0;
+            list.retain(|_| {
+                index += 1;
+                indices.binary_search(&(index - 1)).is_err()
+            });
+        }
+    }
+
+    evicted
+}
+
+/// Folder holding the person folders of an extracted archive: the extraction root, or its
+/// single top-level folder when the archive wraps everything in one
+fn archive_db_root(extracted: &Path) -> Result<PathBuf> {
+    let entries = std::fs::read_dir(extracted)?.collect::<std::io::Result<Vec<_>>>()?;
+    if let [single] = entries.as_slice() {
+        let path = single.path();
+        if path.is_dir()
+            && std::fs::read_dir(&path)?.all(|e| e.map(|e| e.path().is_dir()).unwrap_or(false))
+        {
+            return Ok(path);
+        }
+    }
+    Ok(extracted.to_path_buf())
+}
+
+impl FaceRecognition {
+    pub fn new(
+        fd_model_path: Option<&str>,
+        fr_model_path: Option<&str>,
+        max_size: Option<i32>,
+    ) -> Result<Self> {
+        Self::from_builder(FaceRecognitionBuilder {
+            fd_model_path: fd_model_path.map(str::to_string),
+            fr_model_path: fr_model_path.map(str::to_string),
+            max_size,
+            ..Default::default()
+        })
+    }
+
+    pub fn builder() -> FaceRecognitionBuilder {
+        FaceRecognitionBuilder::new()
+    }
+
+    pub(crate) fn from_builder(config: FaceRecognitionBuilder) -> Result<Self> {
+        let fd_path = resolve_model_path(config.fd_model_path.as_deref(), FD_MODEL_FILE)?;
+        let fr_path = resolve_model_path(config.fr_model_path.as_deref(), FR_MODEL_FILE)?;
+        let fd_path = fd_path.to_str().ok_or_else(|| {
+            FaceRecognitionError::ModelNotFound(format!("{} (non UTF-8 path)", fd_path.display()))
+        })?;
+        let fr_path = fr_path.to_str().ok_or_else(|| {
+            FaceRecognitionError::ModelNotFound(format!("{} (non UTF-8 path)", fr_path.display()))
+        })?;
+
+        debug!("Initializing face detection model: {}", fd_path);
+        // Match C++ default size unless the operating resolution is known
+        let detector_input_size = config.detector_input_size.unwrap_or(Size::new(400, 400));
+        let detector = FaceDetectorYN::create(
+            fd_path,
+            "",
+            detector_input_size,
+            SCORE_THRESHOLD,
+            NMS_THRESHOLD,
+            TOP_K,
+            config.detector_backend.backend,
+            config.detector_backend.target,
+        )?;
+
+        debug!("Initializing face recognition model: {}", fr_path);
+        let face_recognizer = FaceRecognizerSF::create(
+            fr_path,
+            "",
+            config.recognizer_backend.backend,
+            config.recognizer_backend.target,
+        )?;
+
+        let mut face_rec = Self::with_backends(
+            Box::new(detector),
+            Box::new(face_recognizer),
+            config.max_size,
+        );
+        face_rec.recognizer_config = config.recognizer;
+        face_rec.detector_input_size = Some(detector_input_size);
+        Ok(face_rec)
+    }
+
+    /// Create an instance around custom detector/recognizer implementations instead of
+    /// the OpenCV models (e.g. the scripted backends of the `mock` feature)
+    pub fn with_backends(
+        detector: Box<dyn Detector>,
+        face_recognizer: Box<dyn Recognizer>,
+        max_size: Option<i32>,
+    ) -> Self {
+        Self {
+            detector,
+            face_recognizer,
+            primary_fusion: FusionWeight::default(),
+            ensemble: Vec::new(),
+            max_size: max_size.unwrap_or(600),
+            recognizer_config: RecognizerConfig::default(),
+            db_load_status: Arc::new(RwLock::new(DbLoadStatus::NotLoaded)),
+            features_map: Arc::new(RwLock::new(Box::new(InMemoryStore::new()))),
+            db_path: Arc::new(RwLock::new(None)),
+            last_mod_time: Arc::new(RwLock::new(SystemTime::UNIX_EPOCH)),
+            watcher: Arc::new(Mutex::new(None)),
+            watch_task: Arc::new(Mutex::new(None)),
+            watcher_running: Arc::new(AtomicBool::new(false)),
+            watch_paused: Arc::new(AtomicBool::new(false)),
+            reload_pending: Arc::new(AtomicBool::new(false)),
+            reload_events: broadcast::channel(RELOAD_EVENT_CAPACITY).0,
+            background_loader: Mutex::new(None),
+            counters: Counters::default(),
+            name_collision_policy: NameCollisionPolicy::default(),
+            min_enroll_eye_distance: 0.0,
+            min_probe_eye_distance: 0.0,
+            last_load_report: LoadReport::default(),
+            match_log_throttle: LogThrottle::new(1, Duration::ZERO),
+            crop_margin: 0.0,
+            crop_padding: CropPadding::default(),
+            person_thresholds: HashMap::new(),
+            denylist: HashSet::new(),
+            preprocess: None,
+            max_decode_pixels: 0,
+            color_norm: ColorNorm::default(),
+            motion_skip_threshold: 0.0,
+            identity_colors: false,
+            track_capture_time: false,
+            max_total_features: 0,
+            feature_cap_policy: FeatureCapPolicy::default(),
+            post_nms_iou: None,
+            face_sort: FaceSort::Detector,
+            recognition_mode: RecognitionMode::default(),
+            unknown_label: "Unknown".to_string(),
+            unverified_label: "Unknown".to_string(),
+            unverified_band: 0.0,
+            tie_break: TieBreak::default(),
+            person_filter: None,
+            reference_aggregation: ReferenceAggregation::default(),
+            detect_cross_person_duplicates: false,
+            leave_one_out: false,
+            missing_database_policy: MissingDatabasePolicy::default(),
+            invalid_feature_policy: InvalidFeaturePolicy::default(),
+            missing_database_warned: false,
+            empty_database_warned: false,
+            tta: false,
+            flip_augment: false,
+            keep_aligned: false,
+            enrolling: false,
+            try_upside_down: false,
+            fullres_retry: false,
+            #[cfg(feature = "parallel")]
+            parallel_match_min_features: 0,
+            eye_line_correction: false,
+            visualize_naming: VisualizeNaming::default(),
+            tiling: None,
+            full_resolution_crops: false,
+            enrollment_normalization: EnrollmentNormalization::default(),
+            quantize_features: false,
+            feature_precision: FeaturePrecision::F32,
+            detection_size_ceiling: DEFAULT_DETECTION_SIZE_CEILING,
+            strict_single_face: false,
+            confidence_mapping: ConfidenceMapping::default(),
+            force_detection_size_ceiling: false,
+            detector_input_size: None,
+        }
+    }
+
+    pub fn set_max_size(&mut self, size: i32) {
+        self.max_size = size;
+    }
+
+    /// Longest frame side the detector should run on (default 4096, `0` disables the
+    /// check). Larger detection frames, e.g. 8K images with `max_size` 0, log a warning;
+    /// with `force_resize` they are downscaled to the ceiling even in no-resize mode.
+    /// Coordinates stay correct either way: `bbox_scaled`/`bbox_original` map from the
+    /// actual detection size back to the frame.
+    pub fn set_detection_size_ceiling(&mut self, ceiling: i32, force_resize: bool) {
+        self.detection_size_ceiling = ceiling;
+        self.force_detection_size_ceiling = force_resize;
+    }
+
+    /// Set how person folders whose names collide after normalization are handled
+    pub fn set_name_collision_policy(&mut self, policy: NameCollisionPolicy) {
+        self.name_collision_policy = policy;
+    }
+
+    /// Reject enrollment faces whose inter-ocular distance (in original image pixels) is
+    /// below `pixels`. `0.0` disables the check.
+    pub fn set_min_enroll_eye_distance(&mut self, pixels: f32) {
+        self.min_enroll_eye_distance = pixels;
+    }
+
+    /// Report query faces whose inter-ocular distance (in original image pixels) is below
+    /// `pixels` as "Unknown" without matching them: tiny faces compared with references
+    /// from large enrollment photos give unreliable scores either way. Unlike detection
+    /// filtering the face is still returned, with its box. Counted in
+    /// `Metrics::faces_too_small`. `0.0` (the default) disables the check.
+    pub fn set_min_probe_eye_distance(&mut self, pixels: f32) {
+        self.min_probe_eye_distance = pixels;
+    }
+
+    /// Match one query face, the entry point of every recognition path: faces below the
+    /// minimum probe eye distance get an empty ranking and an unmatched result without
+    /// being compared
+    async fn match_face(&mut self, face: &DetectedFace, threshold: f32) -> Result<MatchResults> {
+        if self.probe_too_small(face)? {
+            return Ok(MatchResults {
+                results: Vec::new(),
+                best_match: self.unmatched_result(None, 0.0),
+            });
+        }
+        self.find_best_match(&face.feature, &face.ensemble_features, threshold)
+            .await
+    }
+
+    /// Whether `face` is below the minimum probe eye distance, counting it if so
+    fn probe_too_small(&self, face: &DetectedFace) -> Result<bool> {
+        if self.min_probe_eye_distance <= 0.0 {
+            return Ok(false);
+        }
+        let distance = face.inter_ocular_distance()?;
+        if distance >= self.min_probe_eye_distance {
+            return Ok(false);
+        }
+        debug!(
+            "Skipping face with inter-ocular distance {:.1}px below {:.1}px",
+            distance, self.min_probe_eye_distance
+        );
+        self.counters
+            .faces_too_small
+            .fetch_add(1, Ordering::Relaxed);
+        Ok(true)
+    }
+
+    /// Limit the "Face N best match" info lines of `run` to one call out of `every_n` and
+    /// at most one per `min_interval`. The default (`1`, `Duration::ZERO`) logs every call.
+    pub fn set_match_log_throttle(&mut self, every_n: u64, min_interval: Duration) {
+        self.match_log_throttle = LogThrottle::new(every_n, min_interval);
+    }
+
+    /// Margin added around the detection box, as a fraction of its width/height, when
+    /// the box-crop fallback is used because landmark alignment failed
+    pub fn set_crop_margin(&mut self, margin: f32) {
+        self.crop_margin = margin.max(0.0);
+    }
+
+    /// Border mode used to square the box-crop fallback. Defaults to `CropPadding::None`,
+    /// which resizes the raw box; `CropPadding::Replicate` avoids stretching non-square
+    /// boxes. Reload the database after changing it.
+    pub fn set_crop_padding(&mut self, padding: CropPadding) {
+        self.crop_padding = padding;
+    }
+
+    /// Require a score above `threshold` for `name` instead of the threshold passed to
+    /// `run`, e.g. to be stricter for look-alikes. `name` is compared case-insensitively.
+    pub fn set_person_threshold(&mut self, name: &str, threshold: f32) {
+        self.person_thresholds
+            .insert(person_name_key(name), threshold);
+    }
+
+    pub fn clear_person_threshold(&mut self, name: &str) {
+        self.person_thresholds.remove(&person_name_key(name));
+    }
+
+    /// The per-person thresholds, keyed by `person_name_key`
+    pub fn person_thresholds(&self) -> &HashMap<String, f32> {
+        &self.person_thresholds
+    }
+
+    /// The threshold `name` has to exceed, falling back to `threshold`
+    fn person_threshold(&self, name: &str, threshold: f32) -> f32 {
+        self.person_thresholds
+            .get(&person_name_key(name))
+            .copied()
+            .unwrap_or(threshold)
+    }
+
+    /// Never match the persons in `names` (compared case-insensitively), e.g. for revoked
+    /// access or opt-outs, while keeping their references in the database for audit. They
+    /// are skipped by `find_best_match` and `find_top_matches`, so they never appear in
+    /// results, but they are still loaded and count toward `LoadReport` and `list_persons`.
+    /// Replaces the previous denylist; an empty set disables it.
+    pub fn set_denylist(&mut self, names: HashSet<String>) {
+        self.denylist = names.iter().map(|name| person_name_key(name)).collect();
+    }
+
+    fn is_denied(&self, name: &str) -> bool {
+        !self.denylist.is_empty() && self.denylist.contains(&person_name_key(name))
+    }
+
+    /// Reject encoded images passed to `run_from_bytes` with more than `max_pixels` pixels
+    /// (0, the default, disables the limit) with `ImageTooLarge`.
+    ///
+    /// This guards services decoding untrusted uploads against decompression bombs: a
+    /// few kilobytes of PNG or JPEG can declare an image that needs gigabytes once
+    /// decoded. The size is read from the header and oversized images are rejected
+    /// without decoding. While a limit is set, only formats whose header is understood
+    /// (PNG, JPEG, BMP, GIF, WebP and TIFF) are accepted; anything else fails with
+    /// `UnsupportedImageFormat` instead of being decoded blindly.
+    pub fn set_max_decode_pixels(&mut self, max_pixels: u64) {
+        self.max_decode_pixels = max_pixels;
+    }
+
+    /// Normalize the colors of every frame before detection (and before the `preprocess`
+    /// hook), for databases enrolled with one camera and queried with others. Applies to
+    /// enrollment too, so reload the database after changing it.
+    pub fn set_color_normalization(&mut self, norm: ColorNorm) {
+        self.color_norm = norm;
+    }
+
+    /// Skip recognition in `annotate_video` for frames whose `frame_motion` compared to
+    /// the last processed frame is below `threshold` (mean absolute gray level difference,
+    /// 0-255; 0, the default, processes every frame). Skipped frames carry the previous
+    /// results forward: they are annotated with the last boxes and names, so a face that
+    /// enters or moves slowly is only picked up once the accumulated change exceeds the
+    /// threshold.
+    pub fn set_motion_skip_threshold(&mut self, threshold: f64) {
+        self.motion_skip_threshold = threshold.max(0.0);
+    }
+
+    /// Draw the boxes of recognized faces in a color derived from the person's name, the
+    /// same in every frame and run, so identities are easy to follow in video overlays.
+    /// "Unknown" faces stay green. Off by default (all boxes green).
+    pub fn set_identity_colors(&mut self, enabled: bool) {
+        self.identity_colors = enabled;
+    }
+
+    /// Install a hook that modifies every frame before detection (histogram
+    /// equalization, white balance, denoising, ...).
+    ///
+    /// The hook runs after the frame was decoded (`imread` has already applied the EXIF
+    /// orientation) and downscaled to `max_size`, so it works on the detection-sized
+    /// image. It must not change the frame size. The caller's frame is never modified.
+    pub fn set_preprocess(&mut self, preprocess: PreprocessFn) {
+        self.preprocess = Some(preprocess);
+    }
+
+    pub fn clear_preprocess(&mut self) {
+        self.preprocess = None;
+    }
+
+    /// Cap the total number of stored features across all persons; `0` means no cap.
+    ///
+    /// The cap is enforced after each person is added during a load or import. When it is
+    /// exceeded, features are dropped according to `policy` (see `FeatureCapPolicy`) and
+    /// counted in `LoadReport::features_evicted`.
+    pub fn set_max_total_features(&mut self, max: usize, policy: FeatureCapPolicy) {
+        self.max_total_features = max;
+        self.feature_cap_policy = policy;
+    }
+
+    /// Room left under the feature cap when `stored` features are kept and the policy is
+    /// `FeatureCapPolicy::RejectNew`; `None` when new features are always taken in (no
+    /// cap, or a policy that evicts older ones instead)
+    fn reject_new_capacity(&self, stored: usize) -> Option<usize> {
+        (self.max_total_features > 0 && self.feature_cap_policy == FeatureCapPolicy::RejectNew)
+            .then(|| self.max_total_features.saturating_sub(stored))
+    }
+
+    /// Keep at most the features of `features` that fit under the feature cap with
+    /// `FeatureCapPolicy::RejectNew`, returning the number dropped
+    fn reject_over_cap(&self, features: &mut Vec<StoredFeature>, stored: usize) -> usize {
+        match self.reject_new_capacity(stored) {
+            Some(room) if features.len() > room => {
+                let rejected = features.len() - room;
+                features.truncate(room);
+                rejected
+            }
+            _ => 0,
+        }
+    }
+
+    /// Run an extra non-maximum suppression over the detected faces: of two faces whose
+    /// boxes overlap with an IoU above `iou_threshold`, only the higher scoring one is kept.
+    /// `None` (the default) relies on YuNet's internal NMS only.
+    pub fn set_post_nms_iou(&mut self, iou_threshold: Option<f32>) {
+        self.post_nms_iou = iou_threshold;
+    }
+
+    /// Order of the faces returned by `run`, `run_detailed`, `run_ranked` and the other
+    /// calls built on them, e.g. `FaceSort::LeftToRight` for a stable order in a UI.
+    /// `FaceResult::index` follows this order. Defaults to `FaceSort::Detector`.
+    pub fn set_face_sort(&mut self, sort: FaceSort) {
+        self.face_sort = sort;
+    }
+
+    /// Choose between open-set (default, below-threshold faces are "Unknown") and
+    /// closed-set recognition (the best candidate is always returned)
+    pub fn set_recognition_mode(&mut self, mode: RecognitionMode) {
+        self.recognition_mode = mode;
+    }
+
+    /// Names reported for faces that match nobody: `unverified` when the closest person
+    /// scored within `band` below their threshold, `unknown` otherwise (also for faces
+    /// too small to match). Both default to "Unknown". Such results are flagged as
+    /// `MatchResult::unmatched`, so `is_unknown` holds for either label.
+    pub fn set_unmatched_labels(&mut self, unknown: &str, unverified: &str, band: f32) {
+        self.unknown_label = unknown.to_string();
+        self.unverified_label = unverified.to_string();
+        self.unverified_band = band.max(0.0);
+    }
+
+    /// Result for a face that matched nobody, labeled by how close `closest` (the best
+    /// candidate's score and threshold) came
+    fn unmatched_result(&self, closest: Option<(f32, f32)>, score: f32) -> MatchResult {
+        let label = match closest {
+            Some((best, threshold)) if best > threshold - self.unverified_band => {
+                &self.unverified_label
+            }
+            _ => &self.unknown_label,
+        };
+        MatchResult::unmatched(label.clone(), score)
+    }
+
+    /// Choose how the weighted scores of a person's references combine into the person's
+    /// score: the relatively weighted best score (default) or the weighted mean
+    pub fn set_reference_aggregation(&mut self, aggregation: ReferenceAggregation) {
+        self.reference_aggregation = aggregation;
+    }
+
+    /// Set the weight of the references `person` got from `source`, e.g. to favor the
+    /// most representative photo. Weights are relative among a person's references and
+    /// default to 1.0, or to the number in a `<stem>.weight` sidecar next to the image;
+    /// 0 disables a reference. Returns the number of features updated.
+    pub async fn set_reference_weight<P: AsRef<Path>>(
+        &mut self,
+        person: &str,
+        source: P,
+        weight: f32,
+    ) -> Result<usize> {
+        let person = normalize_person_name(person);
+        let canonical = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let source = canonical(source.as_ref());
+        let mut features_map = self.features_map.write().await;
+        let mut features = features_map
+            .remove(&person)
+            .ok_or_else(|| FaceRecognitionError::PersonNotFound(person.clone()))?;
+        let mut updated = 0;
+        for stored in &mut features {
+            if stored.source.as_deref().map(canonical).as_ref() == Some(&source) {
+                stored.weight = weight.max(0.0);
+                updated += 1;
+            }
+        }
+        for stored in features {
+            features_map.insert(&person, stored);
+        }
+        Ok(updated)
+    }
+
+    /// Load only the persons named in `names` (compared like folder names, ignoring case
+    /// and surrounding whitespace) from database folders and manifests, e.g. to keep just
+    /// the identities a process cares about from a large shared database. `None`, the
+    /// default, loads everyone. Names without a person on disk are listed in
+    /// `LoadReport::persons_not_found`. Takes effect on the next load or reload.
+    pub fn set_person_filter(&mut self, names: Option<Vec<String>>) {
+        self.person_filter = names;
+    }
+
+    /// Record when each enrollment image was taken: its EXIF `DateTimeOriginal` (read as
+    /// UTC, EXIF has no time zone) or, without one, the file modification time. Exposed
+    /// by `reference_capture_times` and used by `prune_references_before`, e.g. to drop
+    /// stale references. Off by default; takes effect for images loaded afterwards.
+    pub fn set_track_capture_time(&mut self, enabled: bool) {
+        self.track_capture_time = enabled;
+    }
+
+    /// Choose how exact score ties between persons are resolved: alphabetically (default)
+    /// or by reporting the face as "Unknown"
+    pub fn set_tie_break(&mut self, tie_break: TieBreak) {
+        self.tie_break = tie_break;
+    }
+
+    /// Compare every stored embedding with those of all other persons after loading and
+    /// warn about identical ones, which point to an image enrolled under two names. The
+    /// pairs are listed in `LoadReport::cross_person_duplicates`. Off by default since the
+    /// check is quadratic in the number of features.
+    pub fn set_detect_cross_person_duplicates(&mut self, enabled: bool) {
+        self.detect_cross_person_duplicates = enabled;
+    }
+
+    /// Leave-one-out evaluation in `suggest_threshold`: each probe is matched without the
+    /// references extracted from that same image, so a labeled set overlapping the
+    /// database (or the database folder itself) does not trivially match itself. This
+    /// relies on the references' `source`, which features loaded from a folder or manifest
+    /// have but imported or restored ones may lack; those are never excluded.
+    pub fn set_leave_one_out(&mut self, enabled: bool) {
+        self.leave_one_out = enabled;
+    }
+
+    /// Test-time augmentation: average each embedding with the embedding of the
+    /// horizontally flipped crop. This is more robust for difficult faces but doubles the
+    /// recognition cost. It applies to database loading and queries alike, so reload the
+    /// database after changing it. `suggest_threshold` reports the accuracy of probes
+    /// embedded without it, to measure its effect.
+    pub fn set_tta(&mut self, enabled: bool) {
+        self.tta = enabled;
+    }
+
+    /// Enrollment augmentation: store the embedding of the horizontally flipped crop as an
+    /// extra reference next to each enrolled face, for database loads, `enroll_file` and
+    /// `reembed`. Can make matching more robust to pose, at the cost of twice the stored
+    /// features (also counted in `LoadReport::features_loaded` and the feature cap).
+    /// Queries are unaffected; reload the database after changing it.
+    pub fn set_flip_augment(&mut self, enabled: bool) {
+        self.flip_augment = enabled;
+    }
+
+    /// Keep the aligned crop each feature was extracted from on the `DetectedFace` (see
+    /// `DetectedFace::aligned`), e.g. to inspect mismatches or build datasets from
+    /// `for_each_detected_face`. Off by default since every face then holds its crop.
+    pub fn set_keep_aligned(&mut self, enabled: bool) {
+        self.keep_aligned = enabled;
+    }
+
+    /// Match probes against the gallery on the rayon thread pool once it holds at least
+    /// `min_features` features (0 disables, the default). Worthwhile for 1:N search over
+    /// tens of thousands of features; small galleries are faster sequentially.
+    #[cfg(feature = "parallel")]
+    pub fn set_parallel_matching(&mut self, min_features: usize) {
+        self.parallel_match_min_features = min_features;
+    }
+
+    /// Handles to the shared database state, for reading it without this instance
+    pub(crate) fn shared_database(&self) -> SharedDatabase {
+        SharedDatabase {
+            status: Arc::clone(&self.db_load_status),
+            features: Arc::clone(&self.features_map),
+            reload_events: self.reload_events.clone(),
+        }
+    }
+
+    /// Use the persons database of `other` (features, load status, path and pending
+    /// watcher reloads) instead of this instance's own, see `FaceRecognitionPool`
+    pub(crate) fn share_database(&mut self, other: &FaceRecognition) {
+        self.features_map = Arc::clone(&other.features_map);
+        self.db_load_status = Arc::clone(&other.db_load_status);
+        self.db_path = Arc::clone(&other.db_path);
+        self.last_mod_time = Arc::clone(&other.last_mod_time);
+        self.reload_pending = Arc::clone(&other.reload_pending);
+        self.reload_events = other.reload_events.clone();
+    }
+
+    /// Keep the persons database in `store` instead of the default `InMemoryStore`. The
+    /// current database is discarded; a store that already holds features (e.g. one
+    /// shared with another process) counts as loaded, otherwise load or enroll as usual.
+    /// Its features are checked like loaded ones, see `set_invalid_feature_policy`.
+    pub async fn set_feature_store(&mut self, mut store: Box<dyn FeatureStore>) -> Result<()> {
+        let invalid = {
+            let entries: Vec<StoreEntry> = store.iter().collect();
+            find_invalid_features(
+                entries
+                    .iter()
+                    .map(|(person, features)| (person.as_ref(), features.as_ref())),
+            )
+        };
+        if !invalid.is_empty() {
+            self.check_invalid_features(&invalid)?;
+            let mut features = store.take_all();
+            remove_features(&mut features, &invalid);
+            store.replace_all(features);
+        }
+        if !store.is_empty() {
+            *self.db_load_status.write().await = DbLoadStatus::Loaded;
+        }
+        *self.features_map.write().await = store;
+        Ok(())
+    }
+
+    /// When detection finds no face, or only low-confidence ones, detect again on the
+    /// frame rotated by 180° and keep whichever pass found the more confident face. Helps
+    /// with upside-down scans and mounted cameras at a fraction of the cost of trying
+    /// several angles. Off by default.
+    pub fn set_try_upside_down(&mut self, enabled: bool) {
+        self.try_upside_down = enabled;
+    }
+
+    /// When detection on the `max_size`-downscaled frame finds no face, detect once more
+    /// on the original resolution, recovering small faces lost to downscaling. The retry
+    /// respects the detection size ceiling (see `set_detection_size_ceiling`): larger
+    /// frames are retried at the ceiling instead. Frames with faces cost only an extra
+    /// copy; empty frames pay for a second, larger pass. Off by default; has no effect on
+    /// frames that are not downscaled or with tiling.
+    pub fn set_fullres_retry(&mut self, enabled: bool) {
+        self.fullres_retry = enabled;
+    }
+
+    /// Rotate each face region so the eyes are level before `align_crop`. The SFace
+    /// alignment already compensates moderate tilt, so this is off by default; it can give
+    /// more consistent embeddings for strongly tilted faces. Reload the database after
+    /// changing it.
+    pub fn set_eye_line_correction(&mut self, enabled: bool) {
+        self.eye_line_correction = enabled;
+    }
+
+    /// Where `load_persons_db` with `visualize` writes annotated enrollment images, by
+    /// default `<stem>_visualize.<ext>` next to the original. `VisualizeNaming::Subfolder`
+    /// keeps them apart from the references altogether.
+    ///
+    /// Fails with `FaceRecognitionError::InvalidConfig` if the suffix or folder name is
+    /// empty or contains a path separator or `..`.
+    pub fn set_visualize_naming(&mut self, naming: VisualizeNaming) -> Result<()> {
+        let name = match &naming {
+            VisualizeNaming::Suffix(name) | VisualizeNaming::Subfolder(name) => name,
+        };
+        if name.is_empty() || name.contains(['/', '\\']) || name.contains("..") {
+            return Err(FaceRecognitionError::InvalidConfig(format!(
+                "visualize naming needs a non-empty name without path separators or \"..\", \
+                 got {:?}",
+                naming
+            )));
+        }
+        self.visualize_naming = naming;
+        Ok(())
+    }
+
+    /// Detect on overlapping tiles at native resolution instead of downscaling to
+    /// `max_size`, for images larger than one tile (e.g. panoramas with small faces).
+    /// Detections along tile seams are merged by IoU. `None` disables tiling.
+    ///
+    /// Fails with `FaceRecognitionError::InvalidConfig` unless `tile_size` is positive and
+    /// `overlap` lies in `0..tile_size`, keeping the previous setting.
+    pub fn set_tiling(&mut self, tiling: Option<TileConfig>) -> Result<()> {
+        if let Some(config) = &tiling {
+            if config.tile_size <= 0 || config.overlap < 0 || config.overlap >= config.tile_size {
+                return Err(FaceRecognitionError::InvalidConfig(format!(
+                    "tiling needs a positive tile size and an overlap below it, got tile size \
+                     {} with overlap {}",
+                    config.tile_size, config.overlap
+                )));
+            }
+        }
+        self.tiling = tiling;
+        Ok(())
+    }
+
+    /// Detect on the `max_size`-downscaled frame as usual, but take the recognition crops
+    /// from the full-resolution original. This gives sharper embeddings for high-resolution
+    /// inputs at the cost of keeping (and preprocessing) a full-size copy of every frame.
+    /// Applies to `run`, database loading and the other `extract_features`-based calls.
+    pub fn set_full_resolution_crops(&mut self, enabled: bool) {
+        self.full_resolution_crops = enabled;
+    }
+
+    /// Reduce enrollment images (database load and `enroll_file`) to a face-centered crop
+    /// of at most `NORMALIZED_ENROLLMENT_SIZE` pixels, which shrinks the database and
+    /// speeds up later loads. Only images with exactly one face that are larger than the
+    /// crop size are touched; originals are kept unless `ReplaceOriginal` is chosen.
+    pub fn set_normalize_enrollment(&mut self, normalization: EnrollmentNormalization) {
+        self.enrollment_normalization = normalization;
+    }
+
+    /// Store reference features as int8 (see `QuantizedFeature`) and match them in integer
+    /// arithmetic. This cuts the database memory by 4x for very large databases, at the
+    /// price of slightly less precise scores. Applies to features loaded, imported or
+    /// enrolled afterwards.
+    pub fn set_quantize_features(&mut self, enabled: bool) {
+        self.quantize_features = enabled;
+    }
+
+    /// Store reference features in half precision with `FeaturePrecision::F16`, halving
+    /// the database memory (256 instead of 512 bytes per SFace feature). Matching widens
+    /// the values on the fly and scores move by about 1e-3, far below useful threshold
+    /// steps. Applies to features loaded, imported or enrolled afterwards; int8
+    /// quantization takes precedence.
+    pub fn set_feature_precision(&mut self, precision: FeaturePrecision) {
+        self.feature_precision = precision;
+    }
+
+    /// Fail `load_persons_db` with `UnexpectedFaceCount` as soon as an enrollment image
+    /// does not contain exactly one face, for curated datasets. The previous database stays
+    /// in place. Unreadable images are still only skipped and reported.
+    pub fn set_strict_single_face(&mut self, strict: bool) {
+        self.strict_single_face = strict;
+    }
+
+    /// What `run` and `run_detailed` do when called before any database was loaded, or
+    /// when the loaded database has no features. Defaults to a one-time warning; use
+    /// `Ignore` for detection-only setups.
+    pub fn set_missing_database_policy(&mut self, policy: MissingDatabasePolicy) {
+        self.missing_database_policy = policy;
+    }
+
+    /// What loading, importing, restoring and enrolling do with stored features that
+    /// cannot be matched: empty, with non-finite values, or of another size than the rest
+    /// of the database. They are checked once as they enter the database; by default they
+    /// are dropped with a warning and counted in `LoadReport::invalid_features`. While
+    /// matching, features of another size than the query are skipped, and a database in
+    /// which no feature fits the model fails with `DatabaseModelMismatch`.
+    pub fn set_invalid_feature_policy(&mut self, policy: InvalidFeaturePolicy) {
+        self.invalid_feature_policy = policy;
+    }
+
+    /// How match scores are mapped to `MatchResult::confidence`
+    pub fn set_confidence_mapping(&mut self, mapping: ConfidenceMapping) {
+        self.confidence_mapping = mapping;
+    }
+
+    /// Add a recognizer (e.g. a second embedding model) to an ensemble with the primary
+    /// recognizer. Every face is then embedded by all recognizers, each reference stores
+    /// one embedding per recognizer, and matching uses the weighted average of the
+    /// normalized per-recognizer scores (see `FusionWeight`) instead of the SFace score.
+    ///
+    /// Each added recognizer adds its own alignment and inference per face and its
+    /// embeddings to every stored reference, so loading, queries and database memory
+    /// grow roughly in proportion. Ensemble embeddings are not quantized; exports and
+    /// snapshots keep them, so restore or import them into an instance with the same
+    /// ensemble. References without them are scored by the recognizers they have. Reload
+    /// the database after changing the ensemble. Parallel matching is not
+    /// used while an ensemble is configured.
+    pub fn add_ensemble_recognizer(
+        &mut self,
+        recognizer: Box<dyn Recognizer>,
+        fusion: FusionWeight,
+    ) {
+        self.ensemble.push(EnsembleMember { recognizer, fusion });
+    }
+
+    /// Weight and normalization of the primary recognizer's score in an ensemble
+    pub fn set_primary_fusion_weight(&mut self, fusion: FusionWeight) {
+        self.primary_fusion = fusion;
+    }
+
+    /// Remove all recognizers added with `add_ensemble_recognizer`
+    pub fn clear_ensemble(&mut self) {
+        self.ensemble.clear();
+    }
+
+    /// Current activity counters
+    pub fn metrics(&self) -> Metrics {
+        Metrics {
+            frames_processed: self.counters.frames_processed.load(Ordering::Relaxed),
+            faces_detected: self.counters.faces_detected.load(Ordering::Relaxed),
+            matches_accepted: self.counters.matches_accepted.load(Ordering::Relaxed),
+            reloads: self.counters.reloads.load(Ordering::Relaxed),
+            faces_too_small: self.counters.faces_too_small.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Set all activity counters back to zero
+    pub fn reset_metrics(&self) {
+        self.counters.frames_processed.store(0, Ordering::Relaxed);
+        self.counters.faces_detected.store(0, Ordering::Relaxed);
+        self.counters.matches_accepted.store(0, Ordering::Relaxed);
+        self.counters.reloads.store(0, Ordering::Relaxed);
+        self.counters.faces_too_small.store(0, Ordering::Relaxed);
+    }
+
+    /// Report of the most recent database load
+    pub fn last_load_report(&self) -> &LoadReport {
+        &self.last_load_report
+    }
+
+    pub async fn db_load_status(&self) -> DbLoadStatus {
+        *self.db_load_status.read().await
+    }
+
+    pub async fn get_db_path(&self) -> Option<PathBuf> {
+        self.db_path.read().await.clone()
+    }
+
+    pub async fn set_db_path<P: AsRef<Path>>(&self, path: P) {
+        let mut db_status = self.db_load_status.write().await;
+        *db_status = DbLoadStatus::NotLoaded;
+        drop(db_status);
+
+        let mut db_path = self.db_path.write().await;
+        *db_path = Some(path.as_ref().to_path_buf());
+    }
+
+    pub async fn load_persons_db<P: AsRef<Path>>(
+        &mut self,
+        persondb_folder: P,
+        force: bool,
+        visualize: bool,
+    ) -> Result<LoadReport> {
+        self.load_persons_db_with_progress(persondb_folder, force, visualize, None, |_, _| {})
+            .await
+    }
+
+    /// Warm up, then load the database, reporting each stage through `progress`.
+    ///
+    /// Cancelling `cancel` aborts between persons with `FaceRecognitionError::Cancelled`
+    /// and leaves the database in the `NotLoaded` state.
+    pub async fn prepare<P, F>(
+        &mut self,
+        db_folder: P,
+        cancel: CancellationToken,
+        mut progress: F,
+    ) -> Result<()>
+    where
+        P: AsRef<Path>,
+        F: FnMut(PrepareStage),
+    {
+        if cancel.is_cancelled() {
+            return Err(FaceRecognitionError::Cancelled);
+        }
+        progress(PrepareStage::Warming);
+        self.warmup()?;
+
+        self.load_persons_db_with_progress(db_folder, true, false, Some(&cancel), |done, total| {
+            progress(PrepareStage::Loading(done, total))
+        })
+        .await?;
+
+        progress(PrepareStage::Ready);
+        Ok(())
+    }
+
+    /// Run one detection and one feature extraction on blank images so that the first
+    /// real frame does not pay the network initialization cost.
+    ///
+    /// Also checks that the recognizer produces embeddings of the configured dimension.
+    pub fn warmup(&mut self) -> Result<()> {
+        debug!("Warming up face detection and recognition models");
+        // Warm up at the configured operating resolution so the first frame needs no reshape
+        let size = self.detector_input_size.unwrap_or(Size::new(400, 400));
+        let frame =
+            Mat::new_rows_cols_with_default(size.height, size.width, CV_8UC3, Scalar::all(0.0))?;
+        self.detector.set_input_size(size)?;
+        self.detector_input_size = Some(size);
+        let mut faces = Mat::default();
+        self.detector.detect(&frame, &mut faces)?;
+
+        let aligned = Mat::new_rows_cols_with_default(
+            RECOGNIZER_INPUT_SIZE.height,
+            RECOGNIZER_INPUT_SIZE.width,
+            CV_8UC3,
+            Scalar::all(0.0),
+        )?;
+        let mut feature = Mat::default();
+        self.face_recognizer.feature(&aligned, &mut feature)?;
+
+        let actual = feature.total() as i32;
+        if actual != self.recognizer_config.expected_feature_dim {
+            error!(
+                "Recognizer produced {}-dimensional features, expected {}",
+                actual, self.recognizer_config.expected_feature_dim
+            );
+            return Err(FaceRecognitionError::FeatureDimensionMismatch {
+                expected: self.recognizer_config.expected_feature_dim,
+                actual,
+            });
+        }
+        Ok(())
+    }
+
+    /// Embedding dimension this instance expects from the recognizer
+    pub fn feature_dim(&self) -> i32 {
+        self.recognizer_config.expected_feature_dim
+    }
+
+    /// Load the database, calling `progress(done, total)` after each person folder
+    async fn load_persons_db_with_progress<P, F>(
+        &mut self,
+        persondb_folder: P,
+        force: bool,
+        visualize: bool,
+        cancel: Option<&CancellationToken>,
+        mut progress: F,
+    ) -> Result<LoadReport>
+    where
+        P: AsRef<Path>,
+        F: FnMut(usize, usize),
+    {
+        let path = persondb_folder.as_ref().to_path_buf();
+
+        // Check if we need to load
+        let current_path = self.db_path.read().await.clone();
+        let current_status = *self.db_load_status.read().await;
+
+        if current_path.is_none() || current_path.as_ref() != Some(&path) {
+            info!("Loading persons database from: {}", path.display());
+            let mut db_status = self.db_load_status.write().await;
+            *db_status = DbLoadStatus::NotLoaded;
+            drop(db_status);
+
+            let mut db_path = self.db_path.write().await;
+            *db_path = Some(path.clone());
+        } else if current_status == DbLoadStatus::Loaded && !force {
+            debug!("PersonsDB already loaded, skipping");
+            return Ok(self.last_load_report.clone());
+        }
+
+        // Set loading status
+        let mut db_status = self.db_load_status.write().await;
+        *db_status = DbLoadStatus::Loading;
+        drop(db_status);
+
+        info!("Loading persons database from: {}", path.display());
+
+        // Build the new database off to the side and swap it in at the end, so readers
+        // only ever see the previous or the new complete map
+        let (new_features, report) = match self
+            .read_persons_folders(&path, visualize, cancel, &mut progress)
+            .await
+        {
+            Ok(loaded) => loaded,
+            Err(e) => return Err(self.fail_load(e).await),
+        };
+
+        // Swap in the new database under a brief write lock
+        self.features_map.write().await.replace_all(new_features);
+
+        // Set loaded status
+        let mut db_status = self.db_load_status.write().await;
+        *db_status = DbLoadStatus::Loaded;
+
+        if report.features_evicted > 0 || !report.skipped_over_cap.is_empty() {
+            warn!(
+                "Feature cap of {} reached, {} features dropped, {} images not read",
+                self.max_total_features,
+                report.features_evicted,
+                report.skipped_over_cap.len()
+            );
+        }
+        info!(
+            "Database loading completed: {} persons, {} features, {} rejected faces, {} failed images",
+            report.persons_loaded,
+            report.features_loaded,
+            report.rejected_faces.len(),
+            report.failed_images.len()
+        );
+        self.last_load_report = report.clone();
+        Ok(report)
+    }
+
+    /// Mark the database load that failed with `error` as not loaded (cancelled) or
+    /// failed and hand the error back. The previous database stays in place.
+    async fn fail_load(&self, error: FaceRecognitionError) -> FaceRecognitionError {
+        *self.db_load_status.write().await = match error {
+            FaceRecognitionError::Cancelled => DbLoadStatus::NotLoaded,
+            _ => DbLoadStatus::Failed,
+        };
+        error
+    }
+
+    /// Fail with `InvalidStoredFeature` for the first of the `invalid` features under
+    /// `InvalidFeaturePolicy::Error`, otherwise report them all in a single warning
+    fn check_invalid_features(&self, invalid: &[(String, usize, String)]) -> Result<()> {
+        let Some((person, _, reason)) = invalid.first() else {
+            return Ok(());
+        };
+        if self.invalid_feature_policy == InvalidFeaturePolicy::Error {
+            return Err(FaceRecognitionError::InvalidStoredFeature {
+                person: person.clone(),
+                reason: reason.clone(),
+            });
+        }
+        warn!(
+            "Dropping {} invalid stored features (e.g. of {}: {})",
+            invalid.len(),
+            person,
+            reason
+        );
+        Ok(())
+    }
+
+    /// Check `features` before they enter the database and drop those that cannot be
+    /// matched, see `set_invalid_feature_policy`. Returns the number dropped.
+    fn drop_invalid_features(
+        &self,
+        features: &mut HashMap<String, Vec<StoredFeature>>,
+    ) -> Result<usize> {
+        let invalid = find_invalid_features(
+            features
+                .iter()
+                .map(|(person, features)| (person.as_str(), features.as_slice())),
+        );
+        self.check_invalid_features(&invalid)?;
+        remove_features(features, &invalid);
+        Ok(invalid.len())
+    }
+
+    /// Read the person folders below `path` into a new feature map, without touching the
+    /// current database
+    async fn read_persons_folders<F>(
+        &mut self,
+        path: &Path,
+        visualize: bool,
+        cancel: Option<&CancellationToken>,
+        progress: &mut F,
+    ) -> Result<(HashMap<String, Vec<StoredFeature>>, LoadReport)>
+    where
+        F: FnMut(usize, usize),
+    {
+        let mut new_features: HashMap<String, Vec<StoredFeature>> = HashMap::new();
+
+        let mut report = LoadReport::default();
+        let mut sequence: u64 = 0;
+        // Features kept so far, to stop reading images once a `RejectNew` cap is full
+        let mut stored_total = 0;
+
+        // Normalized name key -> name the features are stored under
+        let mut seen_names: HashMap<String, String> = HashMap::new();
+
+        let mut candidates = Vec::new();
+        for entry in std::fs::read_dir(&path)? {
+            let person_path = entry?.path();
+            if person_path.is_dir() {
+                candidates.push(person_path);
+            }
+        }
+        // `is_dir` follows symlinks: skip links back into the database folder or above it
+        // and second links to the same folder, keeping real folders over links
+        candidates.sort_by_key(|dir| (dir.is_symlink(), dir.clone()));
+        let root = path.canonicalize()?;
+        let mut visited = HashSet::new();
+        let mut person_dirs = Vec::with_capacity(candidates.len());
+        for person_path in candidates {
+            let real_path = person_path.canonicalize()?;
+            if root.starts_with(&real_path) || !visited.insert(real_path) {
+                warn!(
+                    "Skipping person folder {}: it resolves to the database folder, a parent \
+                     of it or a folder already loaded",
+                    person_path.display()
+                );
+                report.skipped_dirs.push(person_path);
+                continue;
+            }
+            person_dirs.push(person_path);
+        }
+        // Directory listing order is platform dependent; sort so name collisions, feature
+        // sequence numbers and thus feature cap eviction are reproducible
+        person_dirs.sort();
+        let person_dirs = filter_persons(
+            person_dirs,
+            self.person_filter.as_deref(),
+            |dir| {
+                dir.file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("unknown")
+                    .to_string()
+            },
+            &mut report,
+        );
+        let total = person_dirs.len();
+        progress(0, total);
+
+        // Iterate over directories
+        for (person_idx, person_path) in person_dirs.into_iter().enumerate() {
+            if cancel.is_some_and(|token| token.is_cancelled()) {
+                info!("Database loading cancelled");
+                return Err(FaceRecognitionError::Cancelled);
+            }
+
+            let person_name = normalize_person_name(
+                person_path
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("unknown"),
+            );
+
+            let existing_name = seen_names.get(&person_name_key(&person_name)).cloned();
+            if let Some(existing) = &existing_name {
+                warn!(
+                    "Person folder {} collides with already loaded person {}",
+                    person_path.display(),
+                    existing
+                );
+                if self.name_collision_policy == NameCollisionPolicy::Error {
+                    return Err(FaceRecognitionError::NameCollision(format!(
+                        "{} collides with {}",
+                        person_path.display(),
+                        existing
+                    )));
+                }
+            }
+
+            debug!("Loading person: {}", person_name);
+            let mut person_features = Vec::new();
+
+            // Load images from person directory
+            let mut img_paths = std::fs::read_dir(&person_path)?
+                .map(|entry| entry.map(|e| e.path()))
+                .collect::<std::io::Result<Vec<_>>>()?;
+            img_paths.sort();
+            for img_path in img_paths {
+                if !img_path.is_dir() {
+                    // Skip visualize files, weight sidecars and normalized crops written
+                    // next to originals
+                    if self.visualize_naming.is_output(&img_path)
+                        || img_path.extension().and_then(|e| e.to_str())
+                            == Some(WEIGHT_SIDECAR_EXTENSION)
+                        || self.enrollment_normalization.is_output(&img_path)
+                    {
+                        continue;
+                    }
+
+                    if self.reject_new_capacity(stored_total) == Some(0) {
+                        report.skipped_over_cap.push(img_path);
+                        continue;
+                    }
+                    let mut features = self
+                        .load_enrollment_image(
+                            &person_name,
+                            &img_path,
+                            visualize,
+                            &mut report,
+                            &mut sequence,
+                        )
+                        .await?;
+                    report.features_evicted += self.reject_over_cap(&mut features, stored_total);
+                    stored_total += features.len();
+                    person_features.extend(features);
+                }
+            }
+
+            if person_features.is_empty() {
+                warn!("No usable faces found for person {}", person_name);
+                report.empty_persons.push(person_name.clone());
+            }
+
+            // Store features for this person, merging into the first name on collision
+            match existing_name {
+                Some(existing) => {
+                    new_features
+                        .entry(existing)
+                        .or_default()
+                        .extend(person_features);
+                }
+                None => {
+                    report.persons_loaded += 1;
+                    seen_names.insert(person_name_key(&person_name), person_name.clone());
+                    new_features.insert(person_name, person_features);
+                }
+            }
+            if self.max_total_features > 0 {
+                report.features_evicted += enforce_feature_cap(
+                    &mut new_features,
+                    self.max_total_features,
+                    self.feature_cap_policy,
+                );
+            }
+
+            progress(person_idx + 1, total);
+        }
+
+        report.invalid_features = self.drop_invalid_features(&mut new_features)?;
+        // Counted at the end, after eviction by the feature cap
+        report.features_loaded = new_features.values().map(Vec::len).sum();
+        if self.detect_cross_person_duplicates {
+            report.cross_person_duplicates = find_cross_person_duplicates(&new_features)?;
+        }
+        compact_features(
+            &mut new_features,
+            self.quantize_features,
+            self.feature_precision,
+        )?;
+        Ok((new_features, report))
+    }
+
+    /// Read one enrollment image of `person_name` and return the features to store.
+    /// Unreadable images and rejected faces are recorded in `report`; only the strict
+    /// single-face check fails the whole load.
+    async fn load_enrollment_image(
+        &mut self,
+        person_name: &str,
+        img_path: &Path,
+        visualize: bool,
+        report: &mut LoadReport,
+        sequence: &mut u64,
+    ) -> Result<Vec<StoredFeature>> {
+        debug!(
+            "Loading image: {} for person {}",
+            img_path.display(),
+            person_name
+        );
+
+        // A corrupt file must not abort the whole load: any read or detection error only
+        // skips this image
+        let img = match img_path.to_str().map(|p| imread(p, IMREAD_COLOR)) {
+            Some(Ok(img)) if !img.empty() => img,
+            Some(Err(e)) => {
+                error!("Cannot read image {}: {}", img_path.display(), e);
+                report.failed_images.push(img_path.to_path_buf());
+                return Ok(Vec::new());
+            }
+            _ => {
+                error!("Cannot read image: {}", img_path.display());
+                report.failed_images.push(img_path.to_path_buf());
+                return Ok(Vec::new());
+            }
+        };
+
+        report.images_processed += 1;
+
+        // Extract features from all detected faces
+        let detected_faces = match self.extract_enrollment_features(img.clone()).await {
+            Ok(faces) => faces,
+            Err(e) => {
+                error!("Cannot process image {}: {}", img_path.display(), e);
+                report.failed_images.push(img_path.to_path_buf());
+                return Ok(Vec::new());
+            }
+        };
+        if self.strict_single_face && detected_faces.len() != 1 {
+            error!(
+                "Strict single-face enrollment: {} faces in {}",
+                detected_faces.len(),
+                img_path.display()
+            );
+            return Err(FaceRecognitionError::UnexpectedFaceCount {
+                path: img_path.to_path_buf(),
+                count: detected_faces.len(),
+            });
+        }
+        self.normalize_enrollment_image(img_path, &img, &detected_faces);
+        let weight = read_weight_sidecar(img_path);
+        let captured_at = self
+            .track_capture_time
+            .then(|| capture_time(img_path))
+            .flatten();
+        // Boxes for the visualization, taken before the faces are consumed so the image
+        // is not detected a second time
+        let visualize_boxes: Vec<Rect2i> = if visualize {
+            let size = img.size()?;
+            detected_faces
+                .iter()
+                .filter_map(|face| face.bbox_scaled(size).ok())
+                .collect()
+        } else {
+            Vec::new()
+        };
+        let mut features = Vec::new();
+        for detected_face in detected_faces {
+            // Unreadable landmarks or score only lose this face; the distance is only
+            // computed when the minimum is enabled
+            let checked = detected_face.score().and_then(|quality| {
+                let distance = if self.min_enroll_eye_distance > 0.0 {
+                    detected_face.inter_ocular_distance()?
+                } else {
+                    f32::INFINITY
+                };
+                Ok((quality, distance))
+            });
+            let (quality, distance) = match checked {
+                Ok(checked) => checked,
+                Err(e) => {
+                    warn!("Skipping face in {}: {}", img_path.display(), e);
+                    report.rejected_faces.push(RejectedFace {
+                        person: person_name.to_string(),
+                        image: img_path.to_path_buf(),
+                        reason: format!("unreadable detection: {e}"),
+                    });
+                    continue;
+                }
+            };
+            if distance < self.min_enroll_eye_distance {
+                warn!(
+                    "Rejecting face in {}: inter-ocular distance {:.1}px below {:.1}px",
+                    img_path.display(),
+                    distance,
+                    self.min_enroll_eye_distance
+                );
+                report.rejected_faces.push(RejectedFace {
+                    person: person_name.to_string(),
+                    image: img_path.to_path_buf(),
+                    reason: format!("inter-ocular distance {distance:.1}px"),
+                });
+                continue;
+            }
+            let stored = StoredFeature {
+                quality,
+                feature: detected_face.feature,
+                source: Some(img_path.to_path_buf()),
+                sequence: *sequence,
+                quantized: None,
+                half: None,
+                ensemble_features: detected_face.ensemble_features,
+                weight,
+                captured_at,
+                mirrored: false,
+            };
+            *sequence += 1;
+            let mirrored = detected_face
+                .flipped_feature
+                .map(|flipped| mirrored_reference(&stored, flipped, *sequence));
+            features.push(stored);
+            if let Some(mirrored) = mirrored {
+                features.push(mirrored);
+                *sequence += 1;
+            }
+        }
+
+        // Create visualized version if requested; failing to write it keeps the features
+        if visualize {
+            if let Err(e) =
+                self.write_enrollment_visualization(img_path, img, &visualize_boxes, person_name)
+            {
+                warn!(
+                    "Cannot write visualization of {}: {}",
+                    img_path.display(),
+                    e
+                );
+            }
+        }
+
+        Ok(features)
+    }
+
+    /// Draw `boxes` labeled with `person_name` onto `img` and write it next to `img_path`
+    /// according to the visualize naming
+    fn write_enrollment_visualization(
+        &self,
+        img_path: &Path,
+        mut img: Mat,
+        boxes: &[Rect2i],
+        person_name: &str,
+    ) -> Result<()> {
+        let visualize_path = self.visualize_naming.output_path(img_path);
+        if let Some(parent) = visualize_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        for &bbox in boxes {
+            self.visualize_face(&mut img, bbox, Some(person_name))?;
+        }
+
+        let path = visualize_path.to_str().ok_or_else(|| {
+            FaceRecognitionError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                format!("non UTF-8 path {}", visualize_path.display()),
+            ))
+        })?;
+        if !imwrite(path, &img, &opencv::core::Vector::new())? {
+            return Err(FaceRecognitionError::Io(std::io::Error::other(format!(
+                "OpenCV could not write {}",
+                visualize_path.display()
+            ))));
+        }
+        Ok(())
+    }
+
+    pub async fn start_watching(&self, _check_interval_seconds: u64) -> Result<()> {
+        let db_path = {
+            let path_guard = self.db_path.read().await;
+            path_guard
+                .clone()
+                .ok_or(FaceRecognitionError::DatabaseNotLoaded)?
+        };
+
+        if self
+            .watcher_running
+            .load(std::sync::atomic::Ordering::Relaxed)
+        {
+            debug!("Watcher already running");
+            return Ok(());
+        }
+
+        // Update last modification time
+        let latest_mod_time = get_latest_mod_time(&db_path)?;
+        let mut last_mod = self.last_mod_time.write().await;
+        *last_mod = latest_mod_time;
+        drop(last_mod);
+
+        // Start file watcher
+        let mut watcher_guard = self.watcher.lock().unwrap();
+        let mut watcher = FolderWatcher::new()?;
+        watcher.start_watching(&db_path)?;
+
+        // Changes mark the database as stale; the reload itself needs models and happens
+        // on the background loader if there is one, otherwise on the next `run` (or
+        // `reload_if_changed`)
+        let reload_pending = Arc::clone(&self.reload_pending);
+        let wake = self.start_background_reloads();
+        let watch_task = watcher.spawn_change_loop(Arc::clone(&self.watch_paused), move || {
+            reload_pending.store(true, std::sync::atomic::Ordering::Relaxed);
+            if let Some(wake) = &wake {
+                wake.notify_one();
+            }
+        })?;
+        *self.watch_task.lock().unwrap() = Some(watch_task);
+
+        // Store watcher before moving it
+        *watcher_guard = Some(watcher);
+        drop(watcher_guard);
+        self.watcher_running
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+
+        info!("Started watching database folder: {}", db_path.display());
+        Ok(())
+    }
+
+    /// Stop the watcher and wait (up to `WATCH_TASK_JOIN_TIMEOUT`) for its blocking event
+    /// loop to finish, so runtime shutdown does not hang on it.
+    pub async fn stop_watching(&self) {
+        if let Some(mut watcher) = self.watcher.lock().unwrap().take() {
+            watcher.stop_watching();
+        }
+
+        // Dropping the watcher disconnects the event channel, which ends the loop within
+        // one receive timeout
+        let watch_task = self.watch_task.lock().unwrap().take();
+        if let Some(watch_task) = watch_task {
+            match tokio::time::timeout(WATCH_TASK_JOIN_TIMEOUT, watch_task).await {
+                Ok(Ok(())) => debug!("Watcher task finished"),
+                Ok(Err(e)) => error!("Watcher task failed: {}", e),
+                Err(_) => warn!(
+                    "Watcher task did not finish within {:?}",
+                    WATCH_TASK_JOIN_TIMEOUT
+                ),
+            }
+        }
+
+        self.watcher_running
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        self.stop_background_reloads().await;
+        info!("Stopped watching database folder");
+    }
+
+    /// Run watcher-triggered reloads on `loader`, a second instance with its own models,
+    /// in a background task instead of inside the next `run`. Frames keep being matched
+    /// against the previous database until the new one is swapped in.
+    ///
+    /// `loader` takes over this instance's database and should be configured like it,
+    /// since its settings decide how the folder is read. Its load reports and reload
+    /// metrics stay on `loader`; `subscribe_reloads` still reports each reload. Set it
+    /// before `start_watching`.
+    pub fn set_background_loader(&mut self, mut loader: FaceRecognition) {
+        loader.share_database(self);
+        *self.background_loader.lock().unwrap() = Some(BackgroundLoader::Idle(Box::new(loader)));
+    }
+
+    /// Start the background reload task if a loader is set, returning what wakes it
+    fn start_background_reloads(&self) -> Option<Arc<Notify>> {
+        let mut slot = self.background_loader.lock().unwrap();
+        match slot.take() {
+            None => None,
+            Some(BackgroundLoader::Running { wake, task }) => {
+                *slot = Some(BackgroundLoader::Running {
+                    wake: Arc::clone(&wake),
+                    task,
+                });
+                Some(wake)
+            }
+            Some(BackgroundLoader::Idle(mut loader)) => {
+                let wake = Arc::new(Notify::new());
+                let task_wake = Arc::clone(&wake);
+                let running = Arc::clone(&self.watcher_running);
+                let task = tokio::spawn(async move {
+                    loop {
+                        task_wake.notified().await;
+                        if !running.load(Ordering::Relaxed) {
+                            break;
+                        }
+                        if let Err(e) = loader.reload_if_changed().await {
+                            error!(
+                                "Background database reload failed, keeping previous database: {}",
+                                e
+                            );
+                        }
+                    }
+                    loader
+                });
+                *slot = Some(BackgroundLoader::Running {
+                    wake: Arc::clone(&wake),
+                    task,
+                });
+                Some(wake)
+            }
+        }
+    }
+
+    /// End the background reload task, letting a reload in progress finish (up to
+    /// `WATCH_TASK_JOIN_TIMEOUT`), and keep the loader for the next `start_watching`
+    async fn stop_background_reloads(&self) {
+        let (wake, task) = {
+            let mut slot = self.background_loader.lock().unwrap();
+            match slot.take() {
+                Some(BackgroundLoader::Running { wake, task }) => (wake, task),
+                idle => {
+                    *slot = idle;
+                    return;
+                }
+            }
+        };
+        wake.notify_one();
+        match tokio::time::timeout(WATCH_TASK_JOIN_TIMEOUT, task).await {
+            Ok(Ok(loader)) => {
+                *self.background_loader.lock().unwrap() = Some(BackgroundLoader::Idle(loader));
+            }
+            Ok(Err(e)) => error!("Background reload task failed: {}", e),
+            Err(_) => warn!(
+                "Background reload did not finish within {:?}",
+                WATCH_TASK_JOIN_TIMEOUT
+            ),
+        }
+    }
+
+    /// Stop reacting to database changes, e.g. during bulk edits. Changes made while
+    /// paused trigger a single reload after `resume_watching`.
+    pub fn pause_watching(&self) {
+        self.watch_paused
+            .store(true, std::sync::atomic::Ordering::Relaxed);
+        info!("Paused watching database folder");
+    }
+
+    pub fn resume_watching(&self) {
+        self.watch_paused
+            .store(false, std::sync::atomic::Ordering::Relaxed);
+        info!("Resumed watching database folder");
+    }
+
+    /// Reload the database if the watcher saw changes since the last load.
+    /// Returns whether a reload happened; always `false` while a background loader (see
+    /// `set_background_loader`) takes care of reloads.
+    pub async fn reload_if_changed(&mut self) -> Result<bool> {
+        if matches!(
+            *self.background_loader.lock().unwrap(),
+            Some(BackgroundLoader::Running { .. })
+        ) {
+            return Ok(false);
+        }
+        if !self
+            .reload_pending
+            .swap(false, std::sync::atomic::Ordering::Relaxed)
+        {
+            return Ok(false);
+        }
+        let Some(path) = self.get_db_path().await else {
+            return Ok(false);
+        };
+
+        info!("Database folder changed, reloading...");
+        let previous: HashSet<String> = self.list_persons().await.into_iter().collect();
+        self.load_persons_db(path, true, false).await?;
+        self.counters.reloads.fetch_add(1, Ordering::Relaxed);
+
+        let current = self.list_persons().await;
+        let mut persons_removed: Vec<String> = previous
+            .iter()
+            .filter(|name| !current.contains(name))
+            .cloned()
+            .collect();
+        persons_removed.sort();
+        let event = ReloadEvent {
+            at: SystemTime::now(),
+            persons_added: current
+                .iter()
+                .filter(|name| !previous.contains(*name))
+                .cloned()
+                .collect(),
+            persons_removed,
+            persons: current.len(),
+        };
+        // Sending only fails without subscribers
+        let _ = self.reload_events.send(event);
+        Ok(true)
+    }
+
+    /// Receive a `ReloadEvent` after every completed watcher-triggered reload (see
+    /// `start_watching` and `reload_if_changed`), e.g. to invalidate caches. Explicit
+    /// `load_persons_db` calls are not reported. A receiver that falls more than 16 events
+    /// behind gets `RecvError::Lagged` and then continues with the latest events.
+    pub fn subscribe_reloads(&self) -> broadcast::Receiver<ReloadEvent> {
+        self.reload_events.subscribe()
+    }
+
+    /// `extract_features` for an enrollment image, adding the flip augmentation if enabled
+    async fn extract_enrollment_features(&mut self, frame: Mat) -> Result<Vec<DetectedFace>> {
+        self.enrolling = true;
+        let faces = self.extract_features(frame).await;
+        self.enrolling = false;
+        faces
+    }
+
+    async fn extract_features(&mut self, frame: Mat) -> Result<Vec<DetectedFace>> {
+        let faces = self.detect_and_embed(frame)?;
+        self.finish_extraction(faces)
+    }
+
+    /// Order freshly extracted faces by the face sort setting and count them in the metrics
+    fn finish_extraction(&self, mut faces: Vec<DetectedFace>) -> Result<Vec<DetectedFace>> {
+        sort_detected_faces(&mut faces, self.face_sort)?;
+        self.counters
+            .frames_processed
+            .fetch_add(1, Ordering::Relaxed);
+        self.counters
+            .faces_detected
+            .fetch_add(faces.len() as u64, Ordering::Relaxed);
+        Ok(faces)
+    }
+
+    fn detect_and_embed(&mut self, mut frame: Mat) -> Result<Vec<DetectedFace>> {
+        if frame.empty() {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+
+        if let Some(tiling) = self.tiling {
+            if frame.cols() > tiling.tile_size || frame.rows() > tiling.tile_size {
+                return self.extract_features_tiled(frame, tiling);
+            }
+        }
+
+        let original_size = frame.size()?;
+        let full_resolution = if self.full_resolution_crops
+            && self.detection_size_for(original_size) != original_size
+        {
+            let mut full = frame.try_clone()?;
+            self.apply_preprocess(&mut full)?;
+            Some(full)
+        } else {
+            None
+        };
+        // Unprocessed frame for the retry at up to the detection size ceiling, unless the
+        // full-resolution crops provide one
+        let retry_size = self.fullres_retry_size(original_size);
+        let retry_frame = if self.fullres_retry
+            && full_resolution.is_none()
+            && self.detection_size_for(original_size) != retry_size
+        {
+            Some(resized_to(&frame, retry_size)?)
+        } else {
+            None
+        };
+        self.resize_frame(&mut frame, true)?;
+        self.apply_preprocess(&mut frame)?;
+
+        let mut detected_faces = Vec::new();
+        self.detect_resized(&frame, original_size, full_resolution.as_ref(), |face| {
+            detected_faces.push(face);
+            Ok(())
+        })?;
+
+        if detected_faces.is_empty() && self.fullres_retry {
+            let retry = match (full_resolution, retry_frame) {
+                (Some(full), _) if self.detection_size_for(original_size) != retry_size => {
+                    Some(resized_to(&full, retry_size)?)
+                }
+                (Some(_), _) => None,
+                (None, Some(mut original)) => {
+                    self.apply_preprocess(&mut original)?;
+                    Some(original)
+                }
+                (None, None) => None,
+            };
+            if let Some(retry) = retry {
+                debug!(
+                    "No face at {}x{}, retrying at full resolution",
+                    frame.cols(),
+                    frame.rows()
+                );
+                // Detections are in `retry` coordinates, which `detection_size` records
+                self.detect_resized(&retry, original_size, None, |face| {
+                    detected_faces.push(face);
+                    Ok(())
+                })?;
+            }
+        }
+
+        if let Some(iou_threshold) = self.post_nms_iou {
+            detected_faces = suppress_overlapping_faces(detected_faces, iou_threshold)?;
+        }
+
+        Ok(detected_faces)
+    }
+
+    /// Detect on overlapping native-resolution tiles, map the faces back to the full frame
+    /// and merge duplicates found in more than one tile
+    fn extract_features_tiled(
+        &mut self,
+        mut frame: Mat,
+        tiling: TileConfig,
+    ) -> Result<Vec<DetectedFace>> {
+        let original_size = frame.size()?;
+        self.apply_preprocess(&mut frame)?;
+
+        let tile_size = tiling.tile_size.max(1);
+        let step = (tile_size - tiling.overlap).max(1);
+        let starts = |length: i32| -> Vec<i32> {
+            let mut starts: Vec<i32> = (0..(length - tile_size).max(0))
+                .step_by(step as usize)
+                .collect();
+            starts.push((length - tile_size).max(0));
+            starts
+        };
+
+        let mut detected_faces = Vec::new();
+        for y in starts(original_size.height) {
+            for x in starts(original_size.width) {
+                let tile_rect = Rect2i::new(
+                    x,
+                    y,
+                    tile_size.min(original_size.width),
+                    tile_size.min(original_size.height),
+                );
+                let tile = Mat::roi(&frame, tile_rect)?.try_clone()?;
+                let tile_size = tile.size()?;
+                self.detect_resized(&tile, tile_size, None, |face| {
+                    // Shift box and landmarks from tile to frame coordinates
+                    let mut row = face.face_detect;
+                    for col in (0..14).filter(|&c| c != 2 && c != 3) {
+                        let offset = if col % 2 == 0 { x } else { y };
+                        *row.at_2d_mut::<f32>(0, col)? += offset as f32;
+                    }
+                    let mut shifted =
+                        DetectedFace::new(face.name, row, face.feature, original_size);
+                    shifted.ensemble_features = face.ensemble_features;
+                    shifted.flipped_feature = face.flipped_feature;
+                    shifted.aligned = face.aligned;
+                    detected_faces.push(shifted);
+                    Ok(())
+                })?;
+            }
+        }
+
+        debug!(
+            "Tiled detection found {} faces before merging",
+            detected_faces.len()
+        );
+        suppress_overlapping_faces(detected_faces, tiling.merge_iou)
+    }
+
+    /// Detect and embed faces and call `f` with a borrow of each one as soon as it is ready.
+    ///
+    /// Each face costs as much as in `run`: it gets its own copy of the detection row, an
+    /// aligned crop and an embedding. What this saves is the full-size copy of the input
+    /// frame (unless it is downscaled or preprocessed) and collecting the faces into a
+    /// `Vec`. It also skips steps of `run`: faces come in detector order regardless of
+    /// `set_face_sort`, overlapping detections are not suppressed (`set_post_nms_iou`),
+    /// tiling, full-resolution crops and the full-resolution retry are not applied, no
+    /// metrics are recorded and faces are not matched.
+    pub async fn for_each_detected_face<F>(&mut self, frame: &Mat, mut f: F) -> Result<()>
+    where
+        F: FnMut(&DetectedFace),
+    {
+        if frame.empty() {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+
+        let original_size = frame.size()?;
+        let mut resized = Mat::default();
+        let detection_frame = self.detection_frame(frame, &mut resized)?;
+
+        self.detect_resized(detection_frame, original_size, None, |face| {
+            f(&face);
+            Ok(())
+        })
+    }
+
+    /// Run YuNet on `frame` and return its unprocessed output.
+    ///
+    /// The frame is downscaled to `max_size` first, exactly as for `run`. The result has one
+    /// row per face and 15 `f32` columns, in the coordinates of that downscaled frame:
+    ///
+    /// | columns | content |
+    /// |---------|---------|
+    /// | 0-3     | bounding box `x, y, width, height` |
+    /// | 4-5     | right eye `x, y` |
+    /// | 6-7     | left eye `x, y` |
+    /// | 8-9     | nose tip `x, y` |
+    /// | 10-11   | right mouth corner `x, y` |
+    /// | 12-13   | left mouth corner `x, y` |
+    /// | 14      | detection score |
+    pub fn detect_raw(&mut self, frame: &Mat) -> Result<Mat> {
+        if frame.empty() {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+
+        let mut resized = Mat::default();
+        let detection_frame = self.detection_frame(frame, &mut resized)?;
+        self.detect_on(detection_frame)
+    }
+
+    /// Aligned crops of all faces in `frame`, in detector order, as the recognizer sees
+    /// them (`align_crop`, 112x112 for SFace) but taken from the full-resolution frame.
+    /// With `size`, each crop is scaled to that size; the alignment is unchanged, so
+    /// crops of different sizes only differ in resolution. Useful to build datasets for
+    /// other models.
+    pub fn extract_aligned_faces(&mut self, frame: &Mat, size: Option<Size>) -> Result<Vec<Mat>> {
+        let faces = self.detect_raw(frame)?;
+        let frame_size = frame.size()?;
+        let detection_size = self.detection_size_for(frame_size);
+        let mut crops = Vec::with_capacity(faces.rows().max(0) as usize);
+        for i in 0..faces.rows() {
+            let row = scale_face_row(&faces.row(i)?, detection_size, frame_size)?;
+            let mut aligned = Mat::default();
+            self.face_recognizer.align_crop(frame, &row, &mut aligned)?;
+            match size {
+                Some(size) if size != aligned.size()? => {
+                    let interpolation = if size.width < aligned.cols() {
+                        opencv::imgproc::INTER_AREA
+                    } else {
+                        opencv::imgproc::INTER_CUBIC
+                    };
+                    let mut resized = Mat::default();
+                    opencv::imgproc::resize(&aligned, &mut resized, size, 0.0, 0.0, interpolation)?;
+                    crops.push(resized);
+                }
+                _ => crops.push(aligned),
+            }
+        }
+        Ok(crops)
+    }
+
+    /// Write the crops of `extract_aligned_faces` to `out_dir` as `<prefix>_<index>.png`
+    /// and return their paths. The directory is created if needed.
+    pub fn save_aligned_faces<P: AsRef<Path>>(
+        &mut self,
+        frame: &Mat,
+        out_dir: P,
+        prefix: &str,
+        size: Option<Size>,
+    ) -> Result<Vec<PathBuf>> {
+        let out_dir = out_dir.as_ref();
+        std::fs::create_dir_all(out_dir)?;
+        let mut paths = Vec::new();
+        for (i, crop) in self.extract_aligned_faces(frame, size)?.iter().enumerate() {
+            let path = out_dir.join(format!("{prefix}_{i}.png"));
+            let path_str = path.to_str().ok_or(FaceRecognitionError::InvalidImage)?;
+            if !imwrite(path_str, crop, &opencv::core::Vector::new())? {
+                return Err(FaceRecognitionError::Io(std::io::Error::other(format!(
+                    "failed to write {}",
+                    path.display()
+                ))));
+            }
+            paths.push(path);
+        }
+        Ok(paths)
+    }
+
+    /// The best aligned crop of every face track in `video`, e.g. to enroll people from a
+    /// short clip. Faces are followed from frame to frame by box overlap (a track ends
+    /// after 10 frames without its face) and each track keeps the crop with the highest
+    /// `shot_quality`: detection score, frontality and size. Returns
+    /// `(track, crop, quality)` in order of first appearance; tracks are not identities,
+    /// so a person leaving and re-entering the picture gets a new track. Faces below the
+    /// minimum probe eye distance are not tracked, and a face whose quality or crop cannot
+    /// be computed is logged and skipped. Ended tracks are set aside, so only the tracks
+    /// currently in the picture are matched against.
+    pub async fn best_shots(&mut self, video: &Path) -> Result<Vec<(TrackId, Mat, f32)>> {
+        struct Track {
+            id: TrackId,
+            bbox: Rect2i,
+            last_seen: usize,
+            crop: Mat,
+            quality: f32,
+        }
+
+        let video_str = video.to_str().ok_or_else(|| {
+            FaceRecognitionError::Video(format!("invalid path {}", video.display()))
+        })?;
+        let mut capture = VideoCapture::from_file(video_str, CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(FaceRecognitionError::Video(format!(
+                "cannot open input video {}",
+                video.display()
+            )));
+        }
+
+        let mut tracks: Vec<Track> = Vec::new();
+        let mut finished: Vec<Track> = Vec::new();
+        let mut next_id: TrackId = 0;
+        let mut frame = Mat::default();
+        let mut frame_index = 0;
+        while capture.read(&mut frame)? {
+            if frame.empty() {
+                break;
+            }
+            // Let other tasks run between frames of a long video
+            tokio::task::yield_now().await;
+
+            let (ended, active): (Vec<Track>, Vec<Track>) = std::mem::take(&mut tracks)
+                .into_iter()
+                .partition(|track| frame_index - track.last_seen > TRACK_MAX_MISSED_FRAMES);
+            tracks = active;
+            finished.extend(ended);
+
+            let frame_size = frame.size()?;
+            let detection_size = self.detection_size_for(frame_size);
+            let rows = self.detect_raw(&frame)?;
+
+            let mut detections = Vec::with_capacity(rows.rows().max(0) as usize);
+            for i in 0..rows.rows() {
+                let row = scale_face_row(&rows.row(i)?, detection_size, frame_size)?;
+                let face = DetectedFace::new(String::new(), row, Mat::default(), frame_size);
+                if self.probe_too_small(&face)? {
+                    continue;
+                }
+                match shot_quality(&face) {
+                    Ok(quality) => detections.push((face.bbox()?, quality, face)),
+                    Err(e) => warn!(
+                        "Skipping face in frame {} of {}: {}",
+                        frame_index,
+                        video.display(),
+                        e
+                    ),
+                }
+            }
+
+            // Greedy association, most overlapping pairs first
+            let mut pairs = Vec::new();
+            for (t, track) in tracks.iter().enumerate() {
+                for (d, (bbox, _, _)) in detections.iter().enumerate() {
+                    let iou = rect_iou(track.bbox, *bbox);
+                    if iou > TRACK_IOU_THRESHOLD {
+                        pairs.push((iou, t, d));
+                    }
+                }
+            }
+            pairs.sort_by(|a, b| b.0.total_cmp(&a.0));
+            let mut track_of = vec![None; detections.len()];
+            let mut track_taken = vec![false; tracks.len()];
+            for (_, t, d) in pairs {
+                if track_of[d].is_none() && !track_taken[t] {
+                    track_of[d] = Some(t);
+                    track_taken[t] = true;
+                }
+            }
+
+            for ((bbox, quality, face), track) in detections.into_iter().zip(track_of) {
+                let t = match track {
+                    Some(t) => t,
+                    None => {
+                        tracks.push(Track {
+                            id: next_id,
+                            bbox,
+                            last_seen: frame_index,
+                            crop: Mat::default(),
+                            quality: f32::NEG_INFINITY,
+                        });
+                        next_id += 1;
+                        tracks.len() - 1
+                    }
+                };
+                let track = &mut tracks[t];
+                track.bbox = bbox;
+                track.last_seen = frame_index;
+                if quality > track.quality {
+                    let mut crop = Mat::default();
+                    match self
+                        .face_recognizer
+                        .align_crop(&frame, &face.face_detect, &mut crop)
+                    {
+                        Ok(()) => {
+                            track.crop = crop;
+                            track.quality = quality;
+                        }
+                        Err(e) => warn!(
+                            "Cannot crop face of track {} in frame {} of {}: {}",
+                            track.id,
+                            frame_index,
+                            video.display(),
+                            e
+                        ),
+                    }
+                }
+            }
+            frame_index += 1;
+        }
+
+        finished.extend(tracks);
+        finished.sort_by_key(|track| track.id);
+        debug!(
+            "Found {} face tracks in {} frames of {}",
+            finished.len(),
+            frame_index,
+            video.display()
+        );
+        // A track whose every crop failed has nothing to return
+        Ok(finished
+            .into_iter()
+            .filter(|track| !track.crop.empty())
+            .map(|track| (track.id, track.crop, track.quality))
+            .collect())
+    }
+
+    /// Recognize all faces in a `UMat` frame, keeping resizing and detection on the
+    /// OpenCL device (T-API). Only the downscaled frame is mapped to host memory for the
+    /// recognition crops; matching is the same as in `run`. Settings that need the whole
+    /// frame on the host (a preprocess hook, tiling, full-resolution crops or retries and
+    /// the upside-down retry) process it as a `Mat` instead.
+    /// Returns one entry per face whose matching succeeded, like `run`.
+    #[cfg(feature = "umat")]
+    pub async fn run_umat(&mut self, frame: &UMat, threshold: f32) -> Result<Vec<FaceResult>> {
+        if frame.empty() {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+        self.prepare_matching().await?;
+        let original_size = frame.size()?;
+
+        let detected_faces = if self.preprocess.is_some()
+            || self.tiling.is_some()
+            || self.full_resolution_crops
+            || self.fullres_retry
+            || self.try_upside_down
+        {
+            self.extract_features(frame.get_mat(AccessFlag::ACCESS_READ)?.try_clone()?)
+                .await?
+        } else {
+            let faces = self.detect_and_embed_umat(frame, original_size)?;
+            self.finish_extraction(faces)?
+        };
+
+        Ok(self
+            .recognize_faces(&detected_faces, original_size, threshold, None)
+            .await?
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .collect())
+    }
+
+    /// `detect_and_embed` for a `UMat`: resize and detect on the device, then embed from
+    /// the downscaled frame mapped to host memory
+    #[cfg(feature = "umat")]
+    fn detect_and_embed_umat(
+        &mut self,
+        frame: &UMat,
+        original_size: Size,
+    ) -> Result<Vec<DetectedFace>> {
+        let target_size = self.detection_size_for(original_size);
+        let mut resized = UMat::new_def();
+        let detection_frame = if target_size != original_size {
+            opencv::imgproc::resize(
+                frame,
+                &mut resized,
+                target_size,
+                0.0,
+                0.0,
+                opencv::imgproc::INTER_LINEAR,
+            )?;
+            &resized
+        } else {
+            frame
+        };
+
+        self.ensure_detector_input_size(target_size)?;
+        let mut faces = Mat::default();
+        if let Err(e) = self.detector.detect_umat(detection_frame, &mut faces) {
+            error!("Face detection failed: {}", e);
+            return Err(FaceRecognitionError::DetectionFailed);
+        }
+
+        let host_frame = detection_frame.get_mat(AccessFlag::ACCESS_READ)?;
+        let mut detected_faces = Vec::new();
+        self.embed_detections(&host_frame, &faces, original_size, None, |face| {
+            detected_faces.push(face);
+            Ok(())
+        })?;
+        if let Some(iou_threshold) = self.post_nms_iou {
+            detected_faces = suppress_overlapping_faces(detected_faces, iou_threshold)?;
+        }
+        Ok(detected_faces)
+    }
+
+    /// Downscale `frame` into `buf` if it exceeds `max_size`, apply the preprocess hook
+    /// and return the frame to run detection on. `frame` is only copied when needed.
+    fn detection_frame<'a>(&self, frame: &'a Mat, buf: &'a mut Mat) -> Result<&'a Mat> {
+        let original_size = frame.size()?;
+        let target_size = self.detection_size_for(original_size);
+        if target_size == original_size {
+            if self.preprocess.is_none() && self.color_norm == ColorNorm::Off {
+                return Ok(frame);
+            }
+            frame.copy_to(buf)?;
+        } else {
+            opencv::imgproc::resize(
+                frame,
+                buf,
+                target_size,
+                0.0,
+                0.0,
+                opencv::imgproc::INTER_LINEAR,
+            )?;
+        }
+
+        self.apply_preprocess(buf)?;
+        Ok(buf)
+    }
+
+    fn apply_preprocess(&self, frame: &mut Mat) -> Result<()> {
+        normalize_colors(frame, self.color_norm)?;
+        if let Some(preprocess) = &self.preprocess {
+            preprocess(frame)?;
+        }
+        Ok(())
+    }
+
+    /// Run the detector on an already resized frame
+    fn detect_on(&mut self, frame: &Mat) -> Result<Mat> {
+        debug!("Frame size: {}x{}", frame.cols(), frame.rows());
+
+        self.ensure_detector_input_size(frame.size()?)?;
+
+        // Detect faces directly on the resized frame
+        let mut faces = Mat::default();
+        match self.detector.detect(frame, &mut faces) {
+            Ok(_) => {}
+            Err(e) => {
+                error!("Face detection failed: {}", e);
+                return Err(FaceRecognitionError::DetectionFailed);
+            }
+        }
+
+        debug!("Found {} faces", faces.rows());
+        Ok(faces)
+    }
+
+    /// Set detector input size to match the resized frame (like C++ version); skipped
+    /// while consecutive frames keep the same size
+    fn ensure_detector_input_size(&mut self, frame_size: Size) -> Result<()> {
+        if self.detector_input_size != Some(frame_size) {
+            self.detector.set_input_size(frame_size)?;
+            self.detector_input_size = Some(frame_size);
+        }
+        Ok(())
+    }
+
+    /// Run detection and feature extraction on an already resized frame, handing each
+    /// face to `sink`. With `full_resolution` (the original frame), recognition crops are
+    /// taken from it instead of from the downscaled `frame`.
+    fn detect_resized<F>(
+        &mut self,
+        frame: &Mat,
+        original_size: Size,
+        full_resolution: Option<&Mat>,
+        sink: F,
+    ) -> Result<()>
+    where
+        F: FnMut(DetectedFace) -> Result<()>,
+    {
+        let mut faces = self.detect_on(frame)?;
+        if self.try_upside_down {
+            let best_score = best_detection_score(&faces)?;
+            if best_score < UPSIDE_DOWN_RETRY_SCORE {
+                let rotated_faces = self.detect_upside_down(frame)?;
+                if best_detection_score(&rotated_faces)? > best_score {
+                    debug!("Using {} faces found upside down", rotated_faces.rows());
+                    faces = rotated_faces;
+                }
+            }
+        }
+        self.embed_detections(frame, &faces, original_size, full_resolution, sink)
+    }
+
+    /// Detect on `frame` rotated by 180° and map the detections back into `frame`.
+    /// Landmarks keep their meaning, so `align_crop` turns these faces upright.
+    fn detect_upside_down(&mut self, frame: &Mat) -> Result<Mat> {
+        let mut rotated = Mat::default();
+        opencv::core::rotate(frame, &mut rotated, opencv::core::ROTATE_180)?;
+        let mut faces = self.detect_on(&rotated)?;
+
+        let (width, height) = (frame.cols() as f32, frame.rows() as f32);
+        for i in 0..faces.rows() {
+            let w = *faces.at_2d::<f32>(i, 2)?;
+            let h = *faces.at_2d::<f32>(i, 3)?;
+            *faces.at_2d_mut::<f32>(i, 0)? = width - *faces.at_2d::<f32>(i, 0)? - w;
+            *faces.at_2d_mut::<f32>(i, 1)? = height - *faces.at_2d::<f32>(i, 1)? - h;
+            for col in 4..14 {
+                let extent = if col % 2 == 0 { width } else { height };
+                let value = faces.at_2d_mut::<f32>(i, col)?;
+                *value = extent - *value;
+            }
+        }
+        Ok(faces)
+    }
+
+    /// Align, crop and embed the detector output `faces` of `frame`, see `detect_resized`
+    fn embed_detections<F>(
+        &mut self,
+        frame: &Mat,
+        faces: &Mat,
+        original_size: Size,
+        full_resolution: Option<&Mat>,
+        mut sink: F,
+    ) -> Result<()>
+    where
+        F: FnMut(DetectedFace) -> Result<()>,
+    {
+        if faces.rows() <= 0 {
+            warn!("Cannot find any faces");
+            return Ok(());
+        }
+
+        let frame_size = frame.size()?;
+        for i in 0..faces.rows() {
+            let face_row = faces.row(i)?;
+
+            // Without a full-resolution frame, detection results are used directly - no
+            // coordinate scaling needed since detector input size matches frame size
+            let scaled_row;
+            let (base_frame, base_row): (&Mat, &Mat) = match full_resolution {
+                Some(full) => {
+                    scaled_row = scale_face_row(&face_row, frame_size, full.size()?)?;
+                    (full, &scaled_row)
+                }
+                None => (frame, &face_row),
+            };
+
+            let leveled = if self.eye_line_correction {
+                self.eye_level_region(base_frame, base_row)
+                    .unwrap_or_else(|e| {
+                        debug!("Eye-line correction failed for face {}: {}", i, e);
+                        None
+                    })
+            } else {
+                None
+            };
+            let (crop_source, crop_row) = match &leveled {
+                Some((region, row)) => (region, row),
+                None => (base_frame, base_row),
+            };
 
-            // Use face detection results directly - no coordinate scaling needed
-            // since detector input size matches frame size
             let mut aligned_img = Mat::default();
             match self
                 .face_recognizer
-                .align_crop(&frame, &face_row, &mut aligned_img)
+                .align_crop(crop_source, crop_row, &mut aligned_img)
+            {
+                Ok(_) => {}
+                Err(e) => {
+                    debug!(
+                        "Failed to align/crop face {}: {}, falling back to box crop",
+                        i, e
+                    );
+                    match self.box_crop(base_frame, base_row) {
+                        Ok(crop) => aligned_img = crop,
+                        Err(e) => {
+                            debug!("Failed to box crop face {}: {}", i, e);
+                            continue;
+                        }
+                    }
+                }
+            }
+
+            // Extract features
+            let mut feature = Mat::default();
+            match self.face_recognizer.feature(&aligned_img, &mut feature) {
+                Ok(_) => {
+                    debug!(
+                        "Feature extraction successful for face {}, feature size: {}x{}",
+                        i,
+                        feature.rows(),
+                        feature.cols()
+                    );
+                    if feature.rows() > 0 && feature.cols() > 0 {
+                        let first_few: Vec<f32> = (0..std::cmp::min(5, feature.cols()))
+                            .map(|j| *feature.at_2d::<f32>(0, j).unwrap_or(&0.0))
+                            .collect();
+                        debug!("First 5 feature values: {:?}", first_few);
+                    }
+                }
+                Err(e) => {
+                    debug!("Failed to extract features for face {}: {}", i, e);
+                    continue;
+                }
+            }
+
+            if self.tta {
+                feature = match self.flip_averaged_feature(&aligned_img, &feature) {
+                    Ok(averaged) => averaged,
+                    Err(e) => {
+                        debug!("Flip augmentation failed for face {}: {}", i, e);
+                        feature
+                    }
+                };
+            }
+
+            let mut detected = DetectedFace::new_with_detection_size(
+                "Unknown".to_string(),
+                face_row.try_clone()?,
+                feature,
+                original_size,
+                frame.size()?, // Current resized frame size
+            );
+            detected.ensemble_features =
+                self.ensemble_features(crop_source, crop_row, &aligned_img, i)?;
+            if self.enrolling && self.flip_augment {
+                detected.flipped_feature = match self.flipped_feature(&aligned_img) {
+                    Ok(flipped) => Some(flipped),
+                    Err(e) => {
+                        debug!("Flip augmentation failed for face {}: {}", i, e);
+                        None
+                    }
+                };
+            }
+            if self.keep_aligned {
+                detected.aligned = Some(aligned_img);
+            }
+            sink(detected)?;
+        }
+
+        Ok(())
+    }
+
+    /// Embed a face with every ensemble recognizer, using its own alignment where it works
+    /// and the primary recognizer's crop otherwise. Failures leave an empty `Mat`.
+    fn ensemble_features(
+        &mut self,
+        frame: &Mat,
+        face_row: &Mat,
+        primary_aligned: &Mat,
+        index: i32,
+    ) -> Result<Vec<Mat>> {
+        let mut features = Vec::with_capacity(self.ensemble.len());
+        for (member_idx, member) in self.ensemble.iter_mut().enumerate() {
+            let mut aligned = Mat::default();
+            let aligned = match member.recognizer.align_crop(frame, face_row, &mut aligned) {
+                Ok(_) => &aligned,
+                Err(_) => primary_aligned,
+            };
+            let mut feature = Mat::default();
+            if let Err(e) = member.recognizer.feature(aligned, &mut feature) {
+                debug!(
+                    "Ensemble recognizer {} failed for face {}: {}",
+                    member_idx, index, e
+                );
+                feature = Mat::default();
+            }
+            features.push(feature);
+        }
+        Ok(features)
+    }
+
+    /// Cut out the region around a face and rotate it so the eye line is horizontal.
+    /// Returns the rotated region and the detection row mapped into it, or `None` if the
+    /// face is already level.
+    fn eye_level_region(&self, frame: &Mat, face_row: &Mat) -> Result<Option<(Mat, Mat)>> {
+        let value = |col: i32| -> Result<f32> { Ok(*face_row.at_2d::<f32>(0, col)?) };
+        let (x, y, w, h) = (value(0)?, value(1)?, value(2)?, value(3)?);
+        let angle = (value(7)? - value(5)?)
+            .atan2(value(6)? - value(4)?)
+            .to_degrees();
+        if angle.abs() < 1.0 {
+            return Ok(None);
+        }
+
+        // Twice the face size leaves room for the corners that rotate into view
+        let side = w.max(h) * 2.0;
+        let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+        let x0 = ((cx - side / 2.0).floor() as i32).clamp(0, frame.cols());
+        let y0 = ((cy - side / 2.0).floor() as i32).clamp(0, frame.rows());
+        let x1 = ((cx + side / 2.0).ceil() as i32).clamp(0, frame.cols());
+        let y1 = ((cy + side / 2.0).ceil() as i32).clamp(0, frame.rows());
+        if x1 <= x0 || y1 <= y0 {
+            return Ok(None);
+        }
+        let region = Mat::roi(frame, Rect2i::new(x0, y0, x1 - x0, y1 - y0))?;
+
+        let center = opencv::core::Point2f::new(cx - x0 as f32, cy - y0 as f32);
+        let rotation = opencv::imgproc::get_rotation_matrix_2d(center, angle as f64, 1.0)?;
+        let mut rotated = Mat::default();
+        opencv::imgproc::warp_affine(
+            &region,
+            &mut rotated,
+            &rotation,
+            region.size()?,
+            opencv::imgproc::INTER_LINEAR,
+            opencv::core::BORDER_REPLICATE,
+            Scalar::default(),
+        )?;
+
+        // Map the box center and the landmarks into the rotated region
+        let m = |r: i32, c: i32| -> Result<f32> { Ok(*rotation.at_2d::<f64>(r, c)? as f32) };
+        let (m00, m01, m02) = (m(0, 0)?, m(0, 1)?, m(0, 2)?);
+        let (m10, m11, m12) = (m(1, 0)?, m(1, 1)?, m(1, 2)?);
+        let transform = |px: f32, py: f32| {
+            let (lx, ly) = (px - x0 as f32, py - y0 as f32);
+            (m00 * lx + m01 * ly + m02, m10 * lx + m11 * ly + m12)
+        };
+
+        let mut row = face_row.try_clone()?;
+        let (ncx, ncy) = transform(cx, cy);
+        *row.at_2d_mut::<f32>(0, 0)? = ncx - w / 2.0;
+        *row.at_2d_mut::<f32>(0, 1)? = ncy - h / 2.0;
+        for point in 0..5 {
+            let col = 4 + 2 * point;
+            let (px, py) = transform(value(col)?, value(col + 1)?);
+            *row.at_2d_mut::<f32>(0, col)? = px;
+            *row.at_2d_mut::<f32>(0, col + 1)? = py;
+        }
+
+        Ok(Some((rotated, row)))
+    }
+
+    /// Embedding of the horizontally mirrored `aligned` crop
+    fn flipped_feature(&mut self, aligned: &Mat) -> Result<Mat> {
+        let mut flipped = Mat::default();
+        opencv::core::flip(aligned, &mut flipped, 1)?;
+        let mut flipped_feature = Mat::default();
+        self.face_recognizer
+            .feature(&flipped, &mut flipped_feature)?;
+        Ok(flipped_feature)
+    }
+
+    /// L2-normalized mean of `feature` and the embedding of the mirrored `aligned` crop
+    fn flip_averaged_feature(&mut self, aligned: &Mat, feature: &Mat) -> Result<Mat> {
+        let flipped_feature = self.flipped_feature(aligned)?;
+
+        let mut sum = Mat::default();
+        opencv::core::add(
+            feature,
+            &flipped_feature,
+            &mut sum,
+            &opencv::core::no_array(),
+            -1,
+        )?;
+        let mut averaged = Mat::default();
+        opencv::core::normalize(
+            &sum,
+            &mut averaged,
+            1.0,
+            0.0,
+            opencv::core::NORM_L2,
+            -1,
+            &opencv::core::no_array(),
+        )?;
+        Ok(averaged)
+    }
+
+    fn resize_frame(&self, frame: &mut Mat, keep_aspect_ratio: bool) -> Result<()> {
+        // With the aspect ratio kept, `detection_size_for` decides (it also applies the
+        // detection size ceiling when no resizing is requested)
+        if self.max_size <= 0 && !keep_aspect_ratio {
+            return Ok(()); // No resizing requested
+        }
+
+        if frame.empty() {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+
+        let cols = frame.cols();
+        let rows = frame.rows();
+
+        if keep_aspect_ratio {
+            let new_size = self.detection_size_for(Size::new(cols, rows));
+            if new_size.width != cols || new_size.height != rows {
+                let mut resized = Mat::default();
+                opencv::imgproc::resize(
+                    frame,
+                    &mut resized,
+                    new_size,
+                    0.0,
+                    0.0,
+                    opencv::imgproc::INTER_LINEAR,
+                )?;
+                *frame = resized;
+            }
+        } else {
+            let new_size = Size::new(self.max_size, self.max_size);
+            let mut resized = Mat::default();
+            opencv::imgproc::resize(
+                frame,
+                &mut resized,
+                new_size,
+                0.0,
+                0.0,
+                opencv::imgproc::INTER_LINEAR,
+            )?;
+            *frame = resized;
+        }
+
+        Ok(())
+    }
+
+    /// Crop the detection box, expanded by `crop_margin`, and resize it to the recognizer
+    /// input size. Fallback for when `align_crop` fails. Unless `crop_padding` is `None`,
+    /// the box is grown to a square around its center and the parts outside the frame are
+    /// filled with the configured border.
+    fn box_crop(&self, frame: &Mat, face_row: &Mat) -> Result<Mat> {
+        let x = *face_row.at_2d::<f32>(0, 0)?;
+        let y = *face_row.at_2d::<f32>(0, 1)?;
+        let w = *face_row.at_2d::<f32>(0, 2)?;
+        let h = *face_row.at_2d::<f32>(0, 3)?;
+        let (cx, cy) = (x + w / 2.0, y + h / 2.0);
+        let mut w = w * (1.0 + 2.0 * self.crop_margin);
+        let mut h = h * (1.0 + 2.0 * self.crop_margin);
+        let border_type = self.crop_padding.border_type();
+        if border_type.is_some() {
+            w = w.max(h);
+            h = w;
+        }
+
+        // Wanted region, then the part of it inside the frame
+        let wx0 = (cx - w / 2.0).floor() as i32;
+        let wy0 = (cy - h / 2.0).floor() as i32;
+        let wx1 = (cx + w / 2.0).ceil() as i32;
+        let wy1 = (cy + h / 2.0).ceil() as i32;
+        let x0 = wx0.clamp(0, frame.cols());
+        let y0 = wy0.clamp(0, frame.rows());
+        let x1 = wx1.clamp(0, frame.cols());
+        let y1 = wy1.clamp(0, frame.rows());
+        if x1 <= x0 || y1 <= y0 {
+            return Err(FaceRecognitionError::FeatureExtractionFailed);
+        }
+
+        let roi = Mat::roi(frame, Rect2i::new(x0, y0, x1 - x0, y1 - y0))?;
+        let mut padded = Mat::default();
+        if let Some(border_type) = border_type {
+            opencv::core::copy_make_border(
+                &roi,
+                &mut padded,
+                y0 - wy0,
+                wy1 - y1,
+                x0 - wx0,
+                wx1 - x1,
+                border_type,
+                Scalar::default(),
+            )?;
+        }
+
+        let mut crop = Mat::default();
+        opencv::imgproc::resize(
+            if border_type.is_some() {
+                &padded
+            } else {
+                &*roi
+            },
+            &mut crop,
+            RECOGNIZER_INPUT_SIZE,
+            0.0,
+            0.0,
+            opencv::imgproc::INTER_LINEAR,
+        )?;
+        Ok(crop)
+    }
+
+    /// Size of the full-resolution retry for a frame of `size`: the frame itself, or
+    /// downscaled to the detection size ceiling if it exceeds it
+    fn fullres_retry_size(&self, size: Size) -> Size {
+        let max_dim = std::cmp::max(size.width, size.height);
+        let ceiling = self.detection_size_ceiling;
+        if ceiling <= 0 || max_dim <= ceiling {
+            return size;
+        }
+        let scale = ceiling as f64 / max_dim as f64;
+        Size::new(
+            (size.width as f64 * scale) as i32,
+            (size.height as f64 * scale) as i32,
+        )
+    }
+
+    /// Size a frame of `size` is downscaled to before detection (aspect ratio kept)
+    fn detection_size_for(&self, size: Size) -> Size {
+        let max_dim = std::cmp::max(size.width, size.height);
+        let mut max_size = self.max_size;
+        let ceiling = self.detection_size_ceiling;
+        if ceiling > 0 && max_dim > ceiling && (max_size <= 0 || max_size > ceiling) {
+            if self.force_detection_size_ceiling {
+                max_size = ceiling;
+            } else {
+                warn!(
+                    "Detecting on a {}x{} frame exceeds the detection size ceiling of {}px",
+                    size.width, size.height, ceiling
+                );
+            }
+        }
+
+        if max_size <= 0 || max_dim <= max_size {
+            return size;
+        }
+
+        let scale = max_size as f64 / max_dim as f64;
+        Size::new(
+            (size.width as f64 * scale) as i32,
+            (size.height as f64 * scale) as i32,
+        )
+    }
+
+    /// Draw the box of a face, in the color of `name` with identity colors enabled
+    fn visualize_face(&self, frame: &mut Mat, bbox: Rect2i, name: Option<&str>) -> Result<()> {
+        let color = match name {
+            Some(name)
+                if self.identity_colors
+                    && name != "Unknown"
+                    && name != self.unknown_label
+                    && name != self.unverified_label =>
             {
-                Ok(_) => {}
-                Err(e) => {
-                    debug!("Failed to align/crop face {}: {}", i, e);
+                identity_color(name)
+            }
+            _ => Scalar::new(0.0, 255.0, 0.0, 0.0), // Green
+        };
+        rectangle(frame, bbox, color, 2, LINE_8, 0)?;
+        Ok(())
+    }
+
+    /// Pick up watcher changes (a failed reload keeps the previous database) and apply the
+    /// missing database policy before matching
+    async fn prepare_matching(&mut self) -> Result<()> {
+        if let Err(e) = self.reload_if_changed().await {
+            error!("Database reload failed, keeping previous database: {}", e);
+        }
+        self.check_database_loaded().await
+    }
+
+    /// Apply the missing database policy when nothing was ever loaded
+    async fn check_database_loaded(&mut self) -> Result<()> {
+        let (has_persons, has_features) = {
+            let features_map = self.features_map.read().await;
+            (!features_map.is_empty(), features_map.feature_count() > 0)
+        };
+        if has_features {
+            return Ok(());
+        }
+
+        // A loaded database without a single feature usually means every enrollment image
+        // failed detection; report it apart from the database never having been loaded
+        let loaded = *self.db_load_status.read().await == DbLoadStatus::Loaded || has_persons;
+        match self.missing_database_policy {
+            MissingDatabasePolicy::Error if loaded => Err(FaceRecognitionError::DatabaseEmpty),
+            MissingDatabasePolicy::Error => Err(FaceRecognitionError::DatabaseNotLoaded),
+            MissingDatabasePolicy::Warn if loaded => {
+                if !self.empty_database_warned {
+                    warn!(
+                        "Persons database is loaded but holds no features: every face will be \
+                         reported as Unknown. Check the load report for images without \
+                         detectable faces"
+                    );
+                    self.empty_database_warned = true;
+                }
+                Ok(())
+            }
+            MissingDatabasePolicy::Warn => {
+                if !self.missing_database_warned {
+                    warn!(
+                        "No persons database loaded: every face will be reported as Unknown. \
+                         Call load_persons_db first, or set MissingDatabasePolicy::Ignore for \
+                         detection-only use"
+                    );
+                    self.missing_database_warned = true;
+                }
+                Ok(())
+            }
+            MissingDatabasePolicy::Ignore => Ok(()),
+        }
+    }
+
+    async fn find_best_match(
+        &mut self,
+        face_feature: &Mat,
+        ensemble_probe: &[Mat],
+        threshold: f32,
+    ) -> Result<MatchResults> {
+        let features_map = self.features_map.read().await;
+
+        let mut results = Vec::new();
+        // Accepted person and score; `None` until a candidate is accepted, so even a
+        // person named "Unknown" can be recognized
+        let mut best: Option<(String, f32)> = None;
+        let model_dim = face_feature.total();
+        if model_dim == 0
+            || !face_feature
+                .data_typed::<f32>()?
+                .iter()
+                .all(|v| v.is_finite())
+        {
+            return Err(FaceRecognitionError::FeatureExtractionFailed);
+        }
+
+        let person_scores =
+            self.scored_persons(&**features_map, face_feature, ensemble_probe, &|_| false)?;
+        // Malformed features are dropped when they enter the database, and features of
+        // another size are skipped while scoring. Only a database built entirely by another
+        // recognizer (e.g. an imported or restored one) is left to report here.
+        if person_scores.is_empty() {
+            check_database_dim(&**features_map, model_dim)?;
+        }
+
+        for (person_name, person_best) in person_scores {
+            if self.is_denied(&person_name) {
+                continue;
+            }
+            let person_threshold = self.person_threshold(&person_name, threshold);
+
+            // Equal scores go to the alphabetically first person, independent of the
+            // map's iteration order; `TieBreak::Unknown` rejects such ties below
+            let better = best.as_ref().map_or(true, |(name, score)| {
+                person_best > *score || (person_best == *score && person_name < *name)
+            });
+            let accepted = match self.recognition_mode {
+                RecognitionMode::OpenSet => better && person_best > person_threshold,
+                // Every probe is enrolled: the top candidate wins whatever its score
+                RecognitionMode::ClosedSet => better,
+            };
+            if accepted {
+                best = Some((person_name.clone(), person_best));
+            }
+            results.push(MatchResult::new(person_name, person_best));
+        }
+
+        // Highest score first, ties broken by name so the order is stable across runs
+        results.sort_by(|a, b| {
+            b.score
+                .total_cmp(&a.score)
+                .then_with(|| a.name.cmp(&b.name))
+        });
+
+        if let Some((name, score)) = &best {
+            if self.tie_break == TieBreak::Unknown
+                && results.iter().any(|r| r.score == *score && r.name != *name)
+            {
+                debug!(
+                    "Best score {} is shared by several persons, reporting Unknown",
+                    score
+                );
+                best = None;
+            }
+        }
+
+        let best_match = match best {
+            Some((name, score)) => {
+                self.counters
+                    .matches_accepted
+                    .fetch_add(1, Ordering::Relaxed);
+                MatchResult::new(name, score)
+            }
+            None => {
+                let closest = results.first().map(|top| {
+                    let threshold = self.person_threshold(&top.name, threshold);
+                    (top.score, threshold)
+                });
+                self.unmatched_result(closest, 0.0)
+            }
+        };
+
+        let mapping = self.confidence_mapping;
+        Ok(MatchResults {
+            results: results
+                .into_iter()
+                .map(|result| result.with_confidence(mapping))
+                .collect(),
+            best_match: best_match.with_confidence(mapping),
+        })
+    }
+
+    /// Find the threshold that recognizes a labeled set best against the loaded database.
+    ///
+    /// `labeled_dir` has one folder per person, like the database itself, holding probe
+    /// images other than the enrolled ones. The largest face of each image is matched
+    /// once; then every threshold from 0.0 to 1.0 in steps of 0.01 is scored by accuracy,
+    /// where a probe is correct if it is accepted as its folder's person, or reported as
+    /// "Unknown" when that person is not enrolled. Per-person thresholds are ignored.
+    /// Images without a face are skipped; returns `NoFaceDetected` if none has a face.
+    /// With `set_leave_one_out`, references from the probe image itself are ignored. If the
+    /// database holds mirrored references from `set_flip_augment`, the accuracy at the
+    /// suggested threshold without them is reported as well, to measure the augmentation.
+    /// Likewise with `set_tta`, the accuracy of probes embedded without it is reported.
+    pub async fn suggest_threshold<P: AsRef<Path>>(
+        &mut self,
+        labeled_dir: P,
+    ) -> Result<ThresholdSuggestion> {
+        let labeled_dir = labeled_dir.as_ref();
+        // Expected name (None: not enrolled) and the top candidate with its score
+        let mut probes: Vec<(Option<String>, Option<MatchResult>)> = Vec::new();
+        // The same without the mirrored references of `set_flip_augment`, if there are any
+        let mut probes_without_flip: Vec<(Option<String>, Option<MatchResult>)> = Vec::new();
+        // The same with probes embedded without `set_tta`, if it is on
+        let mut probes_without_tta: Vec<(Option<String>, Option<MatchResult>)> = Vec::new();
+        let has_mirrored = self
+            .features_map
+            .read()
+            .await
+            .iter()
+            .any(|(_, features)| features.iter().any(|stored| stored.mirrored));
+        let mut excluded_references = 0;
+        // Canonical path of every reference source, resolved once for leave-one-out
+        let mut canonical_sources: HashMap<PathBuf, PathBuf> = HashMap::new();
+        if self.leave_one_out {
+            for (_, features) in self.features_map.read().await.iter() {
+                for source in features.iter().filter_map(|stored| stored.source.as_ref()) {
+                    canonical_sources.entry(source.clone()).or_insert_with(|| {
+                        source.canonicalize().unwrap_or_else(|_| source.clone())
+                    });
+                }
+            }
+            if canonical_sources.is_empty() {
+                warn!("Leave-one-out evaluation needs reference sources, but no reference has one");
+            }
+        }
+
+        let mut person_dirs: Vec<PathBuf> = std::fs::read_dir(labeled_dir)?
+            .filter_map(|entry| entry.ok().map(|e| e.path()))
+            .filter(|path| path.is_dir())
+            .collect();
+        person_dirs.sort();
+        for person_dir in person_dirs {
+            let label = normalize_person_name(
+                person_dir
+                    .file_name()
+                    .and_then(|n| n.to_str())
+                    .unwrap_or("unknown"),
+            );
+            let expected = self
+                .features_map
+                .read()
+                .await
+                .iter()
+                .map(|(name, _)| name)
+                .find(|name| person_name_key(name) == person_name_key(&label))
+                .map(Cow::into_owned);
+
+            let mut images: Vec<PathBuf> = std::fs::read_dir(&person_dir)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|path| path.is_file())
+                .collect();
+            images.sort();
+            for image_path in images {
+                let img = match image_path.to_str().map(|p| imread(p, IMREAD_COLOR)) {
+                    Some(Ok(img)) if !img.empty() => img,
+                    _ => {
+                        debug!("Skipping unreadable file {}", image_path.display());
+                        continue;
+                    }
+                };
+                let label = image_path.display().to_string();
+                let face = match self.primary_face(&img, &label).await {
+                    Ok(face) => face,
+                    Err(FaceRecognitionError::NoFaceDetected(_)) => {
+                        debug!("No face in {}, skipping", label);
+                        continue;
+                    }
+                    Err(e) => return Err(e),
+                };
+                if has_mirrored {
+                    let (expected, top, _) = self.top_candidate_excluding(
+                        &**self.features_map.read().await,
+                        &canonical_sources,
+                        &face,
+                        &image_path,
+                        true,
+                        expected.clone(),
+                    )?;
+                    probes_without_flip.push((expected, top));
+                }
+                if self.tta {
+                    self.tta = false;
+                    let plain_face = self.primary_face(&img, &label).await;
+                    self.tta = true;
+                    let (expected, top, _) = self.top_candidate_excluding(
+                        &**self.features_map.read().await,
+                        &canonical_sources,
+                        &plain_face?,
+                        &image_path,
+                        false,
+                        expected.clone(),
+                    )?;
+                    probes_without_tta.push((expected, top));
+                }
+                if self.leave_one_out {
+                    let (expected, top, excluded) = self.top_candidate_excluding(
+                        &**self.features_map.read().await,
+                        &canonical_sources,
+                        &face,
+                        &image_path,
+                        false,
+                        expected.clone(),
+                    )?;
+                    excluded_references += excluded;
+                    probes.push((expected, top));
+                    continue;
+                }
+                let ranked = self
+                    .find_best_match(&face.feature, &face.ensemble_features, f32::INFINITY)
+                    .await?;
+                probes.push((expected.clone(), ranked.results.into_iter().next()));
+            }
+        }
+
+        if probes.is_empty() {
+            return Err(FaceRecognitionError::NoFaceDetected(
+                labeled_dir.display().to_string(),
+            ));
+        }
+
+        let accuracy_at = |probes: &[(Option<String>, Option<MatchResult>)], threshold: f32| {
+            let correct = probes
+                .iter()
+                .filter(|(expected, top)| {
+                    let accepted = top
+                        .as_ref()
+                        .filter(|top| top.score > threshold)
+                        .map(|top| &top.name);
+                    accepted == expected.as_ref()
+                })
+                .count();
+            correct as f32 / probes.len() as f32
+        };
+        let mut suggestion = ThresholdSuggestion {
+            probes: probes.len(),
+            excluded_references,
+            ..Default::default()
+        };
+        for step in 0..=THRESHOLD_SWEEP_STEPS {
+            let threshold = step as f32 / THRESHOLD_SWEEP_STEPS as f32;
+            let accuracy = accuracy_at(&probes, threshold);
+            if accuracy >= suggestion.accuracy {
+                suggestion.threshold = threshold;
+                suggestion.accuracy = accuracy;
+            }
+            suggestion.curve.push(ThresholdPoint {
+                threshold,
+                accuracy,
+            });
+        }
+
+        info!(
+            "Suggested threshold {:.2} ({:.1}% accuracy on {} probes)",
+            suggestion.threshold,
+            suggestion.accuracy * 100.0,
+            suggestion.probes
+        );
+        if has_mirrored {
+            let accuracy = accuracy_at(&probes_without_flip, suggestion.threshold);
+            info!(
+                "Without flip augmentation: {:.1}% accuracy at threshold {:.2}",
+                accuracy * 100.0,
+                suggestion.threshold
+            );
+            suggestion.accuracy_without_flip = Some(accuracy);
+        }
+        if !probes_without_tta.is_empty() {
+            let accuracy = accuracy_at(&probes_without_tta, suggestion.threshold);
+            info!(
+                "Without test-time augmentation: {:.1}% accuracy at threshold {:.2}",
+                accuracy * 100.0,
+                suggestion.threshold
+            );
+            suggestion.accuracy_without_tta = Some(accuracy);
+        }
+        Ok(suggestion)
+    }
+
+    /// Top candidate for `face` ignoring the references whose source is `probe_path`, for
+    /// leave-one-out evaluation; sources are compared by their path in `canonical_sources`.
+    /// With `without_flip`, the mirrored references of `set_flip_augment` are ignored too.
+    /// Returns `expected`, or `None` if all of that person's references were ignored, the
+    /// candidate and the number of references excluded for coming from the probe.
+    fn top_candidate_excluding(
+        &self,
+        features_map: &dyn FeatureStore,
+        canonical_sources: &HashMap<PathBuf, PathBuf>,
+        face: &DetectedFace,
+        probe_path: &Path,
+        without_flip: bool,
+        expected: Option<String>,
+    ) -> Result<(Option<String>, Option<MatchResult>, usize)> {
+        let probe_path = probe_path
+            .canonicalize()
+            .unwrap_or_else(|_| probe_path.to_path_buf());
+        let from_probe = |stored: &StoredFeature| {
+            stored
+                .source
+                .as_ref()
+                .and_then(|source| canonical_sources.get(source))
+                == Some(&probe_path)
+        };
+
+        let excluded = features_map
+            .iter()
+            .map(|(_, features)| features.iter().filter(|&stored| from_probe(stored)).count())
+            .sum();
+        let ignored =
+            |stored: &StoredFeature| from_probe(stored) || (without_flip && stored.mirrored);
+        let top = self
+            .top_persons(
+                features_map,
+                &face.feature,
+                &face.ensemble_features,
+                1,
+                &ignored,
+            )?
+            .into_iter()
+            .next()
+            .map(|(name, score)| MatchResult::new(name, score));
+        let expected = expected.filter(|name| {
+            features_map
+                .get(name)
+                .is_some_and(|features| features.iter().any(|stored| !ignored(stored)))
+        });
+        Ok((expected, top, excluded))
+    }
+
+    /// `person_scores`, on the rayon thread pool for large galleries
+    fn scored_persons(
+        &self,
+        features_map: &dyn FeatureStore,
+        face_feature: &Mat,
+        ensemble_probe: &[Mat],
+        exclude: &dyn Fn(&StoredFeature) -> bool,
+    ) -> Result<Vec<(String, f32)>> {
+        match self.parallel_person_scores(features_map, face_feature, exclude)? {
+            Some(scores) => Ok(scores),
+            None => self.person_scores(features_map, face_feature, ensemble_probe, exclude),
+        }
+    }
+
+    /// The `k` best scored persons that are not denylisted, best first; equal scores are
+    /// ordered alphabetically
+    fn top_persons(
+        &self,
+        features_map: &dyn FeatureStore,
+        face_feature: &Mat,
+        ensemble_probe: &[Mat],
+        k: usize,
+        exclude: &dyn Fn(&StoredFeature) -> bool,
+    ) -> Result<Vec<(String, f32)>> {
+        let mut scores: Vec<(String, f32)> = self
+            .scored_persons(features_map, face_feature, ensemble_probe, exclude)?
+            .into_iter()
+            .filter(|(name, _)| !self.is_denied(name))
+            .collect();
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scores.truncate(k);
+        Ok(scores)
+    }
+
+    /// Best score of every person with at least one feature, ignoring the references
+    /// `exclude` returns true for
+    fn person_scores(
+        &self,
+        features_map: &dyn FeatureStore,
+        face_feature: &Mat,
+        ensemble_probe: &[Mat],
+        exclude: &dyn Fn(&StoredFeature) -> bool,
+    ) -> Result<Vec<(String, f32)>> {
+        // Int8 form of the probe, computed once if any stored feature is quantized
+        let mut quantized_probe: Option<QuantizedFeature> = None;
+        let model_dim = face_feature.total();
+        let mut scores = Vec::with_capacity(features_map.len());
+        for (person_name, features) in features_map.iter() {
+            let mut weighted = Vec::with_capacity(features.len());
+            for (feature_idx, stored) in features.iter().enumerate() {
+                if stored.dim() != model_dim || exclude(stored) {
+                    continue;
+                }
+                let feature = &stored.feature;
+                let score = self.feature_similarity(face_feature, &mut quantized_probe, stored)?;
+                let score = self.fused_score(score, ensemble_probe, stored)?;
+                if !score.is_finite() {
+                    continue;
+                }
+                weighted.push((score, stored.weight));
+
+                // Debug feature comparison
+                if feature_idx == 0 {
+                    // Only debug the first feature per person to avoid spam
+                    let query_first_5: Vec<f32> = (0..5)
+                        .map(|j| *face_feature.at_2d::<f32>(0, j).unwrap_or(&0.0))
+                        .collect();
+                    let db_first_5: Vec<f32> = (0..5)
+                        .map(|j| *feature.at_2d::<f32>(0, j).unwrap_or(&0.0))
+                        .collect();
+                    debug!(
+                        "Person {}, feature #{}, score: {}",
+                        person_name, feature_idx, score
+                    );
+                    debug!("  Query: {:?}", query_first_5);
+                    debug!("  DB:    {:?}", db_first_5);
+                } else {
+                    debug!(
+                        "Person {}, feature #{}, score: {}",
+                        person_name, feature_idx, score
+                    );
+                }
+            }
+            // Persons whose references all have weight 0 are left out
+            if let Some(person_best) = self.reference_aggregation.aggregate(weighted) {
+                scores.push((person_name.to_string(), person_best));
+            }
+        }
+        Ok(scores)
+    }
+
+    /// `person_scores` computed on the rayon thread pool in pure Rust, for galleries of at
+    /// least `parallel_match_min_features` features; `None` otherwise
+    #[cfg(feature = "parallel")]
+    fn parallel_person_scores(
+        &self,
+        features_map: &dyn FeatureStore,
+        face_feature: &Mat,
+        exclude: &dyn Fn(&StoredFeature) -> bool,
+    ) -> Result<Option<Vec<(String, f32)>>> {
+        use crate::types::HalfFeature;
+        use rayon::prelude::*;
+
+        enum Reference<'a> {
+            Float(&'a [f32]),
+            Quantized(&'a QuantizedFeature),
+            Half(&'a HalfFeature),
+        }
+
+        let total = features_map.feature_count();
+        if self.parallel_match_min_features == 0
+            || total < self.parallel_match_min_features
+            || !self.ensemble.is_empty()
+        {
+            return Ok(None);
+        }
+
+        let probe = feature_to_vec(face_feature)?;
+        let quantized_probe = QuantizedFeature::from_slice(&probe);
+        let aggregation = self.reference_aggregation;
+        // Borrow the raw data up front: the recognizer and Mats stay on this thread
+        let stored_persons: Vec<_> = features_map
+            .iter()
+            .filter(|(_, features)| !features.is_empty())
+            .collect();
+        let persons = stored_persons
+            .iter()
+            .map(|(name, features)| {
+                let references = features
+                    .iter()
+                    .filter(|stored| stored.dim() == probe.len() && !exclude(stored))
+                    .map(|stored| {
+                        let reference = match (&stored.quantized, &stored.half) {
+                            (Some(quantized), _) => Reference::Quantized(quantized),
+                            (None, Some(half)) => Reference::Half(half),
+                            (None, None) => Reference::Float(stored.feature.data_typed::<f32>()?),
+                        };
+                        Ok((reference, stored.weight))
+                    })
+                    .collect::<Result<Vec<_>>>()?;
+                Ok((name, references))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        Ok(Some(
+            persons
+                .par_iter()
+                .filter_map(|(name, references)| {
+                    let scores: Vec<(f32, f32)> = references
+                        .iter()
+                        .map(|(reference, weight)| {
+                            let score = match reference {
+                                Reference::Float(values) => cosine_similarity(&probe, values),
+                                Reference::Quantized(quantized) => {
+                                    quantized.cosine(&quantized_probe)
+                                }
+                                Reference::Half(half) => half.cosine(&probe),
+                            };
+                            (score, *weight)
+                        })
+                        .filter(|(score, _)| score.is_finite())
+                        .collect();
+                    aggregation
+                        .aggregate(scores)
+                        .map(|score| (name.to_string(), score))
+                })
+                .collect(),
+        ))
+    }
+
+    #[cfg(not(feature = "parallel"))]
+    fn parallel_person_scores(
+        &self,
+        _features_map: &dyn FeatureStore,
+        _face_feature: &Mat,
+        _exclude: &dyn Fn(&StoredFeature) -> bool,
+    ) -> Result<Option<Vec<(String, f32)>>> {
+        Ok(None)
+    }
+
+    /// Rank the database persons for every face in `frame`, in detector order. Each entry
+    /// holds the per-person leaderboard and the accepted best match.
+    pub async fn run_ranked(&mut self, frame: &Mat, threshold: f32) -> Result<Vec<MatchResults>> {
+        Ok(self
+            .run_ranked_faces(frame, threshold)
+            .await?
+            .into_iter()
+            .map(|face| face.ranking)
+            .collect())
+    }
+
+    /// Like `run_ranked`, with each face's index and bounding box in `frame` coordinates,
+    /// e.g. for `ResultsCsvWriter`
+    pub async fn run_ranked_faces(
+        &mut self,
+        frame: &Mat,
+        threshold: f32,
+    ) -> Result<Vec<RankedFace>> {
+        self.prepare_matching().await?;
+        let detected_faces = self.extract_features(frame.clone()).await?;
+        let frame_size = frame.size()?;
+        let mut ranked = Vec::with_capacity(detected_faces.len());
+        for (index, face) in detected_faces.iter().enumerate() {
+            let ranking = self.match_face(face, threshold).await?;
+            ranked.push(RankedFace {
+                index,
+                bbox: face.bbox_scaled(frame_size)?,
+                ranking,
+            });
+        }
+        Ok(ranked)
+    }
+
+    /// Weighted average of the normalized primary `score` and the ensemble recognizers'
+    /// scores for `stored`; `score` itself without an ensemble
+    fn fused_score(
+        &self,
+        score: f32,
+        ensemble_probe: &[Mat],
+        stored: &StoredFeature,
+    ) -> Result<f32> {
+        if self.ensemble.is_empty() {
+            return Ok(score);
+        }
+
+        let mut total = self.primary_fusion.weight * self.primary_fusion.normalization.apply(score);
+        let mut weights = self.primary_fusion.weight;
+        for ((member, probe), reference) in self
+            .ensemble
+            .iter()
+            .zip(ensemble_probe)
+            .zip(&stored.ensemble_features)
+        {
+            if probe.empty() || reference.empty() {
+                continue;
+            }
+            let member_score =
+                member
+                    .recognizer
+                    .match_(probe, reference, SimilarityMetric::Cosine.dis_type())?
+                    as f32;
+            total += member.fusion.weight * member.fusion.normalization.apply(member_score);
+            weights += member.fusion.weight;
+        }
+        Ok(if weights > 0.0 {
+            total / weights
+        } else {
+            score
+        })
+    }
+
+    /// Cosine similarity of a probe and a stored feature, in int8 arithmetic if the stored
+    /// feature is quantized (`quantized_probe` caches the probe's int8 form) and widened
+    /// from f16 if stored in half precision
+    fn feature_similarity(
+        &self,
+        probe: &Mat,
+        quantized_probe: &mut Option<QuantizedFeature>,
+        stored: &StoredFeature,
+    ) -> Result<f32> {
+        Ok(match (&stored.quantized, &stored.half) {
+            (Some(quantized), _) => {
+                if quantized_probe.is_none() {
+                    *quantized_probe = Some(QuantizedFeature::from_slice(&feature_to_vec(probe)?));
+                }
+                quantized.cosine(quantized_probe.as_ref().unwrap())
+            }
+            (None, Some(half)) => half.cosine(probe.data_typed::<f32>()?),
+            (None, None) => self.face_recognizer.match_(
+                probe,
+                &stored.feature,
+                opencv::objdetect::FaceRecognizerSF_DisType::FR_COSINE as i32,
+            )? as f32,
+        })
+    }
+
+    /// Number of stored reference features the primary (largest) face in `image` matches
+    /// with a cosine score above `threshold`. A probe matching many references, or
+    /// references of many persons, hints at a generic or low-quality face. Each reference
+    /// image counts once: the mirrored references of `set_flip_augment` are left out, as
+    /// are denied persons. Returns `NoFaceDetected` if the image has no face, and
+    /// `DatabaseModelMismatch` if the database was built with another recognizer.
+    pub async fn count_matches_above(&mut self, image: &Mat, threshold: f32) -> Result<usize> {
+        let probe = self.primary_face_feature(image, "probe image").await?;
+        let model_dim = probe.total();
+        let features_map = self.features_map.read().await;
+        let mut quantized_probe = None;
+        let mut compared = 0;
+        let mut count = 0;
+        for (person, features) in features_map.iter() {
+            if self.is_denied(&person) {
+                continue;
+            }
+            for stored in features.iter() {
+                if stored.mirrored || stored.dim() != model_dim {
+                    continue;
+                }
+                compared += 1;
+                if self.feature_similarity(&probe, &mut quantized_probe, stored)? > threshold {
+                    count += 1;
+                }
+            }
+        }
+        if compared == 0 {
+            check_database_dim(&**features_map, model_dim)?;
+        }
+        Ok(count)
+    }
+
+    /// Faces in `images` that match nobody above `threshold`, for labeling and enrolling
+    /// them later. Each entry is the index of the image, the face box in that image and a
+    /// crop of the box (clamped to the image) at its original resolution. Faces whose
+    /// matching fails are skipped with a warning.
+    pub async fn collect_unknowns(
+        &mut self,
+        images: &[Mat],
+        threshold: f32,
+    ) -> Result<Vec<(usize, Rect2i, Mat)>> {
+        self.check_database_loaded().await?;
+        let mut unknowns = Vec::new();
+        for (image_index, image) in images.iter().enumerate() {
+            let frame_size = image.size()?;
+            let detected_faces = self.extract_features(image.clone()).await?;
+            for (i, face) in detected_faces.iter().enumerate() {
+                let face_result = match self.recognize_face(i, face, frame_size, threshold).await {
+                    Ok(face_result) => face_result,
+                    Err(e) => {
+                        warn!(
+                            "Face {} of image {} could not be recognized: {}",
+                            i + 1,
+                            image_index,
+                            e
+                        );
+                        continue;
+                    }
+                };
+                if !face_result.best_match.unmatched {
+                    continue;
+                }
+
+                let bbox = face_result.bbox & Rect2i::new(0, 0, image.cols(), image.rows());
+                if bbox.width <= 0 || bbox.height <= 0 {
                     continue;
                 }
+                let crop = Mat::roi(image, bbox)?.try_clone()?;
+                unknowns.push((image_index, face_result.bbox, crop));
+            }
+        }
+        Ok(unknowns)
+    }
+
+    /// Recognize all faces in `frame`.
+    ///
+    /// A face whose matching fails is logged and left out of the result, so one bad face
+    /// does not discard the others; use `run_detailed` to see per-face errors. Errors that
+    /// affect the whole frame (invalid image, detection failure) are still returned as `Err`.
+    pub async fn run(
+        &mut self,
+        frame: &mut Mat,
+        threshold: f32,
+        visualize: bool,
+    ) -> Result<Vec<MatchResult>> {
+        let results = self.run_detailed(frame, threshold, visualize).await?;
+        Ok(results
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .map(|face| face.best_match)
+            .collect())
+    }
+
+    /// Recognize all faces in `frame`, returning one entry per detected face in detector
+    /// order. Each entry is either the face's result or the error that occurred for that
+    /// face alone.
+    pub async fn run_detailed(
+        &mut self,
+        frame: &mut Mat,
+        threshold: f32,
+        visualize: bool,
+    ) -> Result<Vec<std::result::Result<FaceResult, FaceRecognitionError>>> {
+        self.prepare_matching().await?;
+        let detected_faces = self.extract_features(frame.clone()).await?;
+        let frame_size = frame.size()?;
+        self.recognize_faces(
+            &detected_faces,
+            frame_size,
+            threshold,
+            visualize.then_some(frame),
+        )
+        .await
+    }
+
+    /// Match each of `detected_faces` (extracted from a frame of `frame_size`), logging the
+    /// results and drawing them onto `visualize` if given. One entry per face, in order.
+    async fn recognize_faces(
+        &mut self,
+        detected_faces: &[DetectedFace],
+        frame_size: Size,
+        threshold: f32,
+        mut visualize: Option<&mut Mat>,
+    ) -> Result<Vec<std::result::Result<FaceResult, FaceRecognitionError>>> {
+        let mut results = Vec::with_capacity(detected_faces.len());
+        let log_matches = self.match_log_throttle.should_log();
+
+        for (i, face) in detected_faces.iter().enumerate() {
+            let result = self.recognize_face(i, face, frame_size, threshold).await;
+
+            match &result {
+                Ok(face_result) => {
+                    if log_matches {
+                        info!("Face {} best match: {}", i + 1, face_result.best_match.name);
+                    }
+
+                    if let Some(frame) = visualize.as_deref_mut() {
+                        self.visualize_face(
+                            frame,
+                            face_result.bbox,
+                            Some(&face_result.best_match.name),
+                        )?;
+                        self.annotate_with_name_scaled(frame, face, &face_result.best_match.name)?;
+                    }
+                }
+                Err(e) => warn!("Face {} could not be recognized: {}", i + 1, e),
+            }
+            results.push(result);
+        }
+
+        Ok(results)
+    }
+
+    /// Recognize all faces in `frame` without modifying it. Returns the recognized faces
+    /// (faces whose matching fails are left out, as in `run`) and, with `visualize`, an
+    /// annotated copy of the frame, so results and overlay can be stored separately.
+    pub async fn run_and_report(
+        &mut self,
+        frame: &Mat,
+        threshold: f32,
+        visualize: bool,
+    ) -> Result<(Vec<FaceResult>, Option<Mat>)> {
+        let mut annotated = frame.try_clone()?;
+        let results = self
+            .run_detailed(&mut annotated, threshold, visualize)
+            .await?;
+        let faces = results
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .collect();
+        Ok((faces, visualize.then_some(annotated)))
+    }
+
+    /// Recognize all faces in `frame` and flag identities that appear more than once.
+    ///
+    /// Two faces are linked when the cosine similarity of their embeddings exceeds
+    /// `duplicate_threshold`; linked faces are grouped transitively. A duplicate group is a
+    /// hint for liveness checks (e.g. a phone photo held next to the real face), not a
+    /// decision on its own.
+    pub async fn run_with_duplicate_detection(
+        &mut self,
+        frame: &mut Mat,
+        threshold: f32,
+        visualize: bool,
+        duplicate_threshold: f32,
+    ) -> Result<DuplicateDetection> {
+        self.prepare_matching().await?;
+        let detected_faces = self.extract_features(frame.clone()).await?;
+        let frame_size = frame.size()?;
+        let faces = self
+            .recognize_faces(
+                &detected_faces,
+                frame_size,
+                threshold,
+                visualize.then_some(frame),
+            )
+            .await?
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .collect();
+
+        // Union-find over all pairs above the duplicate threshold
+        let mut parent: Vec<usize> = (0..detected_faces.len()).collect();
+        fn root(parent: &mut [usize], mut i: usize) -> usize {
+            while parent[i] != i {
+                parent[i] = parent[parent[i]];
+                i = parent[i];
+            }
+            i
+        }
+        for i in 0..detected_faces.len() {
+            for j in (i + 1)..detected_faces.len() {
+                let score = self.face_recognizer.match_(
+                    &detected_faces[i].feature,
+                    &detected_faces[j].feature,
+                    SimilarityMetric::Cosine.dis_type(),
+                )? as f32;
+                if score > duplicate_threshold {
+                    let (ri, rj) = (root(&mut parent, i), root(&mut parent, j));
+                    parent[rj] = ri;
+                }
+            }
+        }
+
+        let mut groups: std::collections::BTreeMap<usize, Vec<usize>> =
+            std::collections::BTreeMap::new();
+        for i in 0..detected_faces.len() {
+            let r = root(&mut parent, i);
+            groups.entry(r).or_default().push(i);
+        }
+        let duplicate_groups: Vec<Vec<usize>> =
+            groups.into_values().filter(|g| g.len() > 1).collect();
+        if !duplicate_groups.is_empty() {
+            warn!(
+                "Same identity appears more than once in frame: {:?}",
+                duplicate_groups
+            );
+        }
+
+        Ok(DuplicateDetection {
+            faces,
+            duplicate_groups,
+        })
+    }
+
+    async fn recognize_face(
+        &mut self,
+        index: usize,
+        face: &DetectedFace,
+        frame_size: Size,
+        threshold: f32,
+    ) -> Result<FaceResult> {
+        let best_match = self.match_face(face, threshold).await?.best_match;
+        // Scale bounding box to the caller's frame size
+        let bbox = face.bbox_scaled(frame_size)?;
+        Ok(FaceResult {
+            index,
+            bbox,
+            best_match,
+        })
+    }
+
+    /// Whether anyone in `image` is recognized above `threshold`, for access gates that
+    /// only need a yes/no. Faces are embedded and matched one at a time, largest first,
+    /// and the search stops at the first known face instead of processing every face.
+    /// Uses plain detection at `max_size` (no tiling, upside-down retry or
+    /// full-resolution crops).
+    pub async fn any_known_face(&mut self, image: &Mat, threshold: f32) -> Result<bool> {
+        if image.empty() {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+        self.prepare_matching().await?;
+
+        let original_size = image.size()?;
+        let mut resized = Mat::default();
+        let frame = self.detection_frame(image, &mut resized)?;
+        let faces = self.detect_on(frame)?;
+
+        // Largest faces first: close faces are the most likely to be recognized
+        let mut order = Vec::with_capacity(faces.rows().max(0) as usize);
+        for i in 0..faces.rows() {
+            order.push((i, faces.at_2d::<f32>(i, 2)? * faces.at_2d::<f32>(i, 3)?));
+        }
+        order.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        for (i, _) in order {
+            let row = faces.row(i)?.try_clone()?;
+            let mut detected = None;
+            self.embed_detections(frame, &row, original_size, None, |face| {
+                detected = Some(face);
+                Ok(())
+            })?;
+            let Some(face) = detected else {
+                continue;
+            };
+            let matches = self.match_face(&face, threshold).await?;
+            if !matches.best_match.unmatched {
+                debug!("Known face found: {}", matches.best_match.name);
+                return Ok(true);
             }
+        }
+        Ok(false)
+    }
 
-            // Extract features
-            let mut feature = Mat::default();
-            match self.face_recognizer.feature(&aligned_img, &mut feature) {
-                Ok(_) => {
-                    debug!(
-                        "Feature extraction successful for face {}, feature size: {}x{}",
-                        i,
-                        feature.rows(),
-                        feature.cols()
-                    );
-                    if feature.rows() > 0 && feature.cols() > 0 {
-                        let first_few: Vec<f32> = (0..std::cmp::min(5, feature.cols()))
-                            .map(|j| *feature.at_2d::<f32>(0, j).unwrap_or(&0.0))
-                            .collect();
-                        debug!("First 5 feature values: {:?}", first_few);
-                    }
-                }
-                Err(e) => {
-                    debug!("Failed to extract features for face {}: {}", i, e);
-                    continue;
+    pub async fn run_one_face(
+        &mut self,
+        mut frame: Mat,
+        threshold: f32,
+        visualize: bool,
+    ) -> Result<MatchResult> {
+        let results = self.run(&mut frame, threshold, visualize).await?;
+
+        if results.is_empty() {
+            return Ok(self.unmatched_result(None, 0.0));
+        }
+
+        let mut best_match = &results[0];
+        for result in &results {
+            if result.score > best_match.score {
+                best_match = result;
+            }
+        }
+
+        Ok(best_match.clone())
+    }
+
+    /// Like `run_one_face`, but states explicitly whether the match was accepted.
+    ///
+    /// The three outcomes are:
+    /// - no face detected: `("Unknown", 0.0)` and `false`
+    /// - face(s) detected but none matched above the threshold: `("Unknown", s)` and
+    ///   `false`, where `s` is the closest candidate's raw score (`0.0` for an empty database)
+    /// - accepted match: the person's name and score, and `true`
+    ///
+    /// With several faces, an accepted match always wins over an unknown face, and the
+    /// higher score wins otherwise.
+    pub async fn run_one_face_decided(
+        &mut self,
+        frame: Mat,
+        threshold: f32,
+    ) -> Result<(MatchResult, bool)> {
+        let detected_faces = self.extract_features(frame).await?;
+        let mut best: Option<(MatchResult, bool)> = None;
+
+        for face in &detected_faces {
+            let match_results = self.match_face(face, threshold).await?;
+            let candidate = if match_results.best_match.unmatched {
+                let closest = match_results
+                    .results
+                    .iter()
+                    .map(|r| r.score)
+                    .fold(0.0, f32::max);
+                (
+                    MatchResult::unmatched(match_results.best_match.name, closest),
+                    false,
+                )
+            } else {
+                (match_results.best_match, true)
+            };
+
+            let better = match &best {
+                None => true,
+                Some((current, accepted)) => {
+                    (candidate.1, candidate.0.score) > (*accepted, current.score)
                 }
+            };
+            if better {
+                best = Some(candidate);
             }
+        }
 
-            detected_faces.push(DetectedFace::new_with_detection_size(
-                "Unknown".to_string(),
-                face_row.try_clone()?,
-                feature.try_clone()?,
-                original_size,
-                frame.size()?, // Current resized frame size
-            ));
+        Ok(best.unwrap_or_else(|| (self.unmatched_result(None, 0.0), false)))
+    }
+
+    /// Similarity of the faces in two images.
+    ///
+    /// Only the primary face of each image is compared: the one with the largest bounding
+    /// box, so bystanders in the background are ignored. Returns `NoFaceDetected` if
+    /// either image contains no face. The score's direction depends on `metric`.
+    pub async fn compare_images(
+        &mut self,
+        a: &Mat,
+        b: &Mat,
+        metric: SimilarityMetric,
+    ) -> Result<f32> {
+        let feature_a = self.primary_face_feature(a, "first image").await?;
+        let feature_b = self.primary_face_feature(b, "second image").await?;
+        let score = self
+            .face_recognizer
+            .match_(&feature_a, &feature_b, metric.dis_type())?;
+        Ok(score as f32)
+    }
+
+    async fn primary_face_feature(&mut self, frame: &Mat, label: &str) -> Result<Mat> {
+        Ok(self.primary_face(frame, label).await?.feature)
+    }
+
+    /// The largest face in `frame`, see `primary_face_feature`
+    async fn primary_face(&mut self, frame: &Mat, label: &str) -> Result<DetectedFace> {
+        let detected_faces = self.extract_features(frame.clone()).await?;
+        let mut largest: Option<(i32, DetectedFace)> = None;
+        for face in detected_faces {
+            let bbox = face.bbox()?;
+            let area = bbox.width * bbox.height;
+            if largest.as_ref().is_none_or(|(best, _)| area > *best) {
+                largest = Some((area, face));
+            }
+        }
+        match largest {
+            Some((_, face)) => Ok(face),
+            None => Err(FaceRecognitionError::NoFaceDetected(label.to_string())),
         }
+    }
 
-        Ok(detected_faces)
+    #[allow(dead_code)]
+    fn annotate_with_name(&self, frame: &mut Mat, face: &DetectedFace, name: &str) -> Result<()> {
+        let bbox = face.bbox()?;
+
+        // Text parameters
+        let font_face = FONT_HERSHEY_SIMPLEX;
+        let font_scale = 0.8;
+        let thickness = 2;
+        let mut baseline = 0;
+
+        let text_size = get_text_size(name, font_face, font_scale, thickness, &mut baseline)?;
+        let text_x = bbox.x + (bbox.width - text_size.width) / 2;
+        let text_y = std::cmp::max(bbox.y - text_size.height - 5, 0);
+
+        // Draw background rectangle
+        let bg_rect = Rect2i::new(
+            text_x - 2,
+            text_y - 2,
+            text_size.width + 4,
+            text_size.height + 4,
+        );
+
+        let bg_color = Scalar::new(0.0, 0.0, 0.0, 0.0); // Black background
+        rectangle(frame, bg_rect, bg_color, -1, LINE_8, 0)?;
+
+        // Draw text
+        let text_color = Scalar::new(255.0, 255.0, 255.0, 0.0); // White text
+        let text_pos = Point::new(text_x, text_y + text_size.height);
+        put_text(
+            frame, name, text_pos, font_face, font_scale, text_color, thickness, LINE_8, false,
+        )?;
+
+        Ok(())
     }
 
-    fn resize_frame(&self, frame: &mut Mat, keep_aspect_ratio: bool) -> Result<()> {
-        if self.max_size <= 0 {
-            return Ok(()); // No resizing requested
+    fn annotate_with_name_scaled(
+        &self,
+        frame: &mut Mat,
+        face: &DetectedFace,
+        name: &str,
+    ) -> Result<()> {
+        let bbox = face.bbox_scaled(frame.size()?)?;
+        self.draw_label(frame, bbox, name)
+    }
+
+    /// Draw `bbox` and an arbitrary `text` label above it, in the same style `run` uses
+    /// for names. `bbox` is in `frame` coordinates, e.g. `FaceResult::bbox` from
+    /// `run_detailed` on the same frame. With identity colors the box color follows `text`.
+    pub fn annotate(&self, frame: &mut Mat, bbox: Rect2i, text: &str) -> Result<()> {
+        self.visualize_face(frame, bbox, Some(text))?;
+        self.draw_label(frame, bbox, text)
+    }
+
+    fn draw_label(&self, frame: &mut Mat, bbox: Rect2i, text: &str) -> Result<()> {
+        // Text parameters - scale font based on image size
+        let font_face = FONT_HERSHEY_SIMPLEX;
+        let base_font_scale = 0.8;
+        // Scale font based on image width - larger images get bigger text
+        let font_scale = base_font_scale * (frame.cols() as f64 / 800.0).max(0.5).min(3.0);
+        let thickness = ((frame.cols() as f64 / 800.0).max(1.0).min(4.0)) as i32;
+        let mut baseline = 0;
+
+        let text_size = get_text_size(text, font_face, font_scale, thickness, &mut baseline)?;
+        let text_x = bbox.x + (bbox.width - text_size.width) / 2;
+        let text_y = std::cmp::max(bbox.y - text_size.height - 5, 0);
+
+        // Draw background rectangle
+        let bg_rect = Rect2i::new(
+            text_x - 2,
+            text_y - 2,
+            text_size.width + 4,
+            text_size.height + 4,
+        );
+
+        let bg_color = Scalar::new(0.0, 0.0, 0.0, 0.0); // Black background
+        rectangle(frame, bg_rect, bg_color, -1, LINE_8, 0)?;
+
+        // Draw text
+        let text_color = Scalar::new(255.0, 255.0, 255.0, 0.0); // White text
+        let text_pos = Point::new(text_x, text_y + text_size.height);
+        put_text(
+            frame, text, text_pos, font_face, font_scale, text_color, thickness, LINE_8, false,
+        )?;
+
+        Ok(())
+    }
+
+    /// Decode an encoded image (JPEG, PNG, ...) and run recognition on it. Subject to the
+    /// limit of `set_max_decode_pixels`.
+    pub async fn run_from_bytes(
+        &mut self,
+        bytes: &[u8],
+        threshold: f32,
+    ) -> Result<Vec<MatchResult>> {
+        let mut frame = self.decode_image(bytes)?;
+        self.run(&mut frame, threshold, false).await
+    }
+
+    /// Decode `bytes` with `imdecode`, enforcing `max_decode_pixels` from the header before
+    /// decoding and again on the decoded frame
+    fn decode_image(&self, bytes: &[u8]) -> Result<Mat> {
+        let max_pixels = self.max_decode_pixels;
+        if max_pixels > 0 {
+            let (width, height) =
+                encoded_image_size(bytes).ok_or(FaceRecognitionError::UnsupportedImageFormat)?;
+            if width.saturating_mul(height) > max_pixels {
+                return Err(FaceRecognitionError::ImageTooLarge {
+                    width,
+                    height,
+                    max_pixels,
+                });
+            }
         }
 
+        let buf = opencv::core::Vector::<u8>::from_slice(bytes);
+        let frame = imdecode(&buf, IMREAD_COLOR)?;
         if frame.empty() {
             return Err(FaceRecognitionError::InvalidImage);
         }
+        let (width, height) = (frame.cols() as u64, frame.rows() as u64);
+        if max_pixels > 0 && width * height > max_pixels {
+            // `frame` is dropped here, freeing the decoded pixels right away
+            return Err(FaceRecognitionError::ImageTooLarge {
+                width,
+                height,
+                max_pixels,
+            });
+        }
+        Ok(frame)
+    }
 
-        let cols = frame.cols();
-        let rows = frame.rows();
+    /// Names of all persons in the loaded database, sorted alphabetically
+    pub async fn list_persons(&self) -> Vec<String> {
+        sorted_person_names(&**self.features_map.read().await)
+    }
 
-        if keep_aspect_ratio {
-            if cols > self.max_size || rows > self.max_size {
-                let max_dim = std::cmp::max(cols, rows);
-                let scale = self.max_size as f64 / max_dim as f64;
-                let new_size =
-                    Size::new((cols as f64 * scale) as i32, (rows as f64 * scale) as i32);
-                let mut resized = Mat::default();
-                opencv::imgproc::resize(
-                    frame,
-                    &mut resized,
-                    new_size,
-                    0.0,
-                    0.0,
-                    opencv::imgproc::INTER_LINEAR,
-                )?;
-                *frame = resized;
+    /// The `k` persons most similar to a raw embedding, best first, scored like
+    /// `find_best_match` (reference weights, aggregation and quantized storage apply;
+    /// ensemble recognizers don't, as only the primary embedding is given). Useful to
+    /// probe the database without an image, e.g. to check exported/imported features. The
+    /// embedding must have the database's dimension.
+    pub async fn find_top_matches(
+        &mut self,
+        embedding: &[f32],
+        k: usize,
+    ) -> Result<Vec<MatchResult>> {
+        let db_dim = self
+            .features_map
+            .read()
+            .await
+            .iter()
+            .find_map(|(_, features)| features.first().map(|stored| stored.dim() as i32))
+            .unwrap_or_else(|| self.feature_dim());
+        if embedding.len() as i32 != db_dim {
+            return Err(FaceRecognitionError::FeatureDimensionMismatch {
+                expected: db_dim,
+                actual: embedding.len() as i32,
+            });
+        }
+
+        let probe = vec_to_feature(embedding)?;
+        let mapping = self.confidence_mapping;
+        Ok(self
+            .top_persons(&**self.features_map.read().await, &probe, &[], k, &|_| {
+                false
+            })?
+            .into_iter()
+            .map(|(name, score)| MatchResult::new(name, score).with_confidence(mapping))
+            .collect())
+    }
+
+    /// Why the primary (largest) face in `image` was, or was not, recognized: the closest
+    /// persons, best first, with their score, the threshold that applied and the gap to
+    /// it, e.g. "closest was Alice at 0.38, threshold 0.40". Scores are computed exactly as
+    /// by `find_best_match`, including the ensemble. Returns `NoFaceDetected` if the image
+    /// has no face, and no candidates if the face is below the minimum probe eye distance
+    /// (see `set_min_probe_eye_distance`).
+    pub async fn explain(&mut self, image: &Mat, threshold: f32) -> Result<Vec<ExplainEntry>> {
+        let probe = self.primary_face(image, "probe image").await?;
+        if self.probe_too_small(&probe)? {
+            return Ok(Vec::new());
+        }
+        let candidates = self.top_persons(
+            &**self.features_map.read().await,
+            &probe.feature,
+            &probe.ensemble_features,
+            EXPLAIN_CANDIDATES,
+            &|_| false,
+        )?;
+        Ok(candidates
+            .into_iter()
+            .map(|(name, score)| {
+                let threshold = self.person_threshold(&name, threshold);
+                ExplainEntry {
+                    gap: threshold - score,
+                    accepted: score > threshold,
+                    name,
+                    score,
+                    threshold,
+                }
+            })
+            .collect())
+    }
+
+    /// Add the faces found in one image to `person`'s reference features without reloading
+    /// the database. The person is created if needed. Returns the number of features
+    /// stored; faces below the minimum enrollment eye distance are skipped.
+    pub async fn enroll_file<P: AsRef<Path>>(
+        &mut self,
+        person: &str,
+        image_path: P,
+    ) -> Result<usize> {
+        let image_path = image_path.as_ref();
+        let img = image_path
+            .to_str()
+            .map(|p| imread(p, IMREAD_COLOR))
+            .transpose()?
+            .filter(|img| !img.empty())
+            .ok_or(FaceRecognitionError::InvalidImage)?;
+
+        let detected_faces = self.extract_enrollment_features(img.clone()).await?;
+        self.normalize_enrollment_image(image_path, &img, &detected_faces);
+
+        let person = normalize_person_name(person);
+        let weight = read_weight_sidecar(image_path);
+        let captured_at = self
+            .track_capture_time
+            .then(|| capture_time(image_path))
+            .flatten();
+        let mut features_map = self.features_map.write().await;
+        let mut sequence = features_map
+            .iter()
+            .filter_map(|(_, features)| features.iter().map(|f| f.sequence + 1).max())
+            .max()
+            .unwrap_or(0);
+        let mut stored_total = features_map.feature_count();
+        let mut rejected = 0;
+        let mut enrolled = 0;
+        for face in detected_faces {
+            if self.min_enroll_eye_distance > 0.0
+                && face.inter_ocular_distance()? < self.min_enroll_eye_distance
+            {
+                warn!(
+                    "Rejecting face in {}: inter-ocular distance below {:.1}px",
+                    image_path.display(),
+                    self.min_enroll_eye_distance
+                );
+                continue;
             }
-        } else {
-            let new_size = Size::new(self.max_size, self.max_size);
-            let mut resized = Mat::default();
+            let stored = StoredFeature {
+                quality: face.score()?,
+                feature: face.feature,
+                source: Some(image_path.to_path_buf()),
+                sequence,
+                quantized: None,
+                half: None,
+                ensemble_features: face.ensemble_features,
+                weight,
+                captured_at,
+                mirrored: false,
+            };
+            if let Some(reason) = feature_defect(&stored) {
+                self.check_invalid_features(&[(person.clone(), 0, reason)])?;
+                continue;
+            }
+            sequence += 1;
+            let mut references = vec![stored];
+            if let Some(flipped) = face.flipped_feature {
+                references.push(mirrored_reference(&references[0], flipped, sequence));
+                sequence += 1;
+            }
+            rejected += self.reject_over_cap(&mut references, stored_total);
+            if references.is_empty() {
+                continue;
+            }
+            stored_total += references.len();
+            for mut stored in references {
+                compact_feature(&mut stored, self.quantize_features, self.feature_precision)?;
+                features_map.insert(&person, stored);
+            }
+            enrolled += 1;
+        }
+        if rejected > 0 {
+            warn!(
+                "Feature cap of {} reached, {} features of {} not stored",
+                self.max_total_features,
+                rejected,
+                image_path.display()
+            );
+        }
+        if self.max_total_features > 0 && stored_total > self.max_total_features {
+            let mut features = features_map.take_all();
+            let evicted = enforce_feature_cap(
+                &mut features,
+                self.max_total_features,
+                self.feature_cap_policy,
+            );
+            features_map.replace_all(features);
+            if evicted > 0 {
+                warn!(
+                    "Feature cap of {} reached, {} features dropped",
+                    self.max_total_features, evicted
+                );
+            }
+        }
+
+        info!(
+            "Enrolled {} faces from {} for {}",
+            enrolled,
+            image_path.display(),
+            person
+        );
+        Ok(enrolled)
+    }
+
+    /// Write the face-centered crop of an enrollment image according to the enrollment
+    /// normalization setting. Failures are logged and do not affect enrollment.
+    fn normalize_enrollment_image(&self, img_path: &Path, img: &Mat, faces: &[DetectedFace]) {
+        if self.enrollment_normalization == EnrollmentNormalization::Off {
+            return;
+        }
+        if let Err(e) = self.write_normalized_enrollment(img_path, img, faces) {
+            warn!(
+                "Could not write normalized enrollment image for {}: {}",
+                img_path.display(),
+                e
+            );
+        }
+    }
+
+    fn write_normalized_enrollment(
+        &self,
+        img_path: &Path,
+        img: &Mat,
+        faces: &[DetectedFace],
+    ) -> Result<()> {
+        if faces.len() != 1
+            || (img.cols() <= NORMALIZED_ENROLLMENT_SIZE
+                && img.rows() <= NORMALIZED_ENROLLMENT_SIZE)
+        {
+            // Group photos and images that are already small are left alone
+            return Ok(());
+        }
+
+        // Keep a margin around the face so the detector still finds it on reload
+        let bbox = faces[0].bbox_original()?;
+        let side = bbox.width.max(bbox.height) * 2;
+        let (cx, cy) = (bbox.x + bbox.width / 2, bbox.y + bbox.height / 2);
+        let x0 = (cx - side / 2).clamp(0, img.cols());
+        let y0 = (cy - side / 2).clamp(0, img.rows());
+        let x1 = (cx + side / 2).clamp(0, img.cols());
+        let y1 = (cy + side / 2).clamp(0, img.rows());
+        if x1 <= x0 || y1 <= y0 {
+            return Err(FaceRecognitionError::InvalidImage);
+        }
+        let roi = Mat::roi(img, Rect2i::new(x0, y0, x1 - x0, y1 - y0))?;
+
+        let scale = NORMALIZED_ENROLLMENT_SIZE as f64 / (x1 - x0).max(y1 - y0) as f64;
+        let mut crop = Mat::default();
+        if scale < 1.0 {
             opencv::imgproc::resize(
-                frame,
-                &mut resized,
-                new_size,
-                0.0,
-                0.0,
-                opencv::imgproc::INTER_LINEAR,
+                &roi,
+                &mut crop,
+                Size::default(),
+                scale,
+                scale,
+                opencv::imgproc::INTER_AREA,
+            )?;
+        } else {
+            crop = roi.try_clone()?;
+        }
+
+        let target = self.enrollment_normalization.output_path(img_path);
+        let target_str = target.to_str().ok_or(FaceRecognitionError::InvalidImage)?;
+        if !imwrite(target_str, &crop, &opencv::core::Vector::new())? {
+            return Err(FaceRecognitionError::Io(std::io::Error::other(format!(
+                "failed to write {}",
+                target.display()
+            ))));
+        }
+        debug!("Wrote normalized enrollment image {}", target.display());
+        Ok(())
+    }
+
+    /// Render a contact sheet of all reference faces of `name` into `out`, to spot
+    /// mislabeled references at a glance. Each stored feature is shown as the face crop
+    /// from its source image (the face whose embedding is closest to the stored one);
+    /// features without a readable source are shown as gray tiles. Returns the number of
+    /// tiles.
+    pub async fn render_person_montage<P: AsRef<Path>>(
+        &mut self,
+        name: &str,
+        out: P,
+    ) -> Result<usize> {
+        let name = normalize_person_name(name);
+        let references: Vec<(Mat, Option<PathBuf>)> = {
+            let features_map = self.features_map.read().await;
+            let features = features_map
+                .get(&name)
+                .ok_or_else(|| FaceRecognitionError::PersonNotFound(name.clone()))?;
+            features
+                .iter()
+                .map(|f| Ok((f.feature_mat()?, f.source.clone())))
+                .collect::<Result<_>>()?
+        };
+        if references.is_empty() {
+            return Err(FaceRecognitionError::PersonNotFound(name));
+        }
+
+        let placeholder = Mat::new_rows_cols_with_default(
+            RECOGNIZER_INPUT_SIZE.height,
+            RECOGNIZER_INPUT_SIZE.width,
+            CV_8UC3,
+            Scalar::all(128.0),
+        )?;
+        let mut tiles = Vec::with_capacity(references.len());
+        for (feature, source) in &references {
+            let tile = match source {
+                Some(source) => self
+                    .reference_crop(source, feature)
+                    .await
+                    .unwrap_or_else(|e| {
+                        debug!("No montage crop from {}: {}", source.display(), e);
+                        None
+                    }),
+                None => None,
+            };
+            tiles.push(match tile {
+                Some(tile) => tile,
+                None => placeholder.try_clone()?,
+            });
+        }
+
+        // Square-ish grid, empty cells filled with black
+        let columns = (tiles.len() as f64).sqrt().ceil() as usize;
+        let blank = Mat::new_rows_cols_with_default(
+            RECOGNIZER_INPUT_SIZE.height,
+            RECOGNIZER_INPUT_SIZE.width,
+            CV_8UC3,
+            Scalar::all(0.0),
+        )?;
+        let mut rows = opencv::core::Vector::<Mat>::new();
+        for chunk in tiles.chunks(columns) {
+            let mut row_tiles = opencv::core::Vector::<Mat>::new();
+            for tile in chunk {
+                row_tiles.push(tile.try_clone()?);
+            }
+            for _ in chunk.len()..columns {
+                row_tiles.push(blank.try_clone()?);
+            }
+            let mut row = Mat::default();
+            opencv::core::hconcat(&row_tiles, &mut row)?;
+            rows.push(row);
+        }
+        let mut montage = Mat::default();
+        opencv::core::vconcat(&rows, &mut montage)?;
+
+        let out = out.as_ref();
+        let out_str = out.to_str().ok_or(FaceRecognitionError::InvalidImage)?;
+        imwrite(out_str, &montage, &opencv::core::Vector::new())?;
+        info!(
+            "Wrote montage of {} references of {} to {}",
+            tiles.len(),
+            name,
+            out.display()
+        );
+        Ok(tiles.len())
+    }
+
+    /// Crop of the face in `source` whose embedding is closest to `feature`, resized to
+    /// the recognizer input size
+    async fn reference_crop(&mut self, source: &Path, feature: &Mat) -> Result<Option<Mat>> {
+        let img = match source.to_str().map(|p| imread(p, IMREAD_COLOR)) {
+            Some(Ok(img)) if !img.empty() => img,
+            _ => return Ok(None),
+        };
+
+        let mut best: Option<(f64, Rect2i)> = None;
+        for face in self.extract_features(img.clone()).await? {
+            let score = self.face_recognizer.match_(
+                &face.feature,
+                feature,
+                SimilarityMetric::Cosine.dis_type(),
             )?;
-            *frame = resized;
+            if best.is_none_or(|(best_score, _)| score > best_score) {
+                best = Some((score, face.bbox_original()?));
+            }
         }
+        let Some((_, bbox)) = best else {
+            return Ok(None);
+        };
 
-        Ok(())
+        let bbox = bbox & Rect2i::new(0, 0, img.cols(), img.rows());
+        if bbox.width <= 0 || bbox.height <= 0 {
+            return Ok(None);
+        }
+        let roi = Mat::roi(&img, bbox)?;
+        let mut crop = Mat::default();
+        opencv::imgproc::resize(
+            &roi,
+            &mut crop,
+            RECOGNIZER_INPUT_SIZE,
+            0.0,
+            0.0,
+            opencv::imgproc::INTER_AREA,
+        )?;
+        Ok(Some(crop))
     }
 
-    fn visualize_face(&self, frame: &mut Mat, bbox: Rect2i) -> Result<()> {
-        let color = Scalar::new(0.0, 255.0, 0.0, 0.0); // Green
-        rectangle(frame, bbox, color, 2, LINE_8, 0)?;
-        Ok(())
+    /// Whether `name` is a person in the loaded database (surrounding whitespace ignored)
+    pub async fn has_person(&self, name: &str) -> bool {
+        self.features_map
+            .read()
+            .await
+            .contains(&normalize_person_name(name))
     }
 
-    async fn find_best_match(
-        &mut self,
-        face_feature: &Mat,
-        threshold: f32,
-    ) -> Result<MatchResults> {
-        let features_map = self.features_map.read().await;
+    /// Source image and capture time of each reference of `name`, in storage order, or
+    /// `None` if the person is unknown. Capture times are `None` unless recorded, see
+    /// `set_track_capture_time`.
+    pub async fn reference_capture_times(
+        &self,
+        name: &str,
+    ) -> Option<Vec<(Option<PathBuf>, Option<SystemTime>)>> {
+        self.features_map
+            .read()
+            .await
+            .get(&normalize_person_name(name))
+            .map(|features| {
+                features
+                    .iter()
+                    .map(|stored| (stored.source.clone(), stored.captured_at))
+                    .collect()
+            })
+    }
 
-        let mut results = Vec::new();
-        let mut best_match = MatchResult::new("Unknown".to_string(), 0.0);
+    /// Remove every reference captured before `cutoff`. References without a recorded
+    /// capture time are kept; persons left without references stay in the database.
+    /// Returns the number of references removed.
+    ///
+    /// Only the in-memory database changes: the image files stay in the database folder,
+    /// so the next reload, including one triggered by the watcher, brings the references
+    /// back. Move or delete the files listed by `reference_capture_times` to make the
+    /// pruning permanent.
+    pub async fn prune_references_before(&mut self, cutoff: SystemTime) -> usize {
+        let mut features_map = self.features_map.write().await;
+        let mut features = features_map.take_all();
+        let mut removed = 0;
+        for (name, references) in features.iter_mut() {
+            let before = references.len();
+            references.retain(|stored| stored.captured_at.is_none_or(|time| time >= cutoff));
+            if references.len() < before {
+                info!(
+                    "Pruned {} old references of {}",
+                    before - references.len(),
+                    name
+                );
+                removed += before - references.len();
+            }
+        }
+        features_map.replace_all(features);
+        removed
+    }
 
-        for (person_name, features) in features_map.iter() {
-            for (feature_idx, feature) in features.iter().enumerate() {
-                let score = self.face_recognizer.match_(
-                    face_feature,
-                    feature,
-                    opencv::objdetect::FaceRecognizerSF_DisType::FR_COSINE as i32,
-                )? as f32;
-                results.push(MatchResult::new(person_name.clone(), score));
+    /// Number of reference features stored for `name`, or `None` if the person is unknown
+    pub async fn person_feature_count(&self, name: &str) -> Option<usize> {
+        self.features_map
+            .read()
+            .await
+            .get(&normalize_person_name(name))
+            .map(|features| features.len())
+    }
 
-                // Debug feature comparison
-                if feature_idx == 0 {
-                    // Only debug the first feature per person to avoid spam
-                    let query_first_5: Vec<f32> = (0..5)
-                        .map(|j| *face_feature.at_2d::<f32>(0, j).unwrap_or(&0.0))
-                        .collect();
-                    let db_first_5: Vec<f32> = (0..5)
-                        .map(|j| *feature.at_2d::<f32>(0, j).unwrap_or(&0.0))
-                        .collect();
-                    debug!(
-                        "Person {}, feature #{}, score: {}",
-                        person_name, feature_idx, score
-                    );
-                    debug!("  Query: {:?}", query_first_5);
-                    debug!("  DB:    {:?}", db_first_5);
-                } else {
-                    debug!(
-                        "Person {}, feature #{}, score: {}",
-                        person_name, feature_idx, score
-                    );
-                }
+    /// Load a database shipped as a `.zip`, `.tar`, `.tar.gz` or `.tgz` archive.
+    ///
+    /// The archive must contain the usual `<person>/<image>` layout, either at its root or
+    /// inside a single top-level folder. It is extracted to a temporary directory that is
+    /// removed after loading, so the database path is cleared afterwards and the archive
+    /// cannot be watched for changes. For the same reason the loaded features have no
+    /// source image: `reembed` keeps them unchanged, and source-based calls such as
+    /// `set_reference_weight` or leave-one-out evaluation do not see them.
+    pub async fn load_persons_db_archive<P: AsRef<Path>>(
+        &mut self,
+        archive: P,
+    ) -> Result<LoadReport> {
+        let archive = archive.as_ref();
+        let name = archive
+            .file_name()
+            .and_then(|n| n.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let temp_dir = tempfile::tempdir()?;
+        let invalid = |e: &dyn std::fmt::Display| {
+            FaceRecognitionError::InvalidArchive(format!("{}: {}", archive.display(), e))
+        };
 
-                if score > best_match.score && score > threshold {
-                    best_match = MatchResult::new(person_name.clone(), score);
-                }
-            }
+        info!("Extracting database archive: {}", archive.display());
+        let file = std::fs::File::open(archive)?;
+        if name.ends_with(".zip") {
+            zip::ZipArchive::new(file)
+                .and_then(|mut zip| zip.extract(temp_dir.path()))
+                .map_err(|e| invalid(&e))?;
+        } else if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+            tar::Archive::new(flate2::read::GzDecoder::new(file))
+                .unpack(temp_dir.path())
+                .map_err(|e| invalid(&e))?;
+        } else if name.ends_with(".tar") {
+            tar::Archive::new(file)
+                .unpack(temp_dir.path())
+                .map_err(|e| invalid(&e))?;
+        } else {
+            return Err(invalid(
+                &"unsupported archive type, expected .zip, .tar or .tar.gz",
+            ));
         }
 
-        Ok(MatchResults {
-            results,
-            best_match,
-        })
+        let root = archive_db_root(temp_dir.path())?;
+        if !root
+            .read_dir()?
+            .any(|e| e.map(|e| e.path().is_dir()).unwrap_or(false))
+        {
+            return Err(invalid(&"no person folders found"));
+        }
+
+        let report = self.load_persons_db(&root, true, false).await?;
+        *self.db_path.write().await = None;
+        // The extracted images are deleted with `temp_dir`
+        let mut features_map = self.features_map.write().await;
+        let mut features = features_map.take_all();
+        for stored in features.values_mut().flatten() {
+            stored.source = None;
+        }
+        features_map.replace_all(features);
+        Ok(report)
     }
 
-    pub async fn run(
+    /// Load a database from a flat image folder plus a manifest mapping file names (relative
+    /// to `images_dir`) to persons, instead of one folder per person.
+    ///
+    /// The manifest is either a CSV file with `file,person` lines (an optional header and
+    /// `#` comments are skipped) or a JSON file holding an object `{"file": "person"}` or
+    /// an array of `{"file": ..., "person": ...}` entries. Manifest entries whose file does
+    /// not exist are listed in `LoadReport::missing_files`. The database is not watched,
+    /// so the database path is cleared.
+    pub async fn load_persons_db_manifest<P, Q>(
         &mut self,
-        frame: &mut Mat,
-        threshold: f32,
-        visualize: bool,
-    ) -> Result<Vec<MatchResult>> {
-        let frame_for_detection = if visualize {
-            frame.clone()
-        } else {
-            frame.clone()
+        images_dir: P,
+        manifest_path: Q,
+    ) -> Result<LoadReport>
+    where
+        P: AsRef<Path>,
+        Q: AsRef<Path>,
+    {
+        let images_dir = images_dir.as_ref();
+        let entries = read_manifest(manifest_path.as_ref())?;
+        info!(
+            "Loading {} manifest entries from {}",
+            entries.len(),
+            manifest_path.as_ref().display()
+        );
+
+        *self.db_load_status.write().await = DbLoadStatus::Loading;
+        let (new_features, report) = match self.read_manifest_entries(images_dir, entries).await {
+            Ok(loaded) => loaded,
+            Err(e) => return Err(self.fail_load(e).await),
         };
 
-        let detected_faces = self.extract_features(frame_for_detection).await?;
-        let mut results = Vec::new();
+        self.features_map.write().await.replace_all(new_features);
+        *self.db_path.write().await = None;
+        *self.db_load_status.write().await = DbLoadStatus::Loaded;
+        info!(
+            "Manifest loading completed: {} persons, {} features, {} missing files",
+            report.persons_loaded,
+            report.features_loaded,
+            report.missing_files.len()
+        );
+        self.last_load_report = report.clone();
+        Ok(report)
+    }
 
-        for (i, face) in detected_faces.iter().enumerate() {
-            let match_results = self.find_best_match(&face.feature, threshold).await?;
-            let best = match_results.best_match;
+    /// Read the images of manifest `entries` below `images_dir` into a new feature map,
+    /// without touching the current database
+    async fn read_manifest_entries(
+        &mut self,
+        images_dir: &Path,
+        entries: Vec<(String, String)>,
+    ) -> Result<(HashMap<String, Vec<StoredFeature>>, LoadReport)> {
+        let mut report = LoadReport::default();
+        let entries = filter_persons(
+            entries,
+            self.person_filter.as_deref(),
+            |(_, person)| person.clone(),
+            &mut report,
+        );
+        let mut sequence: u64 = 0;
+        let mut stored_total = 0;
+        let mut new_features: HashMap<String, Vec<StoredFeature>> = HashMap::new();
+        // Normalized name key -> name the features are stored under
+        let mut seen_names: HashMap<String, String> = HashMap::new();
 
-            info!("Face {} best match: {}", i + 1, best.name);
-            results.push(best.clone());
+        for (file, person) in entries {
+            let img_path = images_dir.join(&file);
+            if !img_path.is_file() {
+                warn!("Manifest references missing file {}", img_path.display());
+                report.missing_files.push(img_path);
+                continue;
+            }
 
-            if visualize {
-                // Scale bounding box to match the visualization frame size
-                if let Ok(bbox) = face.bbox_scaled(frame.size()?) {
-                    self.visualize_face(frame, bbox)?;
-                    self.annotate_with_name_scaled(frame, face, &best.name)?;
-                }
+            let person = normalize_person_name(&person);
+            let name = seen_names
+                .entry(person_name_key(&person))
+                .or_insert_with(|| person.clone())
+                .clone();
+            if self.reject_new_capacity(stored_total) == Some(0) {
+                report.skipped_over_cap.push(img_path);
+                continue;
             }
+            let mut features = self
+                .load_enrollment_image(&name, &img_path, false, &mut report, &mut sequence)
+                .await?;
+            report.features_evicted += self.reject_over_cap(&mut features, stored_total);
+            stored_total += features.len();
+            new_features.entry(name).or_default().extend(features);
         }
 
-        Ok(results)
+        report.invalid_features = self.drop_invalid_features(&mut new_features)?;
+        report.persons_loaded = new_features.len();
+        report.features_loaded = new_features.values().map(Vec::len).sum();
+        report.empty_persons = new_features
+            .iter()
+            .filter(|(_, features)| features.is_empty())
+            .map(|(name, _)| name.clone())
+            .collect();
+        report.empty_persons.sort();
+        if self.max_total_features > 0 {
+            report.features_evicted += enforce_feature_cap(
+                &mut new_features,
+                self.max_total_features,
+                self.feature_cap_policy,
+            );
+        }
+        if self.detect_cross_person_duplicates {
+            report.cross_person_duplicates = find_cross_person_duplicates(&new_features)?;
+        }
+        compact_features(
+            &mut new_features,
+            self.quantize_features,
+            self.feature_precision,
+        )?;
+        Ok((new_features, report))
     }
 
-    pub async fn run_one_face(
+    /// Recognize faces in every frame of `input` and write the annotated frames to
+    /// `output`, keeping the input frame rate and size. Returns the number of frames written.
+    ///
+    /// The codec follows the output extension: `.avi` uses MJPG, everything else `mp4v`.
+    pub async fn annotate_video(
         &mut self,
-        mut frame: Mat,
+        input: &Path,
+        output: &Path,
         threshold: f32,
-        visualize: bool,
-    ) -> Result<MatchResult> {
-        let results = self.run(&mut frame, threshold, visualize).await?;
+    ) -> Result<usize> {
+        let input_str = input.to_str().ok_or_else(|| {
+            FaceRecognitionError::Video(format!("invalid path {}", input.display()))
+        })?;
+        let output_str = output.to_str().ok_or_else(|| {
+            FaceRecognitionError::Video(format!("invalid path {}", output.display()))
+        })?;
 
-        if results.is_empty() {
-            return Ok(MatchResult::new("Unknown".to_string(), 0.0));
+        let mut capture = VideoCapture::from_file(input_str, CAP_ANY)?;
+        if !capture.is_opened()? {
+            return Err(FaceRecognitionError::Video(format!(
+                "cannot open input video {}",
+                input.display()
+            )));
         }
+        let fps = match capture.get(CAP_PROP_FPS)? {
+            fps if fps > 0.0 => fps,
+            _ => 25.0,
+        };
 
-        let mut best_match = &results[0];
-        for result in &results {
-            if result.score > best_match.score {
-                best_match = result;
+        let is_avi = output
+            .extension()
+            .and_then(|e| e.to_str())
+            .is_some_and(|e| e.eq_ignore_ascii_case("avi"));
+        let fourcc = if is_avi {
+            VideoWriter::fourcc('M', 'J', 'P', 'G')?
+        } else {
+            VideoWriter::fourcc('m', 'p', '4', 'v')?
+        };
+
+        // The writer is opened on the first frame so its size matches the decoded frames
+        let mut writer: Option<VideoWriter> = None;
+        let mut frame = Mat::default();
+        let mut frames_written = 0;
+        let mut frames_skipped = 0;
+        // Last processed frame and its results, for motion skipping
+        let mut last_processed: Option<(Mat, Vec<FaceResult>)> = None;
+        while capture.read(&mut frame)? {
+            if frame.empty() {
+                break;
+            }
+
+            if writer.is_none() {
+                let new_writer = VideoWriter::new(output_str, fourcc, fps, frame.size()?, true)?;
+                if !new_writer.is_opened()? {
+                    return Err(FaceRecognitionError::Video(format!(
+                        "cannot open output video {} (codec not available?)",
+                        output.display()
+                    )));
+                }
+                writer = Some(new_writer);
+            }
+
+            let static_frame = match &last_processed {
+                Some((previous, _)) if self.motion_skip_threshold > 0.0 => {
+                    frame_motion(previous, &frame)? < self.motion_skip_threshold
+                }
+                _ => false,
+            };
+            if static_frame {
+                for face in last_processed.iter().flat_map(|(_, faces)| faces) {
+                    self.annotate(&mut frame, face.bbox, &face.best_match.name)?;
+                }
+                frames_skipped += 1;
+            } else {
+                let original = (self.motion_skip_threshold > 0.0)
+                    .then(|| frame.try_clone())
+                    .transpose()?;
+                let faces = self
+                    .run_detailed(&mut frame, threshold, true)
+                    .await?
+                    .into_iter()
+                    .filter_map(|result| result.ok())
+                    .collect();
+                last_processed = original.map(|original| (original, faces));
+            }
+            if let Some(writer) = writer.as_mut() {
+                writer.write(&frame)?;
             }
+            frames_written += 1;
         }
 
-        Ok(best_match.clone())
+        if let Some(mut writer) = writer {
+            writer.release()?;
+        }
+        info!(
+            "Annotated {} frames from {} into {} ({} static frames skipped)",
+            frames_written,
+            input.display(),
+            output.display(),
+            frames_skipped
+        );
+        Ok(frames_written)
     }
 
-    #[allow(dead_code)]
-    fn annotate_with_name(&self, frame: &mut Mat, face: &DetectedFace, name: &str) -> Result<()> {
-        let bbox = face.bbox()?;
+    /// Recompute all stored features with the current recognizer, e.g. after a model
+    /// upgrade, by re-reading each source image once instead of re-walking the database
+    /// folder. Person/image associations are kept; images that can no longer be read or
+    /// yield no face lose their features and are listed in the report. As in
+    /// `enroll_file`, faces below the minimum enrollment eye distance are skipped and the
+    /// feature cap applies.
+    pub async fn reembed(&mut self) -> Result<ReembedReport> {
+        let old_features = self.features_map.read().await.to_map();
+        let mut report = ReembedReport::default();
 
-        // Text parameters
-        let font_face = FONT_HERSHEY_SIMPLEX;
-        let font_scale = 0.8;
-        let thickness = 2;
-        let mut baseline = 0;
+        // Source image -> persons it provided features for with their weight, in a stable
+        // order
+        let mut sources: std::collections::BTreeMap<PathBuf, Vec<(String, f32)>> =
+            std::collections::BTreeMap::new();
+        let mut capture_times: HashMap<PathBuf, Option<SystemTime>> = HashMap::new();
+        let mut new_features: HashMap<String, Vec<StoredFeature>> = HashMap::new();
+        for (name, features) in &old_features {
+            let kept = new_features.entry(name.clone()).or_default();
+            for stored in features {
+                match &stored.source {
+                    Some(source) => {
+                        capture_times.insert(source.clone(), stored.captured_at);
+                        let persons = sources.entry(source.clone()).or_default();
+                        if !persons.iter().any(|(person, _)| person == name) {
+                            persons.push((name.clone(), stored.weight));
+                        }
+                    }
+                    None => {
+                        report.features_without_source += 1;
+                        kept.push(stored.clone());
+                    }
+                }
+            }
+        }
+        if report.features_without_source > 0 {
+            warn!(
+                "{} features have no source image and were not re-embedded",
+                report.features_without_source
+            );
+        }
 
-        let text_size = get_text_size(name, font_face, font_scale, thickness, &mut baseline)?;
-        let text_x = bbox.x + (bbox.width - text_size.width) / 2;
-        let text_y = std::cmp::max(bbox.y - text_size.height - 5, 0);
+        let mut sequence = old_features
+            .values()
+            .flatten()
+            .map(|f| f.sequence + 1)
+            .max()
+            .unwrap_or(0);
+        let mut stored_total: usize = new_features.values().map(Vec::len).sum();
+        for (source, persons) in sources {
+            report.images_processed += 1;
+            let img = match source.to_str().map(|p| imread(p, IMREAD_COLOR)) {
+                Some(Ok(img)) if !img.empty() => img,
+                _ => {
+                    error!("Cannot read image: {}", source.display());
+                    report.failed_images.push(source);
+                    continue;
+                }
+            };
 
-        // Draw background rectangle
-        let bg_rect = Rect2i::new(
-            text_x - 2,
-            text_y - 2,
-            text_size.width + 4,
-            text_size.height + 4,
-        );
+            let detected_faces = self.extract_enrollment_features(img).await?;
+            if detected_faces.is_empty() {
+                warn!("No face detected anymore in {}", source.display());
+                report.failed_images.push(source);
+                continue;
+            }
 
-        let bg_color = Scalar::new(0.0, 0.0, 0.0, 0.0); // Black background
-        rectangle(frame, bg_rect, bg_color, -1, LINE_8, 0)?;
+            let mut usable = Vec::with_capacity(detected_faces.len());
+            for face in &detected_faces {
+                if self.min_enroll_eye_distance > 0.0
+                    && face.inter_ocular_distance()? < self.min_enroll_eye_distance
+                {
+                    warn!(
+                        "Rejecting face in {}: inter-ocular distance below {:.1}px",
+                        source.display(),
+                        self.min_enroll_eye_distance
+                    );
+                    report.faces_rejected += 1;
+                    continue;
+                }
+                usable.push(face);
+            }
 
-        // Draw text
-        let text_color = Scalar::new(255.0, 255.0, 255.0, 0.0); // White text
-        let text_pos = Point::new(text_x, text_y + text_size.height);
-        put_text(
-            frame, name, text_pos, font_face, font_scale, text_color, thickness, LINE_8, false,
+            for (person, weight) in persons {
+                for face in &usable {
+                    let stored = StoredFeature {
+                        feature: face.feature.try_clone()?,
+                        quality: face.score()?,
+                        source: Some(source.clone()),
+                        sequence,
+                        quantized: None,
+                        half: None,
+                        ensemble_features: face.ensemble_features.clone(),
+                        weight,
+                        captured_at: capture_times.get(&source).copied().flatten(),
+                        mirrored: false,
+                    };
+                    sequence += 1;
+                    let mirrored = match &face.flipped_feature {
+                        Some(flipped) => {
+                            Some(mirrored_reference(&stored, flipped.try_clone()?, sequence))
+                        }
+                        None => None,
+                    };
+                    if mirrored.is_some() {
+                        sequence += 1;
+                    }
+                    let mut references: Vec<StoredFeature> =
+                        std::iter::once(stored).chain(mirrored).collect();
+                    report.features_evicted += self.reject_over_cap(&mut references, stored_total);
+                    if references.is_empty() {
+                        continue;
+                    }
+                    stored_total += references.len();
+                    new_features
+                        .entry(person.clone())
+                        .or_default()
+                        .extend(references);
+                    report.features_reembedded += 1;
+                }
+            }
+        }
+
+        self.drop_invalid_features(&mut new_features)?;
+        if self.max_total_features > 0 {
+            report.features_evicted += enforce_feature_cap(
+                &mut new_features,
+                self.max_total_features,
+                self.feature_cap_policy,
+            );
+        }
+        if report.features_evicted > 0 {
+            warn!(
+                "Feature cap of {} reached, {} features dropped",
+                self.max_total_features, report.features_evicted
+            );
+        }
+        compact_features(
+            &mut new_features,
+            self.quantize_features,
+            self.feature_precision,
         )?;
+        self.features_map.write().await.replace_all(new_features);
+        info!(
+            "Re-embedded {} features from {} images ({} failed)",
+            report.features_reembedded,
+            report.images_processed,
+            report.failed_images.len()
+        );
+        Ok(report)
+    }
+
+    /// Write the loaded features with their weights and ensemble embeddings, and the
+    /// per-person thresholds, to a JSON file
+    pub async fn export_database<P: AsRef<Path>>(&self, path: P) -> Result<()> {
+        let features_map = self.features_map.read().await;
+        let mut export = DatabaseExport {
+            version: DATABASE_EXPORT_VERSION,
+            feature_dim: self.feature_dim(),
+            ..Default::default()
+        };
+        for (name, features) in features_map.iter() {
+            let vectors = features
+                .iter()
+                .map(|stored| feature_to_vec(&stored.feature_mat()?))
+                .collect::<opencv::Result<Vec<_>>>()?;
+            export.persons.insert(name.to_string(), vectors);
+            if features.iter().any(|stored| stored.weight != 1.0) {
+                let weights = features.iter().map(|stored| stored.weight).collect();
+                export.weights.insert(name.to_string(), weights);
+            }
+            if features
+                .iter()
+                .any(|stored| !stored.ensemble_features.is_empty())
+            {
+                let ensemble = features
+                    .iter()
+                    .map(|stored| ensemble_to_vecs(&stored.ensemble_features))
+                    .collect::<opencv::Result<Vec<_>>>()?;
+                export.ensemble.insert(name.to_string(), ensemble);
+            }
+        }
+        export.person_thresholds = self
+            .person_thresholds
+            .iter()
+            .map(|(name, threshold)| (name.clone(), *threshold))
+            .collect();
+        drop(features_map);
 
+        let file = std::fs::File::create(path.as_ref())?;
+        serde_json::to_writer(std::io::BufWriter::new(file), &export)?;
+        info!(
+            "Exported {} persons to {}",
+            export.persons.len(),
+            path.as_ref().display()
+        );
         Ok(())
     }
 
-    fn annotate_with_name_scaled(
-        &self,
-        frame: &mut Mat,
-        face: &DetectedFace,
-        name: &str,
-    ) -> Result<()> {
-        let bbox = face.bbox_scaled(frame.size()?)?;
-
-        // Text parameters - scale font based on image size
-        let font_face = FONT_HERSHEY_SIMPLEX;
-        let base_font_scale = 0.8;
-        // Scale font based on image width - larger images get bigger text
-        let font_scale = base_font_scale * (frame.cols() as f64 / 800.0).max(0.5).min(3.0);
-        let thickness = ((frame.cols() as f64 / 800.0).max(1.0).min(4.0)) as i32;
-        let mut baseline = 0;
+    /// Replace the in-memory database with one written by `export_database`
+    pub async fn import_database<P: AsRef<Path>>(&mut self, path: P) -> Result<()> {
+        let file = std::fs::File::open(path.as_ref())?;
+        let export: DatabaseExport = serde_json::from_reader(std::io::BufReader::new(file))?;
+        if export.feature_dim != self.feature_dim() {
+            return Err(FaceRecognitionError::FeatureDimensionMismatch {
+                expected: self.feature_dim(),
+                actual: export.feature_dim,
+            });
+        }
 
-        let text_size = get_text_size(name, font_face, font_scale, thickness, &mut baseline)?;
-        let text_x = bbox.x + (bbox.width - text_size.width) / 2;
-        let text_y = std::cmp::max(bbox.y - text_size.height - 5, 0);
+        let mut features = HashMap::new();
+        let mut sequence: u64 = 0;
+        let mut rejected = 0;
+        for (name, vectors) in &export.persons {
+            let weights = export.weights.get(name);
+            let ensemble = export.ensemble.get(name);
+            let mut stored = Vec::with_capacity(vectors.len());
+            for (index, vector) in vectors.iter().enumerate() {
+                if self.reject_new_capacity(sequence as usize) == Some(0) {
+                    rejected += 1;
+                    continue;
+                }
+                let ensemble_features = match ensemble.and_then(|e| e.get(index)) {
+                    Some(vectors) => ensemble_from_vecs(vectors)?,
+                    None => Vec::new(),
+                };
+                stored.push(StoredFeature {
+                    feature: vec_to_feature(vector)?,
+                    quality: 1.0,
+                    source: None,
+                    sequence,
+                    quantized: None,
+                    half: None,
+                    ensemble_features,
+                    weight: weights
+                        .and_then(|w| w.get(index))
+                        .map_or(1.0, |&weight| weight.max(0.0)),
+                    captured_at: None,
+                    mirrored: false,
+                });
+                sequence += 1;
+            }
+            features.insert(name.clone(), stored);
+        }
+        self.drop_invalid_features(&mut features)?;
+        if self.max_total_features > 0 {
+            let evicted = rejected
+                + enforce_feature_cap(
+                    &mut features,
+                    self.max_total_features,
+                    self.feature_cap_policy,
+                );
+            if evicted > 0 {
+                warn!(
+                    "Feature cap of {} reached, {} features dropped",
+                    self.max_total_features, evicted
+                );
+            }
+        }
 
-        // Draw background rectangle
-        let bg_rect = Rect2i::new(
-            text_x - 2,
-            text_y - 2,
-            text_size.width + 4,
-            text_size.height + 4,
+        compact_features(
+            &mut features,
+            self.quantize_features,
+            self.feature_precision,
+        )?;
+        self.features_map.write().await.replace_all(features);
+        self.person_thresholds = export
+            .person_thresholds
+            .into_iter()
+            .map(|(name, threshold)| (person_name_key(&name), threshold))
+            .collect();
+        *self.db_load_status.write().await = DbLoadStatus::Loaded;
+        info!(
+            "Imported {} persons from {}",
+            export.persons.len(),
+            path.as_ref().display()
         );
+        Ok(())
+    }
 
-        let bg_color = Scalar::new(0.0, 0.0, 0.0, 0.0); // Black background
-        rectangle(frame, bg_rect, bg_color, -1, LINE_8, 0)?;
+    /// Capture the loaded database (features with their metadata, per-person thresholds,
+    /// database path and load report) for an in-memory handoff to another instance, e.g.
+    /// during a zero-downtime upgrade.
+    pub async fn snapshot(&self) -> Result<DatabaseSnapshot> {
+        let features_map = self.features_map.read().await;
+        let mut persons = std::collections::BTreeMap::new();
+        for (name, features) in features_map.iter() {
+            let features = features
+                .iter()
+                .map(|stored| {
+                    Ok(SnapshotFeature {
+                        values: feature_to_vec(&stored.feature_mat()?)?,
+                        quality: stored.quality,
+                        source: stored.source.clone(),
+                        sequence: stored.sequence,
+                        weight: stored.weight,
+                        captured_at: stored.captured_at,
+                        ensemble: ensemble_to_vecs(&stored.ensemble_features)?,
+                        mirrored: stored.mirrored,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            persons.insert(name.to_string(), features);
+        }
 
-        // Draw text
-        let text_color = Scalar::new(255.0, 255.0, 255.0, 0.0); // White text
-        let text_pos = Point::new(text_x, text_y + text_size.height);
-        put_text(
-            frame, name, text_pos, font_face, font_scale, text_color, thickness, LINE_8, false,
+        Ok(DatabaseSnapshot {
+            feature_dim: self.feature_dim(),
+            persons,
+            person_thresholds: self
+                .person_thresholds
+                .iter()
+                .map(|(name, threshold)| (name.clone(), *threshold))
+                .collect(),
+            db_path: self.db_path.read().await.clone(),
+            load_report: self.last_load_report.clone(),
+        })
+    }
+
+    /// How far each person's representation moved between `other` (e.g. a snapshot taken
+    /// before re-enrolling) and the current database: the cosine distance
+    /// (`1 - cosine similarity`) of the two centroids, the mean of the normalized
+    /// features. Only persons present in both are listed, under their current name.
+    pub async fn embedding_drift(&self, other: &DatabaseSnapshot) -> Result<HashMap<String, f32>> {
+        let other_centroids: HashMap<String, Vec<f32>> = other
+            .persons
+            .iter()
+            .filter_map(|(name, features)| {
+                feature_centroid(features.iter().map(|f| f.values.as_slice()))
+                    .map(|centroid| (person_name_key(name), centroid))
+            })
+            .collect();
+
+        let features_map = self.features_map.read().await;
+        let mut drift = HashMap::new();
+        for (name, features) in features_map.iter() {
+            let Some(other_centroid) = other_centroids.get(&person_name_key(&name)) else {
+                continue;
+            };
+            let values = features
+                .iter()
+                .map(|stored| Ok(feature_to_vec(&stored.feature_mat()?)?))
+                .collect::<Result<Vec<_>>>()?;
+            if let Some(centroid) = feature_centroid(values.iter().map(Vec::as_slice)) {
+                drift.insert(
+                    name.to_string(),
+                    1.0 - cosine_similarity(&centroid, other_centroid),
+                );
+            }
+        }
+        Ok(drift)
+    }
+
+    /// Replace the database with a snapshot taken by `snapshot`, typically on a freshly
+    /// constructed instance. Fails with `FeatureDimensionMismatch` if the snapshot was
+    /// made with a recognizer of another embedding dimension; nothing is changed then.
+    pub async fn restore(&mut self, snapshot: DatabaseSnapshot) -> Result<()> {
+        let expected = self.feature_dim();
+        let mismatch = std::iter::once(snapshot.feature_dim).chain(
+            snapshot
+                .persons
+                .values()
+                .flatten()
+                .map(|feature| feature.values.len() as i32),
+        );
+        for actual in mismatch {
+            if actual != expected {
+                return Err(FaceRecognitionError::FeatureDimensionMismatch { expected, actual });
+            }
+        }
+
+        let mut features = HashMap::new();
+        for (name, snapshot_features) in snapshot.persons {
+            let stored = snapshot_features
+                .into_iter()
+                .map(|feature| {
+                    Ok(StoredFeature {
+                        feature: vec_to_feature(&feature.values)?,
+                        quality: feature.quality,
+                        source: feature.source,
+                        sequence: feature.sequence,
+                        quantized: None,
+                        half: None,
+                        ensemble_features: ensemble_from_vecs(&feature.ensemble)?,
+                        weight: feature.weight,
+                        captured_at: feature.captured_at,
+                        mirrored: feature.mirrored,
+                    })
+                })
+                .collect::<Result<Vec<_>>>()?;
+            features.insert(name, stored);
+        }
+        self.drop_invalid_features(&mut features)?;
+        compact_features(
+            &mut features,
+            self.quantize_features,
+            self.feature_precision,
         )?;
 
+        // Treat the folder as up to date so the watcher does not reload it right away
+        if let Some(path) = &snapshot.db_path {
+            if let Ok(mod_time) = get_latest_mod_time(path) {
+                *self.last_mod_time.write().await = mod_time;
+            }
+        }
+
+        let persons = features.len();
+        self.features_map.write().await.replace_all(features);
+        *self.db_path.write().await = snapshot.db_path;
+        self.person_thresholds = snapshot
+            .person_thresholds
+            .into_iter()
+            .map(|(name, threshold)| (person_name_key(&name), threshold))
+            .collect();
+        self.last_load_report = snapshot.load_report;
+        *self.db_load_status.write().await = DbLoadStatus::Loaded;
+        info!("Restored {} persons from snapshot", persons);
         Ok(())
     }
 
+    /// Recognize the images (jpg, jpeg, png, bmp) directly inside `dir` one at a time as
+    /// they are requested, in path order, so memory stays flat for huge directories. Only
+    /// the file list is read up front:
+    ///
+    /// ```no_run
+    /// # async fn example(face_rec: &mut facerust::FaceRecognition) -> facerust::Result<()> {
+    /// let mut images = face_rec.recognize_dir_iter("photos", 0.5)?;
+    /// while let Some((path, result)) = images.next_image().await {
+    ///     match result {
+    ///         Ok(matches) => println!("{}: {:?}", path.display(), matches),
+    ///         Err(e) => eprintln!("skipped {}: {}", path.display(), e),
+    ///     }
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn recognize_dir_iter<P: AsRef<Path>>(
+        &mut self,
+        dir: P,
+        threshold: f32,
+    ) -> Result<RecognizeDirIter<'_>> {
+        Ok(RecognizeDirIter {
+            files: image_files(dir.as_ref())?.into_iter(),
+            face_rec: self,
+            threshold,
+        })
+    }
+
     /// Simple face detection only (no recognition) - returns count of detected faces
     pub async fn detect_faces_count<P: AsRef<Path>>(&mut self, image_path: P) -> Result<usize> {
         let frame = imread(image_path.as_ref().to_str().unwrap(), IMREAD_COLOR)?;
@@ -603,3 +5957,135 @@ impl FaceRecognition {
         Ok(detected_faces.len())
     }
 }
+
+/// Lazy recognition over a directory, created by `FaceRecognition::recognize_dir_iter`.
+/// Recognition is async, so this is advanced with `next_image().await` rather than
+/// implementing `Iterator`.
+pub struct RecognizeDirIter<'a> {
+    face_rec: &'a mut FaceRecognition,
+    files: std::vec::IntoIter<PathBuf>,
+    threshold: f32,
+}
+
+impl RecognizeDirIter<'_> {
+    /// Recognize the next image in path order together with its path; `None` once all
+    /// images are done. A file that fails yields its error and iteration can continue.
+    pub async fn next_image(&mut self) -> Option<(PathBuf, Result<Vec<MatchResult>>)> {
+        let path = self.files.next()?;
+        let result = match path.to_str().map(|p| imread(p, IMREAD_COLOR)) {
+            Some(Ok(mut frame)) if !frame.empty() => {
+                self.face_rec.run(&mut frame, self.threshold, false).await
+            }
+            Some(Err(e)) => Err(e.into()),
+            _ => Err(FaceRecognitionError::Io(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                format!("could not read image {}", path.display()),
+            ))),
+        };
+        Some((path, result))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stored(sequence: u64, quality: f32) -> StoredFeature {
+        StoredFeature {
+            quality,
+            sequence,
+            ..StoredFeature::new(vec_to_feature(&[1.0, 0.0]).unwrap())
+        }
+    }
+
+    fn sequences(features: &HashMap<String, Vec<StoredFeature>>, person: &str) -> Vec<u64> {
+        features[person].iter().map(|f| f.sequence).collect()
+    }
+
+    #[test]
+    fn feature_cap_evicts_by_policy() {
+        let database = HashMap::from([
+            ("a".to_string(), vec![stored(0, 0.9), stored(2, 0.5)]),
+            ("b".to_string(), vec![stored(1, 0.6), stored(3, 0.8)]),
+        ]);
+
+        let mut features = database.clone();
+        assert_eq!(
+            enforce_feature_cap(&mut features, 2, FeatureCapPolicy::RejectNew),
+            2
+        );
+        assert_eq!(sequences(&features, "a"), [0]);
+        assert_eq!(sequences(&features, "b"), [1]);
+
+        let mut features = database.clone();
+        enforce_feature_cap(&mut features, 3, FeatureCapPolicy::EvictOldest);
+        assert_eq!(sequences(&features, "a"), [2]);
+        assert_eq!(sequences(&features, "b"), [1, 3]);
+
+        let mut features = database.clone();
+        enforce_feature_cap(&mut features, 2, FeatureCapPolicy::EvictLowestQuality);
+        assert_eq!(sequences(&features, "a"), [0]);
+        assert_eq!(sequences(&features, "b"), [3]);
+
+        let mut features = database;
+        assert_eq!(
+            enforce_feature_cap(&mut features, 4, FeatureCapPolicy::EvictOldest),
+            0
+        );
+    }
+
+    #[test]
+    fn converts_civil_time_to_unix_seconds() {
+        assert_eq!(civil_to_unix_secs(1970, 1, 1, 0, 0, 0), 0);
+        // EXIF DateTimeOriginal "2024:02:29 12:34:56"
+        assert_eq!(civil_to_unix_secs(2024, 2, 29, 12, 34, 56), 1_709_210_096);
+        assert_eq!(civil_to_unix_secs(2000, 3, 1, 0, 0, 0), 951_868_800);
+        assert_eq!(civil_to_unix_secs(1969, 12, 31, 23, 59, 59), -1);
+    }
+
+    #[test]
+    fn reads_image_size_from_headers() {
+        let png: &[&[u8]] = &[
+            b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR",
+            &[0, 0, 0x01, 0x40, 0, 0, 0, 0xF0],
+        ];
+        let jpeg: &[&[u8]] = &[
+            &[0xFF, 0xD8, 0xFF, 0xE0, 0x00, 0x04, 0x00, 0x00],
+            &[0xFF, 0xC0, 0x00, 0x11, 0x08, 0x00, 0xF0, 0x01, 0x40],
+        ];
+        let lossy_webp: &[&[u8]] = &[
+            b"RIFF\0\0\0\0WEBPVP8 \0\0\0\0\0\0\0",
+            &[0x9D, 0x01, 0x2A, 0x40, 0x01, 0xF0, 0x00],
+        ];
+        let lossless_webp: &[&[u8]] = &[
+            b"RIFF\0\0\0\0WEBPVP8L\0\0\0\0",
+            &[0x2F, 0x3F, 0xC1, 0x3B, 0x00],
+        ];
+        let tiff_le: &[&[u8]] = &[
+            b"II*\0",
+            &[8, 0, 0, 0, 2, 0],
+            &[0x00, 0x01, 3, 0, 1, 0, 0, 0, 0x40, 0x01, 0, 0],
+            &[0x01, 0x01, 4, 0, 1, 0, 0, 0, 0xF0, 0, 0, 0],
+        ];
+        let tiff_be: &[&[u8]] = &[
+            b"MM\0*",
+            &[0, 0, 0, 8, 0, 2],
+            &[0x01, 0x00, 0, 3, 0, 0, 0, 1, 0x01, 0x40, 0, 0],
+            &[0x01, 0x01, 0, 4, 0, 0, 0, 1, 0, 0, 0, 0xF0],
+        ];
+
+        for parts in [png, jpeg, lossy_webp, lossless_webp, tiff_le, tiff_be] {
+            let header = parts.concat();
+            assert_eq!(
+                encoded_image_size(&header),
+                Some((320, 240)),
+                "{:?}",
+                header
+            );
+            let truncated = &header[..header.len() - 1];
+            assert_eq!(encoded_image_size(truncated), None, "{:?}", truncated);
+        }
+        assert_eq!(encoded_image_size(b"not an image"), None);
+        assert_eq!(encoded_image_size(&[]), None);
+    }
+}
@@ -1,10 +1,85 @@
-use crate::{FaceRecognition, MatchResult};
+use crate::face_recognition::image_files;
+use crate::{DbLoadStatus, FaceRecognition, FaceRecognitionError, FaceResult, MatchResult};
 use opencv::core::Mat;
+use opencv::imgcodecs::{imread, IMREAD_COLOR};
+use opencv::prelude::*;
+use std::cell::RefCell;
 use std::ffi::{CStr, CString};
 use std::os::raw::{c_char, c_float, c_int};
+use std::path::{Path, PathBuf};
 use std::ptr;
 use tokio::runtime::Runtime;
 
+/// The last call succeeded
+pub const FACERUST_OK: c_int = 0;
+/// A pointer or parameter passed by the caller was invalid
+pub const FACERUST_ERR_INVALID_ARGUMENT: c_int = 1;
+/// The call may succeed if retried later, e.g. while the database is (re)loading
+pub const FACERUST_ERR_TRANSIENT: c_int = 2;
+/// Retrying the same call will not help (bad image, missing model, ...)
+pub const FACERUST_ERR_TERMINAL: c_int = 3;
+
+thread_local! {
+    static LAST_ERROR: RefCell<(c_int, Option<CString>)> = const { RefCell::new((FACERUST_OK, None)) };
+}
+
+fn set_last_error(code: c_int, message: &str) {
+    let message = CString::new(message).ok();
+    LAST_ERROR.with(|last| *last.borrow_mut() = (code, message));
+}
+
+fn clear_last_error() {
+    LAST_ERROR.with(|last| *last.borrow_mut() = (FACERUST_OK, None));
+}
+
+/// Record `error` as the last error, classified as transient or terminal.
+///
+/// Retriable: I/O errors other than a missing file or corrupt data, detection failures,
+/// cancelled operations and a database that is not loaded while `database_loading`, i.e.
+/// while a load or reload is in progress. Everything else is terminal, including a
+/// database that was never loaded or whose load failed.
+fn set_last_error_from(error: &FaceRecognitionError, database_loading: bool) {
+    let code = match error {
+        FaceRecognitionError::Io(e)
+            if matches!(
+                e.kind(),
+                std::io::ErrorKind::NotFound | std::io::ErrorKind::InvalidData
+            ) =>
+        {
+            FACERUST_ERR_TERMINAL
+        }
+        FaceRecognitionError::DatabaseNotLoaded if database_loading => FACERUST_ERR_TRANSIENT,
+        FaceRecognitionError::Io(_)
+        | FaceRecognitionError::DetectionFailed
+        | FaceRecognitionError::Cancelled => FACERUST_ERR_TRANSIENT,
+        _ => FACERUST_ERR_TERMINAL,
+    };
+    set_last_error(code, &error.to_string());
+}
+
+/// Whether `face_rec` is loading or reloading its database right now
+fn database_loading(face_rec: &CFaceRecognition) -> bool {
+    face_rec.runtime.block_on(face_rec.inner.db_load_status()) == DbLoadStatus::Loading
+}
+
+/// Code of the last error on the calling thread (`FACERUST_OK` after a successful call)
+#[no_mangle]
+pub extern "C" fn facerecognition_last_error_code() -> c_int {
+    LAST_ERROR.with(|last| last.borrow().0)
+}
+
+/// Message of the last error on the calling thread, or NULL. The string is owned by the
+/// library and valid until the next call on the same thread.
+#[no_mangle]
+pub extern "C" fn facerecognition_last_error_message() -> *const c_char {
+    LAST_ERROR.with(|last| {
+        last.borrow()
+            .1
+            .as_ref()
+            .map_or(ptr::null(), |message| message.as_ptr())
+    })
+}
+
 // Opaque pointer type for FaceRecognition
 pub struct CFaceRecognition {
     inner: FaceRecognition,
@@ -29,11 +104,54 @@ impl From<MatchResult> for CMatchResult {
     }
 }
 
+// Face result with its bounding box for C
+#[repr(C)]
+pub struct CFaceResult {
+    name: *mut c_char,
+    score: c_float,
+    /// Bounding box in the coordinates of the frame passed in
+    x: c_int,
+    y: c_int,
+    w: c_int,
+    h: c_int,
+}
+
+impl From<FaceResult> for CFaceResult {
+    fn from(result: FaceResult) -> Self {
+        let CMatchResult { name, score } = result.best_match.into();
+        Self {
+            name,
+            score,
+            x: result.bbox.x,
+            y: result.bbox.y,
+            w: result.bbox.width,
+            h: result.bbox.height,
+        }
+    }
+}
+
+impl CFaceResult {
+    fn empty(name: &str) -> Self {
+        let CMatchResult { name, score } = error_match_result(name);
+        Self {
+            name,
+            score,
+            x: 0,
+            y: 0,
+            w: 0,
+            h: 0,
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn facerecognition_create() -> *mut CFaceRecognition {
     let runtime = match Runtime::new() {
         Ok(rt) => rt,
-        Err(_) => return ptr::null_mut(),
+        Err(e) => {
+            set_last_error(FACERUST_ERR_TERMINAL, &e.to_string());
+            return ptr::null_mut();
+        }
     };
 
     let face_rec = match FaceRecognition::new(
@@ -42,8 +160,12 @@ pub extern "C" fn facerecognition_create() -> *mut CFaceRecognition {
         Some(1000),
     ) {
         Ok(fr) => fr,
-        Err(_) => return ptr::null_mut(),
+        Err(e) => {
+            set_last_error_from(&e, false);
+            return ptr::null_mut();
+        }
     };
+    clear_last_error();
 
     Box::into_raw(Box::new(CFaceRecognition {
         inner: face_rec,
@@ -57,13 +179,17 @@ pub extern "C" fn facerecognition_load_persons_db(
     db_path: *const c_char,
 ) -> c_int {
     if face_rec.is_null() || db_path.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "null pointer");
         return -1;
     }
 
     let face_rec = unsafe { &mut *face_rec };
     let db_path_str = match unsafe { CStr::from_ptr(db_path) }.to_str() {
         Ok(s) => s,
-        Err(_) => return -1,
+        Err(_) => {
+            set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "db_path is not valid UTF-8");
+            return -1;
+        }
     };
 
     match face_rec.runtime.block_on(async {
@@ -72,11 +198,24 @@ pub extern "C" fn facerecognition_load_persons_db(
             .load_persons_db(db_path_str, false, false)
             .await
     }) {
-        Ok(_) => 0,
-        Err(_) => -1,
+        Ok(_) => {
+            clear_last_error();
+            0
+        }
+        Err(e) => {
+            set_last_error_from(&e, database_loading(face_rec));
+            -1
+        }
     }
 }
 
+/// Recognize the best matching face in the caller's buffer `mat_data`.
+///
+/// The buffer is borrowed, not copied: it must hold `rows * cols * channels` bytes and
+/// stay valid and unmodified until this function returns. The call is fully synchronous,
+/// so the buffer is not used afterwards. Use
+/// `facerecognition_run_one_face_opencv_mat_copy` if another thread may write to or free
+/// the buffer concurrently.
 #[no_mangle]
 pub extern "C" fn facerecognition_run_one_face_opencv_mat(
     face_rec: *mut CFaceRecognition,
@@ -86,55 +225,347 @@ pub extern "C" fn facerecognition_run_one_face_opencv_mat(
     channels: c_int,
     threshold: c_float,
 ) -> CMatchResult {
-    if face_rec.is_null() || mat_data.is_null() {
-        return CMatchResult {
-            name: CString::new("error").unwrap().into_raw(),
-            score: 0.0,
-        };
-    }
+    run_one_face_buffer(face_rec, mat_data, rows, cols, channels, threshold, false)
+}
 
-    let face_rec = unsafe { &mut *face_rec };
+/// Like `facerecognition_run_one_face_opencv_mat`, but copies the buffer into memory owned
+/// by the library first. The caller's buffer is only read while this function is copying
+/// it, at the cost of one copy of the image.
+#[no_mangle]
+pub extern "C" fn facerecognition_run_one_face_opencv_mat_copy(
+    face_rec: *mut CFaceRecognition,
+    mat_data: *const u8,
+    rows: c_int,
+    cols: c_int,
+    channels: c_int,
+    threshold: c_float,
+) -> CMatchResult {
+    run_one_face_buffer(face_rec, mat_data, rows, cols, channels, threshold, true)
+}
 
-    // Create OpenCV Mat from raw data
+/// Wrap the caller's pixel buffer in a `Mat`, recording an invalid-argument error on
+/// failure. The Mat header points into the buffer; with `copy` it is replaced by an owned
+/// deep copy before any processing.
+fn mat_from_buffer(
+    mat_data: *const u8,
+    rows: c_int,
+    cols: c_int,
+    channels: c_int,
+    copy: bool,
+) -> Option<Mat> {
     let mat_type = match channels {
         1 => opencv::core::CV_8UC1,
         3 => opencv::core::CV_8UC3,
         _ => {
-            return CMatchResult {
-                name: CString::new("error").unwrap().into_raw(),
-                score: 0.0,
-            }
+            set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "channels must be 1 or 3");
+            return None;
         }
     };
 
     let mat = unsafe {
-        match Mat::new_rows_cols_with_data_unsafe(
+        Mat::new_rows_cols_with_data_unsafe(
             rows,
             cols,
             mat_type,
             mat_data as *mut _,
             opencv::core::Mat_AUTO_STEP,
-        ) {
-            Ok(m) => m,
-            Err(_) => {
-                return CMatchResult {
-                    name: CString::new("error").unwrap().into_raw(),
-                    score: 0.0,
-                }
-            }
+        )
+    }
+    .and_then(|mat| if copy { mat.try_clone() } else { Ok(mat) });
+    match mat {
+        Ok(m) => Some(m),
+        Err(e) => {
+            set_last_error(FACERUST_ERR_INVALID_ARGUMENT, &e.to_string());
+            None
         }
+    }
+}
+
+fn run_one_face_buffer(
+    face_rec: *mut CFaceRecognition,
+    mat_data: *const u8,
+    rows: c_int,
+    cols: c_int,
+    channels: c_int,
+    threshold: c_float,
+    copy: bool,
+) -> CMatchResult {
+    if face_rec.is_null() || mat_data.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "null pointer");
+        return error_match_result("error");
+    }
+
+    let face_rec = unsafe { &mut *face_rec };
+    let Some(mat) = mat_from_buffer(mat_data, rows, cols, channels, copy) else {
+        return error_match_result("error");
     };
 
     let result = face_rec
         .runtime
         .block_on(async { face_rec.inner.run_one_face(mat, threshold, false).await });
 
+    // Errors keep returning "unknown" for compatibility; callers tell them apart from a
+    // real non-match through facerecognition_last_error_code()
     match result {
-        Ok(match_result) => match_result.into(),
-        Err(_) => CMatchResult {
-            name: CString::new("unknown").unwrap().into_raw(),
-            score: 0.0,
-        },
+        Ok(match_result) => {
+            clear_last_error();
+            match_result.into()
+        }
+        Err(e) => {
+            set_last_error_from(&e, database_loading(face_rec));
+            error_match_result("unknown")
+        }
+    }
+}
+
+/// Recognize all faces in the caller's buffer (borrowed as in
+/// `facerecognition_run_one_face_opencv_mat`), returning an array of `*out_count` results
+/// with bounding boxes in detector order. Free it with `facerecognition_free_face_results`.
+/// Returns NULL with `*out_count == 0` when no face was recognized or on error.
+#[no_mangle]
+pub extern "C" fn facerecognition_run_opencv_mat(
+    face_rec: *mut CFaceRecognition,
+    mat_data: *const u8,
+    rows: c_int,
+    cols: c_int,
+    channels: c_int,
+    threshold: c_float,
+    out_count: *mut c_int,
+) -> *mut CFaceResult {
+    if out_count.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "null pointer");
+        return ptr::null_mut();
+    }
+    unsafe { *out_count = 0 };
+    let faces = match run_faces_buffer(face_rec, mat_data, rows, cols, channels, threshold) {
+        Some(faces) => faces,
+        None => return ptr::null_mut(),
+    };
+    if faces.is_empty() {
+        return ptr::null_mut();
+    }
+
+    let results: Box<[CFaceResult]> = faces.into_iter().map(CFaceResult::from).collect();
+    unsafe { *out_count = results.len() as c_int };
+    Box::into_raw(results) as *mut CFaceResult
+}
+
+/// Like `facerecognition_run_one_face_opencv_mat`, but also returns the bounding box of the
+/// best matching face (all zero with name "Unknown" when no face was found)
+#[no_mangle]
+pub extern "C" fn facerecognition_run_best_face_opencv_mat(
+    face_rec: *mut CFaceRecognition,
+    mat_data: *const u8,
+    rows: c_int,
+    cols: c_int,
+    channels: c_int,
+    threshold: c_float,
+) -> CFaceResult {
+    let Some(faces) = run_faces_buffer(face_rec, mat_data, rows, cols, channels, threshold) else {
+        return CFaceResult::empty("unknown");
+    };
+    faces
+        .into_iter()
+        .reduce(|best, face| {
+            if face.best_match.score > best.best_match.score {
+                face
+            } else {
+                best
+            }
+        })
+        .map_or_else(|| CFaceResult::empty("Unknown"), CFaceResult::from)
+}
+
+/// Recognize all faces in the buffer; `None` (with the last error set) on failure
+fn run_faces_buffer(
+    face_rec: *mut CFaceRecognition,
+    mat_data: *const u8,
+    rows: c_int,
+    cols: c_int,
+    channels: c_int,
+    threshold: c_float,
+) -> Option<Vec<FaceResult>> {
+    if face_rec.is_null() || mat_data.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "null pointer");
+        return None;
+    }
+    let face_rec = unsafe { &mut *face_rec };
+    let mut mat = mat_from_buffer(mat_data, rows, cols, channels, false)?;
+
+    let result = face_rec
+        .runtime
+        .block_on(face_rec.inner.run_detailed(&mut mat, threshold, false));
+    match result {
+        Ok(faces) => {
+            clear_last_error();
+            Some(faces.into_iter().filter_map(|face| face.ok()).collect())
+        }
+        Err(e) => {
+            set_last_error_from(&e, database_loading(face_rec));
+            None
+        }
+    }
+}
+
+/// Borrow a C string argument as UTF-8 (OpenCV only opens UTF-8 paths), recording an
+/// invalid-argument error naming `what` otherwise
+fn c_str_arg<'a>(value: *const c_char, what: &str) -> Option<&'a str> {
+    if value.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, &format!("{} is null", what));
+        return None;
+    }
+    match unsafe { CStr::from_ptr(value) }.to_str() {
+        Ok(s) => Some(s),
+        Err(_) => {
+            set_last_error(
+                FACERUST_ERR_INVALID_ARGUMENT,
+                &format!("{} is not valid UTF-8", what),
+            );
+            None
+        }
+    }
+}
+
+fn read_image(path: &str) -> crate::Result<Mat> {
+    if !Path::new(path).exists() {
+        return Err(FaceRecognitionError::Io(std::io::Error::new(
+            std::io::ErrorKind::NotFound,
+            format!("image {} does not exist", path),
+        )));
+    }
+    let image = imread(path, IMREAD_COLOR)?;
+    if image.empty() {
+        return Err(FaceRecognitionError::InvalidImage);
+    }
+    Ok(image)
+}
+
+fn error_match_result(name: &str) -> CMatchResult {
+    CMatchResult {
+        name: CString::new(name).unwrap().into_raw(),
+        score: 0.0,
+    }
+}
+
+/// Recognize the best matching face in the image file at `image_path`.
+///
+/// Behaves like `facerecognition_run_one_face_opencv_mat`: errors (including an
+/// unreadable file) return "unknown" and are reported through the last-error functions.
+#[no_mangle]
+pub extern "C" fn facerecognition_recognize_file(
+    face_rec: *mut CFaceRecognition,
+    image_path: *const c_char,
+    threshold: c_float,
+) -> CMatchResult {
+    if face_rec.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "null pointer");
+        return error_match_result("error");
+    }
+    let Some(image_path) = c_str_arg(image_path, "image_path") else {
+        return error_match_result("error");
+    };
+    let face_rec = unsafe { &mut *face_rec };
+
+    let result = read_image(image_path).and_then(|image| {
+        face_rec
+            .runtime
+            .block_on(face_rec.inner.run_one_face(image, threshold, false))
+    });
+    match result {
+        Ok(match_result) => {
+            clear_last_error();
+            match_result.into()
+        }
+        Err(e) => {
+            set_last_error_from(&e, database_loading(face_rec));
+            error_match_result("unknown")
+        }
+    }
+}
+
+/// One image in the report written by `facerecognition_recognize_dir`
+#[derive(serde::Serialize)]
+struct FileReport {
+    file: PathBuf,
+    /// Best match of every detected face, in detector order
+    matches: Vec<MatchResult>,
+    /// Why the image could not be processed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+/// Recognize all faces in every image (jpg, jpeg, png, bmp) directly inside `dir_path`
+/// and write a JSON report to `out_json_path`: an array of
+/// `{"file": ..., "matches": [{"name": ..., "score": ...}, ...]}` entries, with an
+/// `"error"` field for images that could not be processed.
+///
+/// Returns the number of images processed, or -1 if the directory could not be listed or
+/// the report could not be written (see the last-error functions). Failing images do not
+/// stop the batch.
+#[no_mangle]
+pub extern "C" fn facerecognition_recognize_dir(
+    face_rec: *mut CFaceRecognition,
+    dir_path: *const c_char,
+    out_json_path: *const c_char,
+    threshold: c_float,
+) -> c_int {
+    if face_rec.is_null() {
+        set_last_error(FACERUST_ERR_INVALID_ARGUMENT, "null pointer");
+        return -1;
+    }
+    let Some(dir_path) = c_str_arg(dir_path, "dir_path") else {
+        return -1;
+    };
+    let Some(out_json_path) = c_str_arg(out_json_path, "out_json_path") else {
+        return -1;
+    };
+    let face_rec = unsafe { &mut *face_rec };
+
+    let files = match image_files(Path::new(dir_path)) {
+        Ok(files) => files,
+        Err(e) => {
+            set_last_error_from(&e.into(), false);
+            return -1;
+        }
+    };
+
+    let mut reports = Vec::with_capacity(files.len());
+    for file in files {
+        let result = file
+            .to_str()
+            .ok_or(FaceRecognitionError::InvalidImage)
+            .and_then(read_image)
+            .and_then(|mut image| {
+                face_rec
+                    .runtime
+                    .block_on(face_rec.inner.run(&mut image, threshold, false))
+            });
+        reports.push(match result {
+            Ok(matches) => FileReport {
+                file,
+                matches,
+                error: None,
+            },
+            Err(e) => FileReport {
+                file,
+                matches: Vec::new(),
+                error: Some(e.to_string()),
+            },
+        });
+    }
+
+    let written = serde_json::to_string_pretty(&reports)
+        .map_err(FaceRecognitionError::from)
+        .and_then(|json| std::fs::write(out_json_path, json).map_err(Into::into));
+    match written {
+        Ok(()) => {
+            clear_last_error();
+            c_int::try_from(reports.len()).unwrap_or(c_int::MAX)
+        }
+        Err(e) => {
+            set_last_error_from(&e, false);
+            -1
+        }
     }
 }
 
@@ -150,6 +581,33 @@ pub extern "C" fn facerecognition_free_match_result(result: *mut CMatchResult) {
     }
 }
 
+#[no_mangle]
+pub extern "C" fn facerecognition_free_face_result(result: *mut CFaceResult) {
+    if !result.is_null() {
+        unsafe {
+            let result = &mut *result;
+            if !result.name.is_null() {
+                let _ = CString::from_raw(result.name);
+                result.name = ptr::null_mut();
+            }
+        }
+    }
+}
+
+/// Free an array returned by `facerecognition_run_opencv_mat`
+#[no_mangle]
+pub extern "C" fn facerecognition_free_face_results(results: *mut CFaceResult, count: c_int) {
+    if results.is_null() || count <= 0 {
+        return;
+    }
+    unsafe {
+        let mut results = Box::from_raw(ptr::slice_from_raw_parts_mut(results, count as usize));
+        for result in results.iter_mut() {
+            facerecognition_free_face_result(result);
+        }
+    }
+}
+
 #[no_mangle]
 pub extern "C" fn facerecognition_destroy(face_rec: *mut CFaceRecognition) {
     if !face_rec.is_null() {
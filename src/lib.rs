@@ -1,13 +1,43 @@
+pub mod backend;
+pub mod builder;
+pub mod export;
 pub mod face_recognition;
 pub mod ffi;
+pub mod logging;
+#[cfg(feature = "mock")]
+pub mod mock;
+pub mod pool;
+#[cfg(feature = "server")]
+pub mod server;
+pub mod store;
 pub mod types;
 pub mod watcher;
 
-pub use face_recognition::FaceRecognition;
-pub use types::{DbLoadStatus, DetectedFace, MatchResult, MatchResults};
+pub use backend::{Detector, Recognizer};
+pub use builder::{ComputeBackend, FaceRecognitionBuilder, RecognizerConfig};
+pub use export::ResultsCsvWriter;
+pub use face_recognition::{
+    frame_motion, image_files, FaceRecognition, PreprocessFn, RecognizeDirIter,
+};
+pub use logging::{clear_log_callback, set_log_callback, LogCallback, LogLevel};
+pub use pool::FaceRecognitionPool;
+#[cfg(feature = "server")]
+pub use server::{serve, ServerConfig};
+pub use store::{FeatureStore, InMemoryStore, StoreEntry};
+pub use types::{
+    ColorNorm, ConfidenceMapping, CropPadding, CrossPersonDuplicate, DatabaseExport,
+    DatabaseSnapshot, DbLoadStatus, DetectedFace, DuplicateDetection, EnrollmentNormalization,
+    ExplainEntry, FaceResult, FaceSort, FeatureCapPolicy, FeaturePrecision, FusionWeight,
+    HalfFeature, InvalidFeaturePolicy, LoadReport, MatchResult, MatchResults, Metrics,
+    MissingDatabasePolicy, NameCollisionPolicy, PrepareStage, QuantizedFeature, RankedFace,
+    RecognitionMode, ReembedReport, ReferenceAggregation, RejectedFace, ReloadEvent,
+    SimilarityMetric, SnapshotFeature, StoredFeature, ThresholdPoint, ThresholdSuggestion,
+    TieBreak, TileConfig, TrackId, VisualizeNaming,
+};
 
 // Re-export opencv for convenience
 pub use opencv;
+pub use tokio_util::sync::CancellationToken;
 
 #[derive(Debug, thiserror::Error)]
 pub enum FaceRecognitionError {
@@ -17,16 +47,61 @@ pub enum FaceRecognitionError {
     Io(#[from] std::io::Error),
     #[error("Model not found: {0}")]
     ModelNotFound(String),
+    #[error("Invalid configuration: {0}")]
+    InvalidConfig(String),
     #[error("Database not loaded")]
     DatabaseNotLoaded,
+    #[error("Database is loaded but contains no features")]
+    DatabaseEmpty,
+    #[error("No face detected in {0}")]
+    NoFaceDetected(String),
+    #[error("Expected exactly one face in {path}, found {count}")]
+    UnexpectedFaceCount {
+        path: std::path::PathBuf,
+        count: usize,
+    },
+    #[error("Person not found: {0}")]
+    PersonNotFound(String),
     #[error("Face detection failed")]
     DetectionFailed,
     #[error("Feature extraction failed")]
     FeatureExtractionFailed,
     #[error("Invalid image")]
     InvalidImage,
+    #[error("Image of {width}x{height} pixels exceeds the limit of {max_pixels} pixels")]
+    ImageTooLarge {
+        width: u64,
+        height: u64,
+        max_pixels: u64,
+    },
+    #[error("Image format not supported while a decode size limit is set")]
+    UnsupportedImageFormat,
     #[error("Directory watch error: {0}")]
     WatchError(String),
+    #[error("Person name collision: {0}")]
+    NameCollision(String),
+    #[error("Operation cancelled")]
+    Cancelled,
+    #[error("Video error: {0}")]
+    Video(String),
+    #[error("Invalid manifest: {0}")]
+    InvalidManifest(String),
+    #[error("Invalid database archive: {0}")]
+    InvalidArchive(String),
+    #[error("Serialization error: {0}")]
+    Serialization(#[from] serde_json::Error),
+    #[error(
+        "Database was built with another recognizer: stored features have {database_dim} \
+         values, the current model produces {model_dim}"
+    )]
+    DatabaseModelMismatch {
+        database_dim: usize,
+        model_dim: usize,
+    },
+    #[error("Invalid stored feature of {person}: {reason}")]
+    InvalidStoredFeature { person: String, reason: String },
+    #[error("Feature dimension mismatch: expected {expected}, got {actual}")]
+    FeatureDimensionMismatch { expected: i32, actual: i32 },
 }
 
 pub type Result<T> = std::result::Result<T, FaceRecognitionError>;
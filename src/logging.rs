@@ -0,0 +1,75 @@
+//! Optional log sink for embedders that do not use `tracing`.
+//!
+//! The crate logs through `tracing` as usual; when a callback is installed with
+//! `set_log_callback`, every message is additionally passed to it.
+
+use std::fmt;
+use std::sync::RwLock;
+
+/// Severity of a message passed to the log callback
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+/// User sink for the crate's log messages, see `set_log_callback`
+pub type LogCallback = Box<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+static LOG_CALLBACK: RwLock<Option<LogCallback>> = RwLock::new(None);
+
+/// Mirror all log messages of the crate to `callback`. The callback is process-wide and
+/// replaces any previously installed one; `tracing` output is unaffected.
+pub fn set_log_callback(callback: LogCallback) {
+    if let Ok(mut guard) = LOG_CALLBACK.write() {
+        *guard = Some(callback);
+    }
+}
+
+/// Remove the callback installed with `set_log_callback`
+pub fn clear_log_callback() {
+    if let Ok(mut guard) = LOG_CALLBACK.write() {
+        *guard = None;
+    }
+}
+
+#[doc(hidden)]
+pub fn emit(level: LogLevel, args: fmt::Arguments<'_>) {
+    if let Ok(guard) = LOG_CALLBACK.read() {
+        if let Some(callback) = guard.as_ref() {
+            callback(level, &args.to_string());
+        }
+    }
+}
+
+macro_rules! error {
+    ($($arg:tt)*) => {{
+        ::tracing::error!($($arg)*);
+        $crate::logging::emit($crate::logging::LogLevel::Error, format_args!($($arg)*));
+    }};
+}
+
+macro_rules! warn {
+    ($($arg:tt)*) => {{
+        ::tracing::warn!($($arg)*);
+        $crate::logging::emit($crate::logging::LogLevel::Warn, format_args!($($arg)*));
+    }};
+}
+
+macro_rules! info {
+    ($($arg:tt)*) => {{
+        ::tracing::info!($($arg)*);
+        $crate::logging::emit($crate::logging::LogLevel::Info, format_args!($($arg)*));
+    }};
+}
+
+macro_rules! debug {
+    ($($arg:tt)*) => {{
+        ::tracing::debug!($($arg)*);
+        $crate::logging::emit($crate::logging::LogLevel::Debug, format_args!($($arg)*));
+    }};
+}
+
+pub(crate) use {debug, error, info, warn};
@@ -0,0 +1,109 @@
+//! Deterministic stand-ins for the OpenCV models (enabled by the `mock` feature).
+//!
+//! They let `run`, `find_best_match` and the matching logic be exercised without ONNX
+//! models:
+//!
+//! ```ignore
+//! let detector = ScriptedDetector::new(vec![ScriptedDetector::face(10.0, 10.0, 50.0, 50.0)]);
+//! let recognizer = ScriptedRecognizer::new(vec![vec![1.0, 0.0, 0.0]]);
+//! let mut face_rec =
+//!     FaceRecognition::with_backends(Box::new(detector), Box::new(recognizer), None);
+//! ```
+
+use crate::backend::{Detector, Recognizer};
+use opencv::{
+    core::{Mat, Scalar, Size, CV_8UC3},
+    prelude::*,
+};
+
+/// Detector returning the same scripted face rows for every frame
+pub struct ScriptedDetector {
+    faces: Vec<[f32; 15]>,
+}
+
+impl ScriptedDetector {
+    pub fn new(faces: Vec<[f32; 15]>) -> Self {
+        Self { faces }
+    }
+
+    /// A detection row for the given box with plausible landmarks and score 0.99
+    pub fn face(x: f32, y: f32, w: f32, h: f32) -> [f32; 15] {
+        [
+            x,
+            y,
+            w,
+            h,
+            x + 0.3 * w,
+            y + 0.4 * h,
+            x + 0.7 * w,
+            y + 0.4 * h,
+            x + 0.5 * w,
+            y + 0.6 * h,
+            x + 0.35 * w,
+            y + 0.8 * h,
+            x + 0.65 * w,
+            y + 0.8 * h,
+            0.99,
+        ]
+    }
+}
+
+impl Detector for ScriptedDetector {
+    fn set_input_size(&mut self, _size: Size) -> opencv::Result<()> {
+        Ok(())
+    }
+
+    fn detect(&mut self, _frame: &Mat, faces: &mut Mat) -> opencv::Result<()> {
+        *faces = if self.faces.is_empty() {
+            Mat::default()
+        } else {
+            Mat::from_slice_2d(&self.faces)?
+        };
+        Ok(())
+    }
+}
+
+/// Recognizer handing out scripted embeddings in order, cycling when exhausted
+pub struct ScriptedRecognizer {
+    embeddings: Vec<Vec<f32>>,
+    next: usize,
+}
+
+impl ScriptedRecognizer {
+    pub fn new(embeddings: Vec<Vec<f32>>) -> Self {
+        Self {
+            embeddings,
+            next: 0,
+        }
+    }
+}
+
+impl Recognizer for ScriptedRecognizer {
+    fn align_crop(&self, _frame: &Mat, _face: &Mat, aligned: &mut Mat) -> opencv::Result<()> {
+        *aligned = Mat::new_rows_cols_with_default(112, 112, CV_8UC3, Scalar::all(0.0))?;
+        Ok(())
+    }
+
+    fn feature(&mut self, _aligned: &Mat, feature: &mut Mat) -> opencv::Result<()> {
+        if self.embeddings.is_empty() {
+            *feature = Mat::default();
+            return Ok(());
+        }
+        let embedding = &self.embeddings[self.next % self.embeddings.len()];
+        self.next += 1;
+        *feature = Mat::from_slice(embedding)?.try_clone()?;
+        Ok(())
+    }
+
+    fn match_(&self, feature1: &Mat, feature2: &Mat, _dis_type: i32) -> opencv::Result<f64> {
+        let a = feature1.data_typed::<f32>()?;
+        let b = feature2.data_typed::<f32>()?;
+        let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+        let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+        let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return Ok(0.0);
+        }
+        Ok((dot / (norm_a * norm_b)) as f64)
+    }
+}
@@ -0,0 +1,139 @@
+//! Concurrent recognition through a shared reference.
+//!
+//! `FaceRecognition` takes `&mut self` because the OpenCV models mutate internal buffers on
+//! every call, so a single instance has to sit behind a lock that serializes all requests.
+//! `FaceRecognitionPool` holds several instances, each with its own models behind its own
+//! lock but all sharing one persons database, so `run(&self, ...)` can be called
+//! concurrently, e.g. from an `Arc<FaceRecognitionPool>` in a server.
+
+use crate::face_recognition::{sorted_person_names, SharedDatabase};
+use crate::{
+    DbLoadStatus, FaceRecognition, FaceRecognitionError, FaceResult, LoadReport, MatchResult,
+    ReloadEvent, Result,
+};
+use opencv::core::Mat;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use tokio::sync::{broadcast, Mutex, MutexGuard};
+
+/// Several `FaceRecognition` workers sharing one persons database
+pub struct FaceRecognitionPool {
+    workers: Vec<Mutex<FaceRecognition>>,
+    next: AtomicUsize,
+    database: SharedDatabase,
+}
+
+impl FaceRecognitionPool {
+    /// Create `size` workers (at least one) with `factory`, which also applies any
+    /// settings: each worker keeps its own configuration, only the database (features,
+    /// load status, path and watcher reloads) is shared. Each worker loads its own copy of
+    /// the models, so memory grows with `size`.
+    pub fn new<F>(size: usize, mut factory: F) -> Result<Self>
+    where
+        F: FnMut() -> Result<FaceRecognition>,
+    {
+        let primary = factory()?;
+        let database = primary.shared_database();
+        let mut workers = Vec::with_capacity(size.max(1));
+        for _ in 1..size.max(1) {
+            let mut worker = factory()?;
+            worker.share_database(&primary);
+            workers.push(Mutex::new(worker));
+        }
+        workers.insert(0, Mutex::new(primary));
+        Ok(Self {
+            workers,
+            next: AtomicUsize::new(0),
+            database,
+        })
+    }
+
+    /// Number of workers
+    pub fn size(&self) -> usize {
+        self.workers.len()
+    }
+
+    /// Lock an idle worker, or wait for the next one in turn if all are busy. Use it for
+    /// calls the pool does not wrap; the database changes made through any worker are
+    /// seen by all of them.
+    pub async fn worker(&self) -> MutexGuard<'_, FaceRecognition> {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let count = self.workers.len();
+        for offset in 0..count {
+            if let Ok(worker) = self.workers[(start + offset) % count].try_lock() {
+                return worker;
+            }
+        }
+        self.workers[start % count].lock().await
+    }
+
+    /// `FaceRecognition::run` on an idle worker
+    pub async fn run(
+        &self,
+        frame: &mut Mat,
+        threshold: f32,
+        visualize: bool,
+    ) -> Result<Vec<MatchResult>> {
+        self.worker().await.run(frame, threshold, visualize).await
+    }
+
+    /// `FaceRecognition::run_detailed` on an idle worker
+    pub async fn run_detailed(
+        &self,
+        frame: &mut Mat,
+        threshold: f32,
+        visualize: bool,
+    ) -> Result<Vec<std::result::Result<FaceResult, FaceRecognitionError>>> {
+        self.worker()
+            .await
+            .run_detailed(frame, threshold, visualize)
+            .await
+    }
+
+    /// `FaceRecognition::run_from_bytes` on an idle worker
+    pub async fn run_from_bytes(&self, bytes: &[u8], threshold: f32) -> Result<Vec<MatchResult>> {
+        self.worker().await.run_from_bytes(bytes, threshold).await
+    }
+
+    /// Load the shared database through one worker
+    pub async fn load_persons_db<P: AsRef<Path>>(
+        &self,
+        persondb_folder: P,
+        force: bool,
+        visualize: bool,
+    ) -> Result<LoadReport> {
+        self.worker()
+            .await
+            .load_persons_db(persondb_folder, force, visualize)
+            .await
+    }
+
+    /// Watch the shared database folder; a change is reloaded by whichever worker
+    /// handles the next request
+    pub async fn start_watching(&self, interval_secs: u64) -> Result<()> {
+        self.workers[0]
+            .lock()
+            .await
+            .start_watching(interval_secs)
+            .await
+    }
+
+    pub async fn stop_watching(&self) {
+        self.workers[0].lock().await.stop_watching().await
+    }
+
+    /// `FaceRecognition::subscribe_reloads`; reloads by any worker are reported
+    pub fn subscribe_reloads(&self) -> broadcast::Receiver<ReloadEvent> {
+        self.database.reload_events.subscribe()
+    }
+
+    /// Load status of the shared database, read without waiting for a worker
+    pub async fn db_load_status(&self) -> DbLoadStatus {
+        *self.database.status.read().await
+    }
+
+    /// Persons in the shared database, read without waiting for a worker
+    pub async fn list_persons(&self) -> Vec<String> {
+        sorted_person_names(&**self.database.features.read().await)
+    }
+}
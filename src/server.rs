@@ -0,0 +1,200 @@
+//! Minimal HTTP service around a [`FaceRecognitionPool`] (enabled by the `server` feature).
+//!
+//! Endpoints:
+//! - `POST /recognize` — multipart form with an `image` field, optional `?threshold=0.4`;
+//!   returns the match for every detected face as JSON
+//! - `GET /healthz` — liveness and database status
+//! - `POST /reload` — force a reload of the persons database
+//! - `GET /persons` — names in the loaded database
+
+use crate::logging::{error, info};
+use crate::{
+    DbLoadStatus, FaceRecognition, FaceRecognitionError, FaceRecognitionPool, MatchResult, Result,
+};
+use axum::{
+    extract::{Multipart, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use serde::{Deserialize, Serialize};
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+#[derive(Debug, Clone)]
+pub struct ServerConfig {
+    pub addr: SocketAddr,
+    pub db_path: PathBuf,
+    pub threshold: f32,
+    pub fd_model_path: Option<String>,
+    pub fr_model_path: Option<String>,
+    pub max_size: Option<i32>,
+    /// Largest upload in pixels, see `FaceRecognition::set_max_decode_pixels` (0: no limit)
+    pub max_decode_pixels: u64,
+    /// Number of recognition workers, i.e. requests processed concurrently; each loads
+    /// its own copy of the models
+    pub workers: usize,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            addr: SocketAddr::from(([127, 0, 0, 1], 8080)),
+            db_path: PathBuf::from("./media/db"),
+            threshold: 0.4,
+            fd_model_path: None,
+            fr_model_path: None,
+            max_size: None,
+            max_decode_pixels: 50_000_000,
+            workers: 1,
+        }
+    }
+}
+
+struct AppState {
+    face_rec: FaceRecognitionPool,
+    db_path: PathBuf,
+    threshold: f32,
+}
+
+type SharedState = Arc<AppState>;
+
+#[derive(Debug, Deserialize)]
+struct RecognizeQuery {
+    threshold: Option<f32>,
+}
+
+#[derive(Debug, Serialize)]
+struct RecognizeResponse {
+    faces: Vec<MatchResult>,
+}
+
+#[derive(Debug, Serialize)]
+struct HealthResponse {
+    status: &'static str,
+    db_status: DbLoadStatus,
+}
+
+#[derive(Debug, Serialize)]
+struct PersonsResponse {
+    persons: Vec<String>,
+}
+
+struct ApiError(StatusCode, String);
+
+impl From<FaceRecognitionError> for ApiError {
+    fn from(err: FaceRecognitionError) -> Self {
+        let status = match err {
+            FaceRecognitionError::InvalidImage => StatusCode::BAD_REQUEST,
+            FaceRecognitionError::ImageTooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            FaceRecognitionError::UnsupportedImageFormat => StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        };
+        Self(status, err.to_string())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        let body = Json(serde_json::json!({ "error": self.1 }));
+        (self.0, body).into_response()
+    }
+}
+
+/// Load the models and database from `config`, then serve requests until the process exits
+pub async fn serve(config: ServerConfig) -> Result<()> {
+    let face_rec = FaceRecognitionPool::new(config.workers, || {
+        let mut face_rec = FaceRecognition::new(
+            config.fd_model_path.as_deref(),
+            config.fr_model_path.as_deref(),
+            config.max_size,
+        )?;
+        face_rec.set_max_decode_pixels(config.max_decode_pixels);
+        Ok(face_rec)
+    })?;
+    face_rec
+        .load_persons_db(&config.db_path, false, false)
+        .await?;
+
+    let state = Arc::new(AppState {
+        face_rec,
+        db_path: config.db_path.clone(),
+        threshold: config.threshold,
+    });
+
+    let app = Router::new()
+        .route("/recognize", post(recognize))
+        .route("/healthz", get(healthz))
+        .route("/reload", post(reload))
+        .route("/persons", get(persons))
+        .with_state(state);
+
+    let listener = tokio::net::TcpListener::bind(config.addr).await?;
+    info!("Serving face recognition on http://{}", config.addr);
+    axum::serve(listener, app).await?;
+    Ok(())
+}
+
+async fn recognize(
+    State(state): State<SharedState>,
+    Query(query): Query<RecognizeQuery>,
+    mut multipart: Multipart,
+) -> std::result::Result<Json<RecognizeResponse>, ApiError> {
+    let mut image = None;
+    while let Some(field) = multipart
+        .next_field()
+        .await
+        .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?
+    {
+        if field.name() == Some("image") {
+            let bytes = field
+                .bytes()
+                .await
+                .map_err(|e| ApiError(StatusCode::BAD_REQUEST, e.to_string()))?;
+            image = Some(bytes);
+            break;
+        }
+    }
+    let image = image.ok_or_else(|| {
+        ApiError(
+            StatusCode::BAD_REQUEST,
+            "missing multipart field `image`".to_string(),
+        )
+    })?;
+
+    let threshold = query.threshold.unwrap_or(state.threshold);
+    let faces = state.face_rec.run_from_bytes(&image, threshold).await?;
+    Ok(Json(RecognizeResponse { faces }))
+}
+
+async fn healthz(State(state): State<SharedState>) -> Json<HealthResponse> {
+    let db_status = state.face_rec.db_load_status().await;
+    Json(HealthResponse {
+        status: "ok",
+        db_status,
+    })
+}
+
+async fn reload(
+    State(state): State<SharedState>,
+) -> std::result::Result<Json<PersonsResponse>, ApiError> {
+    if let Err(e) = state
+        .face_rec
+        .load_persons_db(&state.db_path, true, false)
+        .await
+    {
+        error!("Reload failed: {}", e);
+        return Err(e.into());
+    }
+    Ok(Json(PersonsResponse {
+        persons: state.face_rec.list_persons().await,
+    }))
+}
+
+async fn persons(State(state): State<SharedState>) -> Json<PersonsResponse> {
+    Json(PersonsResponse {
+        persons: state.face_rec.list_persons().await,
+    })
+}
@@ -0,0 +1,150 @@
+//! Storage of the enrolled reference features.
+//!
+//! `FaceRecognition` keeps its persons database in a `FeatureStore`. `InMemoryStore`, the
+//! default, holds everything in a `HashMap`; other implementations (e.g. backed by SQLite,
+//! Redis or pgvector) can be plugged in through `FaceRecognition::set_feature_store`.
+
+use crate::types::{cosine_similarity, feature_to_vec, StoredFeature};
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+/// A person and their features as handed out by `FeatureStore::iter`, borrowed or owned
+pub type StoreEntry<'a> = (Cow<'a, str>, Cow<'a, [StoredFeature]>);
+
+/// Persons and their reference features.
+///
+/// Person names are stored as given; `FaceRecognition` normalizes them before calling the
+/// store. `get` and `iter` hand out `Cow`s, so a store backed by a remote database can
+/// fetch features on demand and return them owned instead of keeping a local copy;
+/// `nearest` can be overridden to search remotely instead.
+pub trait FeatureStore: Send + Sync {
+    /// Append `feature` to the features of `person`, adding the person if needed
+    fn insert(&mut self, person: &str, feature: StoredFeature);
+
+    /// Remove `person` and return their features, `None` if the person is unknown
+    fn remove(&mut self, person: &str) -> Option<Vec<StoredFeature>>;
+
+    /// Features of `person`, `None` if the person is unknown
+    fn get(&self, person: &str) -> Option<Cow<'_, [StoredFeature]>>;
+
+    /// All persons with their features, in no particular order
+    fn iter(&self) -> Box<dyn Iterator<Item = StoreEntry<'_>> + '_>;
+
+    /// Replace the whole content, used by database loads, imports and restores
+    fn replace_all(&mut self, features: HashMap<String, Vec<StoredFeature>>);
+
+    /// Remove and return the whole content
+    fn take_all(&mut self) -> HashMap<String, Vec<StoredFeature>> {
+        let features = self.to_map();
+        self.replace_all(HashMap::new());
+        features
+    }
+
+    /// Copy of the whole content
+    fn to_map(&self) -> HashMap<String, Vec<StoredFeature>> {
+        self.iter()
+            .map(|(person, features)| (person.into_owned(), features.into_owned()))
+            .collect()
+    }
+
+    /// The `k` persons most similar to `probe`, best first, with each person's best plain
+    /// cosine similarity. The default compares against every stored feature. This is a
+    /// raw candidate search for store users; `FaceRecognition` scores through `iter` so
+    /// that reference weights, aggregation and the ensemble apply.
+    fn nearest(&self, probe: &[f32], k: usize) -> opencv::Result<Vec<(String, f32)>> {
+        let mut scores = Vec::new();
+        for (person, features) in self.iter() {
+            let mut best = f32::NEG_INFINITY;
+            for stored in features.iter() {
+                best = best.max(cosine_similarity(
+                    probe,
+                    &feature_to_vec(&stored.feature_mat()?)?,
+                ));
+            }
+            if !features.is_empty() {
+                scores.push((person.to_string(), best));
+            }
+        }
+        scores.sort_by(|a, b| b.1.total_cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+        scores.truncate(k);
+        Ok(scores)
+    }
+
+    /// Number of persons
+    fn len(&self) -> usize {
+        self.iter().count()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn contains(&self, person: &str) -> bool {
+        self.get(person).is_some()
+    }
+
+    /// Total number of stored features over all persons
+    fn feature_count(&self) -> usize {
+        self.iter().map(|(_, features)| features.len()).sum()
+    }
+}
+
+/// The default `FeatureStore`, keeping all features in memory
+#[derive(Debug, Default)]
+pub struct InMemoryStore {
+    features: HashMap<String, Vec<StoredFeature>>,
+}
+
+impl InMemoryStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl FeatureStore for InMemoryStore {
+    fn insert(&mut self, person: &str, feature: StoredFeature) {
+        self.features
+            .entry(person.to_string())
+            .or_default()
+            .push(feature);
+    }
+
+    fn remove(&mut self, person: &str) -> Option<Vec<StoredFeature>> {
+        self.features.remove(person)
+    }
+
+    fn get(&self, person: &str) -> Option<Cow<'_, [StoredFeature]>> {
+        self.features
+            .get(person)
+            .map(|features| Cow::Borrowed(features.as_slice()))
+    }
+
+    fn iter(&self) -> Box<dyn Iterator<Item = StoreEntry<'_>> + '_> {
+        Box::new(self.features.iter().map(|(person, features)| {
+            (
+                Cow::Borrowed(person.as_str()),
+                Cow::Borrowed(features.as_slice()),
+            )
+        }))
+    }
+
+    fn replace_all(&mut self, features: HashMap<String, Vec<StoredFeature>>) {
+        self.features = features;
+    }
+
+    fn take_all(&mut self) -> HashMap<String, Vec<StoredFeature>> {
+        std::mem::take(&mut self.features)
+    }
+
+    fn to_map(&self) -> HashMap<String, Vec<StoredFeature>> {
+        self.features.clone()
+    }
+
+    fn len(&self) -> usize {
+        self.features.len()
+    }
+
+    fn contains(&self, person: &str) -> bool {
+        self.features.contains_key(person)
+    }
+}
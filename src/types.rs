@@ -1,11 +1,19 @@
-use opencv::{core::Mat, core::Rect2i, core::Size, prelude::*};
+use opencv::{
+    core::Mat, core::Point2f, core::Rect2i, core::Size, objdetect::FaceRecognizerSF_DisType,
+    prelude::*,
+};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum DbLoadStatus {
     NotLoaded,
     Loading,
     Loaded,
+    /// The most recent load failed; the previous database, if any, is still in use
+    Failed,
 }
 
 impl std::fmt::Display for DbLoadStatus {
@@ -14,27 +22,540 @@ impl std::fmt::Display for DbLoadStatus {
             DbLoadStatus::NotLoaded => write!(f, "NOT_LOADED"),
             DbLoadStatus::Loading => write!(f, "LOADING"),
             DbLoadStatus::Loaded => write!(f, "LOADED"),
+            DbLoadStatus::Failed => write!(f, "FAILED"),
         }
     }
 }
 
-#[derive(Debug, Clone)]
+/// A detected face that was not stored during a database load
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedFace {
+    pub person: String,
+    pub image: PathBuf,
+    pub reason: String,
+}
+
+/// The same embedding stored under two different persons, usually one reference image
+/// copied into two person folders
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CrossPersonDuplicate {
+    pub first: String,
+    pub first_source: Option<PathBuf>,
+    pub second: String,
+    pub second_source: Option<PathBuf>,
+}
+
+/// Summary of a `load_persons_db` run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LoadReport {
+    pub persons_loaded: usize,
+    pub images_processed: usize,
+    /// Features in the database after the load, i.e. without evicted or invalid ones
+    pub features_loaded: usize,
+    /// Persons for which no feature could be stored
+    pub empty_persons: Vec<String>,
+    pub rejected_faces: Vec<RejectedFace>,
+    /// Images skipped because they could not be read or processed (e.g. corrupt files)
+    pub failed_images: Vec<PathBuf>,
+    /// Files referenced by a manifest that do not exist
+    pub missing_files: Vec<PathBuf>,
+    /// Features dropped because the total feature cap was exceeded
+    pub features_evicted: usize,
+    /// Names of the person filter (see `FaceRecognition::set_person_filter`) for which no
+    /// person was found
+    #[serde(default)]
+    pub persons_not_found: Vec<String>,
+    /// Identical embeddings found under different persons, filled only when
+    /// `FaceRecognition::set_detect_cross_person_duplicates` is enabled
+    #[serde(default)]
+    pub cross_person_duplicates: Vec<CrossPersonDuplicate>,
+    /// Person folders skipped because they resolve, through symlinks, to the database
+    /// folder, one of its parents or a folder that was already loaded
+    #[serde(default)]
+    pub skipped_dirs: Vec<PathBuf>,
+    /// Images not read because the feature cap was already full with
+    /// `FeatureCapPolicy::RejectNew`
+    #[serde(default)]
+    pub skipped_over_cap: Vec<PathBuf>,
+    /// Features dropped because they cannot be matched, see
+    /// `FaceRecognition::set_invalid_feature_policy`
+    #[serde(default)]
+    pub invalid_features: usize,
+}
+
+/// Accuracy of the labeled set at one threshold, see `FaceRecognition::suggest_threshold`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ThresholdPoint {
+    pub threshold: f32,
+    /// Fraction of probes whose predicted identity (or "Unknown") was correct
+    pub accuracy: f32,
+}
+
+/// Result of `FaceRecognition::suggest_threshold`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ThresholdSuggestion {
+    /// Threshold with the highest accuracy (the highest such threshold on ties)
+    pub threshold: f32,
+    pub accuracy: f32,
+    /// Number of labeled images with a detected face that were evaluated
+    pub probes: usize,
+    /// Accuracy for every evaluated threshold, in ascending threshold order
+    pub curve: Vec<ThresholdPoint>,
+    /// References left out because they came from the probe image itself, see
+    /// `FaceRecognition::set_leave_one_out`
+    #[serde(default)]
+    pub excluded_references: usize,
+    /// Accuracy at `threshold` ignoring the mirrored references of
+    /// `FaceRecognition::set_flip_augment`; `None` if the database has none
+    #[serde(default)]
+    pub accuracy_without_flip: Option<f32>,
+    /// Accuracy at `threshold` with the probes embedded without the test-time augmentation
+    /// of `FaceRecognition::set_tta`, against the same database; `None` without it
+    #[serde(default)]
+    pub accuracy_without_tta: Option<f32>,
+}
+
+/// Activity counters since creation or the last `FaceRecognition::reset_metrics`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Metrics {
+    /// Frames (queries and enrollment images) that went through face detection
+    pub frames_processed: u64,
+    pub faces_detected: u64,
+    /// Faces matched to a person above the threshold
+    pub matches_accepted: u64,
+    /// Database reloads triggered by the folder watcher
+    pub reloads: u64,
+    /// Faces reported as "Unknown" without matching because they were smaller than the
+    /// minimum probe eye distance
+    #[serde(default)]
+    pub faces_too_small: u64,
+}
+
+/// A completed watcher-triggered reload, see `FaceRecognition::subscribe_reloads`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReloadEvent {
+    /// When the reload finished
+    pub at: SystemTime,
+    /// Persons in the reloaded database that were not in the previous one
+    pub persons_added: Vec<String>,
+    /// Persons of the previous database that are gone
+    pub persons_removed: Vec<String>,
+    /// Number of persons after the reload
+    pub persons: usize,
+}
+
+/// Summary of a `FaceRecognition::reembed` run
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReembedReport {
+    pub images_processed: usize,
+    pub features_reembedded: usize,
+    /// Source images that could not be read or in which no face was detected anymore
+    pub failed_images: Vec<PathBuf>,
+    /// Features kept unchanged because their source image is unknown (e.g. imported)
+    pub features_without_source: usize,
+    /// Faces below the minimum enrollment eye distance, see
+    /// `FaceRecognition::set_min_enroll_eye_distance`
+    #[serde(default)]
+    pub faces_rejected: usize,
+    /// Features dropped because the total feature cap was exceeded
+    #[serde(default)]
+    pub features_evicted: usize,
+}
+
+/// Stage reported by `FaceRecognition::prepare`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PrepareStage {
+    Warming,
+    /// Persons loaded so far and total person folders
+    Loading(usize, usize),
+    Ready,
+}
+
+/// Whether probes may belong to people outside the database
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum RecognitionMode {
+    /// Probes below the threshold are reported as "Unknown"
+    #[default]
+    OpenSet,
+    /// Every probe is one of the enrolled persons: the threshold is ignored and the best
+    /// candidate is always returned (useful for benchmarks and evaluation)
+    ClosedSet,
+}
+
+/// Where `load_persons_db` with `visualize` writes the annotated copy of an enrollment
+/// image, see `FaceRecognition::set_visualize_naming`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum VisualizeNaming {
+    /// Next to the original as `<stem><suffix>.<ext>`. Such a file is only skipped when
+    /// loading if its original `<stem>.<ext>` is present too.
+    Suffix(String),
+    /// In the named subfolder of the person folder, under the original file name. Loading
+    /// never reads subfolders, so real references cannot be mistaken for output.
+    Subfolder(String),
+}
+
+impl Default for VisualizeNaming {
+    fn default() -> Self {
+        Self::Suffix("_visualize".to_string())
+    }
+}
+
+impl VisualizeNaming {
+    /// Path of the annotated copy of `image`
+    pub fn output_path(&self, image: &Path) -> PathBuf {
+        let file_name = image.file_name().unwrap_or_default();
+        match self {
+            Self::Suffix(suffix) => {
+                let stem = image
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("image");
+                let extension = image.extension().and_then(|e| e.to_str()).unwrap_or("jpg");
+                image.with_file_name(format!("{stem}{suffix}.{extension}"))
+            }
+            Self::Subfolder(folder) => image.with_file_name(folder).join(file_name),
+        }
+    }
+
+    /// Whether `path`, a file in a person folder, is the annotated copy of another image
+    pub fn is_output(&self, path: &Path) -> bool {
+        match self {
+            Self::Suffix(suffix) => path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|stem| stem.strip_suffix(suffix.as_str()))
+                .filter(|original| !original.is_empty())
+                .is_some_and(|original| {
+                    let mut original = path.with_file_name(original);
+                    if let Some(extension) = path.extension() {
+                        original.set_extension(extension);
+                    }
+                    original.is_file()
+                }),
+            Self::Subfolder(_) => false,
+        }
+    }
+}
+
+/// Order of the detected faces in the output of `run` and the other recognition calls,
+/// see `FaceRecognition::set_face_sort`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FaceSort {
+    /// As returned by the detector, which may change between similar frames
+    #[default]
+    Detector,
+    /// By the box's left edge, then its top edge
+    LeftToRight,
+    /// By the box's top edge, then its left edge
+    TopToBottom,
+    /// By box area, largest first
+    LargestFirst,
+    /// By detection score, highest first
+    ScoreDesc,
+}
+
+/// Precision of the stored reference features, see
+/// `FaceRecognition::set_feature_precision`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FeaturePrecision {
+    #[default]
+    F32,
+    /// Half precision: half the memory, scores differ from `F32` by about 1e-3
+    F16,
+}
+
+/// How matching decides between persons with exactly the same best score, e.g. when one
+/// reference image is enrolled under two names
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum TieBreak {
+    /// The alphabetically first person name wins, independent of load order
+    #[default]
+    Alphabetical,
+    /// The face is reported as "Unknown"; the tied persons stay in the ranked results
+    Unknown,
+}
+
+/// How the scores of a person's references combine into the person's score, using the
+/// reference weights. With all weights at 1.0 `Max` is the plain best score.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ReferenceAggregation {
+    /// Best score, each positive score scaled by its weight relative to the person's
+    /// largest weight; a lower weight never raises a score, so negative scores are kept
+    #[default]
+    Max,
+    /// Weighted mean of all scores
+    WeightedMean,
+}
+
+impl ReferenceAggregation {
+    /// Combine `(score, weight)` pairs; references with a weight of 0 are ignored and
+    /// `None` is returned if none is left
+    pub fn aggregate(self, scores: impl IntoIterator<Item = (f32, f32)>) -> Option<f32> {
+        let scores: Vec<(f32, f32)> = scores
+            .into_iter()
+            .filter(|&(_, weight)| weight > 0.0)
+            .collect();
+        if scores.is_empty() {
+            return None;
+        }
+        Some(match self {
+            Self::Max => {
+                let max_weight = scores.iter().map(|&(_, w)| w).fold(0.0, f32::max);
+                scores
+                    .iter()
+                    .map(|&(score, weight)| score.min(score * weight / max_weight))
+                    .fold(f32::NEG_INFINITY, f32::max)
+            }
+            Self::WeightedMean => {
+                let total: f32 = scores.iter().map(|&(_, w)| w).sum();
+                scores.iter().map(|&(score, w)| score * w).sum::<f32>() / total
+            }
+        })
+    }
+}
+
+/// What `FaceRecognition::run` does when no database has been loaded, or the loaded one
+/// holds no features at all, which would otherwise report every face as "Unknown"
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum MissingDatabasePolicy {
+    /// Log a warning (once per instance and case) and continue
+    #[default]
+    Warn,
+    /// Fail with `FaceRecognitionError::DatabaseNotLoaded`, or
+    /// `FaceRecognitionError::DatabaseEmpty` for a database without features
+    Error,
+    /// Continue silently, for detection-only use
+    Ignore,
+}
+
+/// What happens to stored features that are empty, non-finite or of another size than
+/// the rest of the database, e.g. from a partially failed extraction or a corrupt import,
+/// when they enter the database, see `FaceRecognition::set_invalid_feature_policy`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum InvalidFeaturePolicy {
+    /// Drop them with a warning and keep the remaining features
+    #[default]
+    Skip,
+    /// Fail with `FaceRecognitionError::InvalidStoredFeature`
+    Error,
+}
+
+/// How the box-crop fallback (used when landmark alignment fails) makes its crop square
+/// before resizing it to the recognizer input, so faces are not stretched
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum CropPadding {
+    /// Resize the (possibly non-square, edge-clipped) box as is, the behavior before
+    /// padding existed
+    #[default]
+    None,
+    /// Repeat the outermost pixels (`BORDER_REPLICATE`)
+    Replicate,
+    /// Mirror the image at the border (`BORDER_REFLECT_101`)
+    Reflect,
+    /// Fill with black (`BORDER_CONSTANT`)
+    Constant,
+}
+
+impl CropPadding {
+    /// The matching OpenCV border type, `None` when no padding is applied
+    pub fn border_type(self) -> Option<i32> {
+        match self {
+            CropPadding::None => None,
+            CropPadding::Replicate => Some(opencv::core::BORDER_REPLICATE),
+            CropPadding::Reflect => Some(opencv::core::BORDER_REFLECT_101),
+            CropPadding::Constant => Some(opencv::core::BORDER_CONSTANT),
+        }
+    }
+}
+
+/// Share of one recognizer in the fused score of an ensemble: its cosine score is mapped
+/// by `normalization` (to bring models with different score ranges onto one scale) and
+/// averaged with the other recognizers' scores using `weight`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FusionWeight {
+    pub weight: f32,
+    pub normalization: ConfidenceMapping,
+}
+
+impl Default for FusionWeight {
+    fn default() -> Self {
+        Self {
+            weight: 1.0,
+            normalization: ConfidenceMapping::Raw,
+        }
+    }
+}
+
+/// Color normalization applied to every frame before detection, to reduce the effect of
+/// differing camera color casts, see `FaceRecognition::set_color_normalization`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum ColorNorm {
+    #[default]
+    Off,
+    /// Contrast-limited histogram equalization (CLAHE, clip limit 2.0 on 8x8 tiles) of the
+    /// luminance only, so hues are kept
+    Equalize,
+    /// Gray-world white balance: scale the channels so their means are equal
+    GrayWorld,
+}
+
+/// Similarity measure used when comparing two embeddings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum SimilarityMetric {
+    /// Cosine similarity, higher is more similar
+    #[default]
+    Cosine,
+    /// L2 distance between normalized features, lower is more similar
+    NormL2,
+}
+
+impl SimilarityMetric {
+    /// The matching `FaceRecognizerSF_DisType` value
+    pub fn dis_type(self) -> i32 {
+        match self {
+            SimilarityMetric::Cosine => FaceRecognizerSF_DisType::FR_COSINE as i32,
+            SimilarityMetric::NormL2 => FaceRecognizerSF_DisType::FR_NORM_L2 as i32,
+        }
+    }
+}
+
+/// Tiled detection for very large or wide images, see `FaceRecognition::set_tiling`
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct TileConfig {
+    /// Edge length of the square tiles in original-image pixels
+    pub tile_size: i32,
+    /// Overlap between neighbouring tiles; should exceed the largest expected face
+    pub overlap: i32,
+    /// IoU above which detections from neighbouring tiles are merged
+    pub merge_iou: f32,
+}
+
+impl Default for TileConfig {
+    fn default() -> Self {
+        Self {
+            tile_size: 640,
+            overlap: 128,
+            merge_iou: 0.3,
+        }
+    }
+}
+
+/// Whether enrollment images are reduced to a face-centered crop, see
+/// `FaceRecognition::set_normalize_enrollment`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum EnrollmentNormalization {
+    #[default]
+    Off,
+    /// Write the crop next to the original as `<name>_face.<ext>`; such a file is
+    /// skipped when loading the database if its original `<name>.<ext>` is present too
+    Alongside,
+    /// Overwrite the original image with the crop
+    ReplaceOriginal,
+}
+
+/// File name marker of crops written next to their original
+const NORMALIZED_ENROLLMENT_SUFFIX: &str = "_face";
+
+impl EnrollmentNormalization {
+    /// Path the face-centered crop of `image` is written to
+    pub fn output_path(&self, image: &Path) -> PathBuf {
+        match self {
+            Self::ReplaceOriginal => image.to_path_buf(),
+            Self::Off | Self::Alongside => {
+                let stem = image
+                    .file_stem()
+                    .and_then(|s| s.to_str())
+                    .unwrap_or("image");
+                let extension = image.extension().and_then(|e| e.to_str()).unwrap_or("jpg");
+                image.with_file_name(format!("{stem}{NORMALIZED_ENROLLMENT_SUFFIX}.{extension}"))
+            }
+        }
+    }
+
+    /// Whether `path`, a file in a person folder, is the crop written next to another
+    /// image with `Alongside`; a reference that merely ends in `_face` is not
+    pub fn is_output(&self, path: &Path) -> bool {
+        *self == Self::Alongside
+            && path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|stem| stem.strip_suffix(NORMALIZED_ENROLLMENT_SUFFIX))
+                .filter(|original| !original.is_empty())
+                .is_some_and(|original| {
+                    let mut original = path.with_file_name(original);
+                    if let Some(extension) = path.extension() {
+                        original.set_extension(extension);
+                    }
+                    original.is_file()
+                })
+    }
+}
+
+/// How `load_persons_db` handles two person folders whose names normalize to the same key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum NameCollisionPolicy {
+    /// Merge the features of colliding folders under the first name seen
+    #[default]
+    Merge,
+    /// Abort the load with `FaceRecognitionError::NameCollision`
+    Error,
+}
+
+/// Normalize a person folder name: trims surrounding whitespace
+pub fn normalize_person_name(name: &str) -> String {
+    name.trim().to_string()
+}
+
+/// Key used to detect colliding person names (normalized and case-insensitive)
+pub fn person_name_key(name: &str) -> String {
+    normalize_person_name(name).to_lowercase()
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchResult {
     pub name: String,
+    /// Raw cosine similarity
     pub score: f32,
+    /// `score` mapped to 0..1 by the configured `ConfidenceMapping`
+    #[serde(default)]
+    pub confidence: f32,
+    /// No person was accepted; `name` is then a fallback label, see
+    /// `FaceRecognition::set_unmatched_labels`
+    #[serde(default)]
+    pub unmatched: bool,
 }
 
 impl MatchResult {
     pub fn new(name: String, score: f32) -> Self {
-        Self { name, score }
+        Self {
+            name,
+            score,
+            confidence: ConfidenceMapping::Raw.apply(score),
+            unmatched: false,
+        }
+    }
+
+    /// A result that matched nobody, reported under `label`
+    pub fn unmatched(label: String, score: f32) -> Self {
+        Self {
+            unmatched: true,
+            ..Self::new(label, score)
+        }
+    }
+
+    /// Set `confidence` from `score` using `mapping`
+    pub fn with_confidence(mut self, mapping: ConfidenceMapping) -> Self {
+        self.confidence = mapping.apply(self.score);
+        self
     }
 
     pub fn to_lower_case(&self) -> String {
         self.name.to_lowercase()
     }
 
+    /// Whether no person was accepted, whatever the fallback label
     pub fn is_unknown(&self) -> bool {
-        self.to_lower_case() == "unknown"
+        self.unmatched || self.to_lower_case() == "unknown"
     }
 
     pub fn to_string(&self) -> String {
@@ -44,10 +565,439 @@ impl MatchResult {
             format!("{} ({:.2})", self.name, self.score)
         }
     }
+
+    /// Format the score with `precision` decimals, as a percentage if `as_percent`
+    pub fn format_score(&self, precision: usize, as_percent: bool) -> String {
+        if as_percent {
+            format!("{:.*}%", precision, self.score * 100.0)
+        } else {
+            format!("{:.*}", precision, self.score)
+        }
+    }
 }
 
+/// One reference feature of a person in the database
 #[derive(Debug, Clone)]
+pub struct StoredFeature {
+    pub feature: Mat,
+    /// Detection score of the face the feature was extracted from
+    pub quality: f32,
+    /// Image the feature was extracted from, if known
+    pub source: Option<PathBuf>,
+    /// Insertion order within the database; lower is older
+    pub sequence: u64,
+    /// Int8 form of the feature when quantized storage is enabled; `feature` is then empty
+    pub quantized: Option<QuantizedFeature>,
+    /// f16 form of the feature with `FeaturePrecision::F16`; `feature` is then empty
+    pub half: Option<HalfFeature>,
+    /// Embeddings of the same face from the ensemble recognizers, in registration order
+    /// (empty without an ensemble, see `FaceRecognition::add_ensemble_recognizer`)
+    pub ensemble_features: Vec<Mat>,
+    /// Relative importance among the person's references (1.0 by default), see
+    /// `ReferenceAggregation`
+    pub weight: f32,
+    /// When the source image was taken, see `FaceRecognition::set_track_capture_time`
+    pub captured_at: Option<SystemTime>,
+    /// Embedding of the mirrored crop stored by `FaceRecognition::set_flip_augment`
+    pub mirrored: bool,
+}
+
+impl StoredFeature {
+    /// Reference holding `feature` with default metadata: quality 1.0, weight 1.0, no
+    /// source or capture time, sequence 0 and no compact form or ensemble embeddings
+    pub fn new(feature: Mat) -> Self {
+        Self {
+            feature,
+            quality: 1.0,
+            source: None,
+            sequence: 0,
+            quantized: None,
+            half: None,
+            ensemble_features: Vec::new(),
+            weight: 1.0,
+            captured_at: None,
+            mirrored: false,
+        }
+    }
+
+    /// The embedding as a `CV_32F` row, dequantized if stored as int8 or f16
+    pub fn feature_mat(&self) -> opencv::Result<Mat> {
+        match (&self.quantized, &self.half) {
+            (Some(quantized), _) => vec_to_feature(&quantized.dequantize()),
+            (None, Some(half)) => vec_to_feature(&half.to_vec()),
+            (None, None) => self.feature.try_clone(),
+        }
+    }
+
+    /// Number of values in the embedding
+    pub fn dim(&self) -> usize {
+        match (&self.quantized, &self.half) {
+            (Some(quantized), _) => quantized.values.len(),
+            (None, Some(half)) => half.values.len(),
+            (None, None) => self.feature.total(),
+        }
+    }
+
+    /// Bytes held by the embedding values in their current storage form: 4 per value as
+    /// `f32`, 2 as f16, and 1 plus the 4-byte scale as int8
+    pub fn feature_bytes(&self) -> usize {
+        match (&self.quantized, &self.half) {
+            (Some(quantized), _) => quantized.values.len() + std::mem::size_of::<f32>(),
+            (None, Some(half)) => half.values.len() * std::mem::size_of::<half::f16>(),
+            (None, None) => self.feature.total() * std::mem::size_of::<f32>(),
+        }
+    }
+
+    /// Replace the feature by its int8 form, cutting its memory by 4x
+    pub fn quantize(&mut self) -> opencv::Result<()> {
+        if self.quantized.is_none() {
+            self.quantized = Some(QuantizedFeature::from_slice(&feature_to_vec(
+                &self.feature_mat()?,
+            )?));
+            self.feature = Mat::default();
+            self.half = None;
+        }
+        Ok(())
+    }
+
+    /// Replace the `f32` feature by its f16 form, halving its memory. Quantized features
+    /// are left as they are.
+    pub fn to_half(&mut self) -> opencv::Result<()> {
+        if self.quantized.is_none() && self.half.is_none() {
+            self.half = Some(HalfFeature::from_slice(&feature_to_vec(&self.feature)?));
+            self.feature = Mat::default();
+        }
+        Ok(())
+    }
+}
+
+/// An embedding stored in half precision
+#[derive(Debug, Clone, PartialEq)]
+pub struct HalfFeature {
+    pub values: Vec<half::f16>,
+}
+
+impl HalfFeature {
+    pub fn from_slice(values: &[f32]) -> Self {
+        Self {
+            values: values.iter().map(|&v| half::f16::from_f32(v)).collect(),
+        }
+    }
+
+    pub fn to_vec(&self) -> Vec<f32> {
+        self.values.iter().map(|v| v.to_f32()).collect()
+    }
+
+    /// Cosine similarity with an `f32` probe, widening each value on the fly
+    pub fn cosine(&self, probe: &[f32]) -> f32 {
+        let mut dot = 0.0f32;
+        let mut norm_a = 0.0f32;
+        let mut norm_b = 0.0f32;
+        for (a, &b) in self.values.iter().zip(probe) {
+            let a = a.to_f32();
+            dot += a * b;
+            norm_a += a * a;
+            norm_b += b * b;
+        }
+        if norm_a == 0.0 || norm_b == 0.0 {
+            return 0.0;
+        }
+        dot / (norm_a.sqrt() * norm_b.sqrt())
+    }
+}
+
+/// Symmetric int8 quantization of an embedding: `value ≈ values[i] as f32 * scale`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct QuantizedFeature {
+    pub values: Vec<i8>,
+    pub scale: f32,
+}
+
+impl QuantizedFeature {
+    pub fn from_slice(values: &[f32]) -> Self {
+        let max = values.iter().fold(0.0f32, |max, v| max.max(v.abs()));
+        let scale = if max > 0.0 { max / 127.0 } else { 1.0 };
+        Self {
+            values: values
+                .iter()
+                .map(|v| (v / scale).round().clamp(-127.0, 127.0) as i8)
+                .collect(),
+            scale,
+        }
+    }
+
+    pub fn dequantize(&self) -> Vec<f32> {
+        self.values.iter().map(|&v| v as f32 * self.scale).collect()
+    }
+
+    /// Cosine similarity computed in integer arithmetic (the scales cancel out)
+    pub fn cosine(&self, other: &QuantizedFeature) -> f32 {
+        let mut dot = 0i64;
+        let mut norm_a = 0i64;
+        let mut norm_b = 0i64;
+        for (&a, &b) in self.values.iter().zip(&other.values) {
+            let (a, b) = (a as i64, b as i64);
+            dot += a * b;
+            norm_a += a * a;
+            norm_b += b * b;
+        }
+        if norm_a == 0 || norm_b == 0 {
+            return 0.0;
+        }
+        (dot as f64 / ((norm_a as f64).sqrt() * (norm_b as f64).sqrt())) as f32
+    }
+}
+
+/// Which features are dropped when the database exceeds its feature cap
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum FeatureCapPolicy {
+    /// Keep the features already stored and drop the newest ones; once the cap is full,
+    /// further enrollment images are not read at all
+    #[default]
+    RejectNew,
+    /// Drop the features that were stored first
+    EvictOldest,
+    /// Drop the features with the lowest detection score, oldest first on ties
+    EvictLowestQuality,
+}
+
+/// Current version of the `DatabaseExport` file format
+pub const DATABASE_EXPORT_VERSION: u32 = 1;
+
+/// Serializable form of a loaded database, written by `FaceRecognition::export_database`
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DatabaseExport {
+    pub version: u32,
+    /// Embedding dimension of the recognizer that produced the features
+    pub feature_dim: i32,
+    pub persons: BTreeMap<String, Vec<Vec<f32>>>,
+    #[serde(default)]
+    pub person_thresholds: BTreeMap<String, f32>,
+    /// Reference weights per person, parallel to `persons`; persons whose references all
+    /// have the default weight of 1.0 are left out
+    #[serde(default)]
+    pub weights: BTreeMap<String, Vec<f32>>,
+    /// Ensemble embeddings per person, parallel to `persons`; persons stored without an
+    /// ensemble are left out
+    #[serde(default)]
+    pub ensemble: BTreeMap<String, Vec<Vec<Vec<f32>>>>,
+}
+
+/// Monotonic mapping of a raw cosine score to a 0..1 confidence for display
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub enum ConfidenceMapping {
+    /// The raw score clamped to 0..1
+    #[default]
+    Raw,
+    /// Linear from 0 at `low` to 1 at `high`, clamped; e.g. threshold ± 0.2
+    Linear { low: f32, high: f32 },
+    /// Logistic curve reaching 0.5 at `midpoint` (typically the threshold)
+    Sigmoid { midpoint: f32, steepness: f32 },
+}
+
+impl ConfidenceMapping {
+    pub fn apply(self, score: f32) -> f32 {
+        match self {
+            ConfidenceMapping::Raw => score.clamp(0.0, 1.0),
+            ConfidenceMapping::Linear { low, high } => {
+                if high <= low {
+                    return if score >= high { 1.0 } else { 0.0 };
+                }
+                ((score - low) / (high - low)).clamp(0.0, 1.0)
+            }
+            ConfidenceMapping::Sigmoid {
+                midpoint,
+                steepness,
+            } => 1.0 / (1.0 + (-steepness * (score - midpoint)).exp()),
+        }
+    }
+}
+
+/// One stored feature inside a `DatabaseSnapshot`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotFeature {
+    pub values: Vec<f32>,
+    pub quality: f32,
+    pub source: Option<PathBuf>,
+    pub sequence: u64,
+    #[serde(default = "default_weight")]
+    pub weight: f32,
+    #[serde(default)]
+    pub captured_at: Option<SystemTime>,
+    /// Embeddings from the ensemble recognizers, see `StoredFeature::ensemble_features`
+    #[serde(default)]
+    pub ensemble: Vec<Vec<f32>>,
+    #[serde(default)]
+    pub mirrored: bool,
+}
+
+fn default_weight() -> f32 {
+    1.0
+}
+
+/// Complete in-memory database state, taken by `FaceRecognition::snapshot` and handed to
+/// another instance with `FaceRecognition::restore`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DatabaseSnapshot {
+    /// Embedding dimension of the recognizer that produced the features
+    pub feature_dim: i32,
+    pub persons: BTreeMap<String, Vec<SnapshotFeature>>,
+    pub person_thresholds: BTreeMap<String, f32>,
+    /// Database folder the features were loaded from, if any
+    pub db_path: Option<PathBuf>,
+    pub load_report: LoadReport,
+}
+
+/// Intersection over union of two rectangles
+pub fn rect_iou(a: Rect2i, b: Rect2i) -> f32 {
+    let x0 = a.x.max(b.x);
+    let y0 = a.y.max(b.y);
+    let x1 = (a.x + a.width).min(b.x + b.width);
+    let y1 = (a.y + a.height).min(b.y + b.height);
+    let intersection = ((x1 - x0).max(0) as i64 * (y1 - y0).max(0) as i64) as f32;
+    let union = a.area() as f32 + b.area() as f32 - intersection;
+    if union <= 0.0 {
+        0.0
+    } else {
+        intersection / union
+    }
+}
+
+/// Copy a single-row `CV_32F` feature into a `Vec`
+pub fn feature_to_vec(feature: &Mat) -> opencv::Result<Vec<f32>> {
+    if feature.empty() {
+        return Ok(Vec::new());
+    }
+    Ok(feature.reshape(1, 1)?.data_typed::<f32>()?.to_vec())
+}
+
+/// Cosine similarity of two embeddings, 0 if either is all zeros or their lengths differ
+pub fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm_a = a.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norm_b = b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm_a == 0.0 || norm_b == 0.0 {
+        0.0
+    } else {
+        dot / (norm_a * norm_b)
+    }
+}
+
+/// Mean of the L2-normalized `features`, `None` if there are none
+pub fn feature_centroid<'a>(features: impl IntoIterator<Item = &'a [f32]>) -> Option<Vec<f32>> {
+    let mut sum: Vec<f32> = Vec::new();
+    let mut count = 0;
+    for feature in features {
+        if sum.is_empty() {
+            sum = vec![0.0; feature.len()];
+        }
+        let norm = feature.iter().map(|x| x * x).sum::<f32>().sqrt();
+        if norm == 0.0 || feature.len() != sum.len() {
+            continue;
+        }
+        for (total, value) in sum.iter_mut().zip(feature) {
+            *total += value / norm;
+        }
+        count += 1;
+    }
+    if count == 0 {
+        return None;
+    }
+    sum.iter_mut().for_each(|total| *total /= count as f32);
+    Some(sum)
+}
+
+/// Build a single-row `CV_32F` feature from a slice
+pub fn vec_to_feature(values: &[f32]) -> opencv::Result<Mat> {
+    Mat::from_slice(values)?.try_clone()
+}
+
+/// Serde form of a `Rect2i`: `{"x": .., "y": .., "width": .., "height": ..}`
+mod rect_serde {
+    use opencv::core::Rect2i;
+    use serde::{Deserialize, Deserializer, Serialize, Serializer};
+
+    #[derive(Serialize, Deserialize)]
+    struct Rect {
+        x: i32,
+        y: i32,
+        width: i32,
+        height: i32,
+    }
+
+    pub fn serialize<S: Serializer>(rect: &Rect2i, serializer: S) -> Result<S::Ok, S::Error> {
+        Rect {
+            x: rect.x,
+            y: rect.y,
+            width: rect.width,
+            height: rect.height,
+        }
+        .serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Rect2i, D::Error> {
+        let rect = Rect::deserialize(deserializer)?;
+        Ok(Rect2i::new(rect.x, rect.y, rect.width, rect.height))
+    }
+}
+
+/// Recognition result for one detected face
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FaceResult {
+    /// Position of the face in the output order (detector order unless
+    /// `FaceRecognition::set_face_sort` is used)
+    pub index: usize,
+    /// Bounding box in the coordinates of the frame passed to `run_detailed`
+    #[serde(with = "rect_serde")]
+    pub bbox: Rect2i,
+    pub best_match: MatchResult,
+}
+
+/// Identifier of a face tracked across video frames, see `FaceRecognition::best_shots`
+pub type TrackId = u64;
+
+/// One face of `FaceRecognition::run_ranked_faces`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RankedFace {
+    /// Position of the face in the output order, see `FaceResult::index`
+    pub index: usize,
+    /// Bounding box in the coordinates of the frame passed to `run_ranked_faces`
+    #[serde(with = "rect_serde")]
+    pub bbox: Rect2i,
+    pub ranking: MatchResults,
+}
+
+/// Result of `FaceRecognition::run_with_duplicate_detection`
+#[derive(Debug, Clone)]
+pub struct DuplicateDetection {
+    /// Recognized faces; faces whose matching failed are left out as in `run`
+    pub faces: Vec<FaceResult>,
+    /// Groups of face indices (see `FaceResult::index`) that look like the same identity.
+    /// Only groups with two or more faces are listed.
+    pub duplicate_groups: Vec<Vec<usize>>,
+}
+
+/// One candidate of `FaceRecognition::explain`
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExplainEntry {
+    pub name: String,
+    /// Best cosine similarity over the person's references
+    pub score: f32,
+    /// Threshold the score had to exceed: the person's own threshold if set, else the
+    /// one passed to `explain`
+    pub threshold: f32,
+    /// `threshold - score`: how far the score fell short (negative if it passed)
+    pub gap: f32,
+    pub accepted: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MatchResults {
+    /// One entry per person with their best score over all reference features, sorted
+    /// by descending score (then by name)
     pub results: Vec<MatchResult>,
     pub best_match: MatchResult,
 }
@@ -59,6 +1009,14 @@ pub struct DetectedFace {
     pub feature: Mat,
     pub original_size: Size,
     pub detection_size: Size, // Size of frame when detection was performed
+    /// Embeddings from the ensemble recognizers, in registration order; an empty `Mat`
+    /// where a recognizer failed
+    pub ensemble_features: Vec<Mat>,
+    /// Embedding of the horizontally mirrored crop, computed for enrollment faces when
+    /// `FaceRecognition::set_flip_augment` is enabled
+    pub flipped_feature: Option<Mat>,
+    /// Crop the feature was extracted from, kept with `FaceRecognition::set_keep_aligned`
+    pub(crate) aligned: Option<Mat>,
 }
 
 impl DetectedFace {
@@ -69,6 +1027,9 @@ impl DetectedFace {
             feature,
             original_size,
             detection_size: original_size, // Default to original_size for backward compatibility
+            ensemble_features: Vec::new(),
+            flipped_feature: None,
+            aligned: None,
         }
     }
 
@@ -85,6 +1046,9 @@ impl DetectedFace {
             feature,
             original_size,
             detection_size,
+            ensemble_features: Vec::new(),
+            flipped_feature: None,
+            aligned: None,
         }
     }
 
@@ -101,6 +1065,79 @@ impl DetectedFace {
         Ok(Rect2i::new(x, y, w, h))
     }
 
+    /// The aligned crop the feature was extracted from, `None` unless
+    /// `FaceRecognition::set_keep_aligned` is enabled
+    pub fn aligned(&self) -> Option<&Mat> {
+        self.aligned.as_ref()
+    }
+
+    /// Detection confidence reported by YuNet
+    pub fn score(&self) -> opencv::Result<f32> {
+        if self.face_detect.empty() {
+            return Ok(0.0);
+        }
+        Ok(*self.face_detect.at_2d::<f32>(0, 14)?)
+    }
+
+    /// The embedding as a plain vector
+    pub fn feature_vec(&self) -> opencv::Result<Vec<f32>> {
+        feature_to_vec(&self.feature)
+    }
+
+    /// The embedding quantized to int8, with the scale that maps the values back
+    pub fn feature_vec_i8(&self) -> opencv::Result<(Vec<i8>, f32)> {
+        let quantized = QuantizedFeature::from_slice(&self.feature_vec()?);
+        Ok((quantized.values, quantized.scale))
+    }
+
+    /// The five YuNet landmarks (right eye, left eye, nose tip, right and left mouth
+    /// corner) in detection_size coordinates
+    pub fn landmarks(&self) -> opencv::Result<[Point2f; 5]> {
+        let mut points = [Point2f::default(); 5];
+        if self.face_detect.empty() {
+            return Ok(points);
+        }
+
+        for (i, point) in points.iter_mut().enumerate() {
+            let col = 4 + 2 * i as i32;
+            point.x = *self.face_detect.at_2d::<f32>(0, col)?;
+            point.y = *self.face_detect.at_2d::<f32>(0, col + 1)?;
+        }
+        Ok(points)
+    }
+
+    /// Landmarks scaled to a specific frame size
+    pub fn landmarks_scaled(&self, target_size: Size) -> opencv::Result<[Point2f; 5]> {
+        let mut points = self.landmarks()?;
+        if self.detection_size.width > 0 && self.detection_size.height > 0 {
+            let scale_x = target_size.width as f32 / self.detection_size.width as f32;
+            let scale_y = target_size.height as f32 / self.detection_size.height as f32;
+            for point in points.iter_mut() {
+                point.x *= scale_x;
+                point.y *= scale_y;
+            }
+        }
+        Ok(points)
+    }
+
+    /// Landmarks in the coordinates of the original (unresized) image
+    pub fn landmarks_original(&self) -> opencv::Result<[Point2f; 5]> {
+        self.landmarks_scaled(self.original_size)
+    }
+
+    /// Distance between the eye landmarks in original image pixels
+    pub fn inter_ocular_distance(&self) -> opencv::Result<f32> {
+        let [right_eye, left_eye, ..] = self.landmarks_original()?;
+        let dx = left_eye.x - right_eye.x;
+        let dy = left_eye.y - right_eye.y;
+        Ok((dx * dx + dy * dy).sqrt())
+    }
+
+    /// Bounding box in the coordinates of the original (unresized) image
+    pub fn bbox_original(&self) -> opencv::Result<Rect2i> {
+        self.bbox_scaled(self.original_size)
+    }
+
     /// Get bounding box scaled to a specific frame size
     pub fn bbox_scaled(&self, target_size: Size) -> opencv::Result<Rect2i> {
         if self.face_detect.empty() {
@@ -134,3 +1171,103 @@ impl DetectedFace {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Deterministic pseudo-random embedding with values in -1..1
+    fn embedding(seed: u32, dim: usize) -> Vec<f32> {
+        let mut state = seed.wrapping_mul(2_654_435_761).wrapping_add(1);
+        (0..dim)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    fn stored(values: &[f32]) -> StoredFeature {
+        StoredFeature::new(vec_to_feature(values).unwrap())
+    }
+
+    #[test]
+    fn compact_features_use_less_memory() {
+        let mut feature = stored(&embedding(1, 128));
+        assert_eq!(feature.feature_bytes(), 512);
+
+        feature.to_half().unwrap();
+        assert_eq!(feature.feature_bytes(), 256);
+        assert_eq!(feature.dim(), 128);
+
+        let mut feature = stored(&embedding(1, 128));
+        feature.quantize().unwrap();
+        assert_eq!(feature.feature_bytes(), 132);
+        assert_eq!(feature.dim(), 128);
+    }
+
+    #[test]
+    fn compact_features_keep_scores_and_ranking() {
+        let gallery: Vec<Vec<f32>> = (0..50).map(|seed| embedding(seed, 128)).collect();
+        for probe_seed in 100..110 {
+            // A probe close to one gallery entry, so there is a clear best match
+            let target = &gallery[probe_seed as usize % gallery.len()];
+            let noise = embedding(probe_seed, 128);
+            let probe: Vec<f32> = target
+                .iter()
+                .zip(&noise)
+                .map(|(t, n)| t + 0.3 * n)
+                .collect();
+            let quantized_probe = QuantizedFeature::from_slice(&probe);
+
+            let mut best = [(0, f32::MIN); 3];
+            for (index, reference) in gallery.iter().enumerate() {
+                let exact = cosine_similarity(&probe, reference);
+                let half = HalfFeature::from_slice(reference).cosine(&probe);
+                let int8 = QuantizedFeature::from_slice(reference).cosine(&quantized_probe);
+                assert!((half - exact).abs() < 1e-3, "f16 {half} vs f32 {exact}");
+                assert!((int8 - exact).abs() < 1e-2, "int8 {int8} vs f32 {exact}");
+                for (entry, score) in best.iter_mut().zip([exact, half, int8]) {
+                    if score > entry.1 {
+                        *entry = (index, score);
+                    }
+                }
+            }
+            assert_eq!(best[0].0, probe_seed as usize % gallery.len());
+            assert_eq!(best[1].0, best[0].0);
+            assert_eq!(best[2].0, best[0].0);
+        }
+    }
+
+    #[test]
+    fn reference_weights_never_raise_scores() {
+        let max = ReferenceAggregation::Max;
+        assert_eq!(max.aggregate([(0.8, 1.0), (0.6, 0.5)]), Some(0.8));
+        assert_eq!(max.aggregate([(0.8, 0.5), (0.6, 1.0)]), Some(0.6));
+        assert_eq!(max.aggregate([(-0.2, 0.5), (-0.4, 1.0)]), Some(-0.2));
+        assert_eq!(max.aggregate([(0.9, 0.0)]), None);
+        assert_eq!(
+            ReferenceAggregation::WeightedMean.aggregate([(0.75, 3.0), (0.25, 1.0)]),
+            Some(0.625)
+        );
+        assert_eq!(ReferenceAggregation::WeightedMean.aggregate([]), None);
+    }
+
+    #[test]
+    fn normalized_crop_needs_its_original() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["anna.jpg", "anna_face.jpg", "john_face.jpg"] {
+            std::fs::write(dir.path().join(name), b"").unwrap();
+        }
+
+        let alongside = EnrollmentNormalization::Alongside;
+        assert!(alongside.is_output(&dir.path().join("anna_face.jpg")));
+        assert!(!alongside.is_output(&dir.path().join("john_face.jpg")));
+        assert!(!alongside.is_output(&dir.path().join("anna.jpg")));
+        assert!(!EnrollmentNormalization::Off.is_output(&dir.path().join("anna_face.jpg")));
+        assert_eq!(
+            alongside.output_path(&dir.path().join("anna.jpg")),
+            dir.path().join("anna_face.jpg")
+        );
+    }
+}
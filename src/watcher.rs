@@ -1,8 +1,11 @@
+use crate::logging::{debug, error, info, warn};
 use notify::{Config, Event, EventKind, RecommendedWatcher, RecursiveMode, Watcher};
-use std::path::Path;
-use std::sync::mpsc;
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc};
 use std::time::{Duration, SystemTime};
-use tracing::{debug, error, info, warn};
+use tokio::task::JoinHandle;
 
 use crate::{FaceRecognitionError, Result};
 
@@ -42,7 +45,25 @@ impl FolderWatcher {
         info!("Stopped watching directory");
     }
 
-    pub async fn watch_for_changes<F>(&mut self, mut callback: F) -> Result<()>
+    pub async fn watch_for_changes<F>(&mut self, callback: F) -> Result<()>
+    where
+        F: FnMut() + Send + 'static,
+    {
+        self.spawn_change_loop(Arc::new(AtomicBool::new(false)), callback)?
+            .await
+            .map_err(|e| FaceRecognitionError::WatchError(e.to_string()))?;
+
+        Ok(())
+    }
+
+    /// Run the event loop on a blocking thread, calling `callback` for (debounced)
+    /// changes. While `paused` is set, events are dropped; if any were dropped, `callback`
+    /// runs once after `paused` is cleared again. The loop ends when the watcher stops.
+    pub fn spawn_change_loop<F>(
+        &mut self,
+        paused: Arc<AtomicBool>,
+        mut callback: F,
+    ) -> Result<JoinHandle<()>>
     where
         F: FnMut() + Send + 'static,
     {
@@ -51,8 +72,9 @@ impl FolderWatcher {
             .take()
             .ok_or_else(|| FaceRecognitionError::WatchError("Watcher not started".to_string()))?;
 
-        tokio::task::spawn_blocking(move || {
+        Ok(tokio::task::spawn_blocking(move || {
             let mut last_change_time = SystemTime::now();
+            let mut missed_while_paused = false;
 
             loop {
                 match receiver.recv_timeout(Duration::from_secs(1)) {
@@ -62,6 +84,12 @@ impl FolderWatcher {
                         // Filter for relevant events (file modifications/creations)
                         match event.kind {
                             EventKind::Create(_) | EventKind::Modify(_) => {
+                                if paused.load(Ordering::Relaxed) {
+                                    debug!("Watcher paused, dropping event");
+                                    missed_while_paused = true;
+                                    continue;
+                                }
+
                                 let now = SystemTime::now();
                                 // Debounce events - only trigger if more than 2 seconds have passed
                                 if now
@@ -83,6 +111,12 @@ impl FolderWatcher {
                         error!("File watcher error: {}", e);
                     }
                     Err(mpsc::RecvTimeoutError::Timeout) => {
+                        if missed_while_paused && !paused.load(Ordering::Relaxed) {
+                            info!("Watcher resumed after missed changes, triggering reload...");
+                            callback();
+                            missed_while_paused = false;
+                            last_change_time = SystemTime::now();
+                        }
                         // Normal timeout, continue watching
                         continue;
                     }
@@ -92,11 +126,7 @@ impl FolderWatcher {
                     }
                 }
             }
-        })
-        .await
-        .map_err(|e| FaceRecognitionError::WatchError(e.to_string()))?;
-
-        Ok(())
+        }))
     }
 }
 
@@ -106,16 +136,22 @@ impl Drop for FolderWatcher {
     }
 }
 
+/// Latest modification time of the files below `path`. Symlinked folders are followed, but
+/// each real folder is visited once, so symlink cycles cannot recurse forever.
 pub fn get_latest_mod_time<P: AsRef<Path>>(path: P) -> Result<SystemTime> {
     let mut latest_time = SystemTime::UNIX_EPOCH;
 
-    fn visit_dir(dir: &Path, latest: &mut SystemTime) -> std::io::Result<()> {
-        if dir.is_dir() {
+    fn visit_dir(
+        dir: &Path,
+        latest: &mut SystemTime,
+        visited: &mut HashSet<PathBuf>,
+    ) -> std::io::Result<()> {
+        if dir.is_dir() && visited.insert(dir.canonicalize()?) {
             for entry in std::fs::read_dir(dir)? {
                 let entry = entry?;
                 let path = entry.path();
                 if path.is_dir() {
-                    visit_dir(&path, latest)?;
+                    visit_dir(&path, latest, visited)?;
                 } else {
                     let metadata = entry.metadata()?;
                     let modified = metadata.modified()?;
@@ -128,6 +164,6 @@ pub fn get_latest_mod_time<P: AsRef<Path>>(path: P) -> Result<SystemTime> {
         Ok(())
     }
 
-    visit_dir(path.as_ref(), &mut latest_time)?;
+    visit_dir(path.as_ref(), &mut latest_time, &mut HashSet::new())?;
     Ok(latest_time)
 }
@@ -0,0 +1,57 @@
+//! Setup shared by the integration tests, which run without models on the scripted
+//! backends of the `mock` feature: `cargo test --features mock`
+
+#![allow(dead_code)]
+
+use std::path::Path;
+
+use facerust::mock::{ScriptedDetector, ScriptedRecognizer};
+use facerust::opencv::{
+    core::{Mat, Scalar, Vector, CV_8UC3},
+    imgcodecs::imwrite,
+};
+use facerust::types::vec_to_feature;
+use facerust::{FaceRecognition, StoredFeature};
+
+pub const DIM: usize = 128;
+
+/// An instance whose detector reports `faces` in every frame and whose recognizer hands
+/// out `embeddings` in turn
+pub fn face_recognition(faces: Vec<[f32; 15]>, embeddings: Vec<Vec<f32>>) -> FaceRecognition {
+    FaceRecognition::with_backends(
+        Box::new(ScriptedDetector::new(faces)),
+        Box::new(ScriptedRecognizer::new(embeddings)),
+        None,
+    )
+}
+
+/// Detection row of a face in the middle of a `frame()`
+pub fn face() -> [f32; 15] {
+    ScriptedDetector::face(100.0, 100.0, 200.0, 200.0)
+}
+
+/// Unit embedding along `axis`, tilted towards `axis + 1` by `tilt`
+pub fn embedding(axis: usize, tilt: f32) -> Vec<f32> {
+    let mut values = vec![0.0; DIM];
+    values[axis] = 1.0;
+    values[(axis + 1) % DIM] = tilt;
+    values
+}
+
+pub fn stored(values: &[f32], sequence: u64) -> StoredFeature {
+    StoredFeature {
+        quality: 0.99,
+        sequence,
+        ..StoredFeature::new(vec_to_feature(values).unwrap())
+    }
+}
+
+pub fn frame() -> Mat {
+    Mat::new_rows_cols_with_default(400, 400, CV_8UC3, Scalar::all(0.0)).unwrap()
+}
+
+/// Write a plain gray image the scripted detector finds `face()` in
+pub fn write_image(path: &Path) {
+    let image = Mat::new_rows_cols_with_default(400, 400, CV_8UC3, Scalar::all(128.0)).unwrap();
+    assert!(imwrite(path.to_str().unwrap(), &image, &Vector::new()).unwrap());
+}
@@ -0,0 +1,60 @@
+//! Validation of settings
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use facerust::{FaceRecognition, FaceRecognitionError, TileConfig, VisualizeNaming};
+
+fn face_recognition() -> FaceRecognition {
+    common::face_recognition(Vec::new(), Vec::new())
+}
+
+#[test]
+fn rejects_tiling_without_progress() {
+    let mut face_rec = face_recognition();
+    for (tile_size, overlap) in [(0, 0), (-64, 0), (256, 256), (256, 300), (256, -1)] {
+        let config = TileConfig {
+            tile_size,
+            overlap,
+            ..TileConfig::default()
+        };
+        assert!(
+            matches!(
+                face_rec.set_tiling(Some(config)),
+                Err(FaceRecognitionError::InvalidConfig(_))
+            ),
+            "tile size {tile_size}, overlap {overlap}"
+        );
+    }
+
+    assert!(face_rec.set_tiling(Some(TileConfig::default())).is_ok());
+    assert!(face_rec.set_tiling(None).is_ok());
+}
+
+#[test]
+fn rejects_visualize_names_that_escape_the_person_folder() {
+    let mut face_rec = face_recognition();
+    for naming in [
+        VisualizeNaming::Suffix(String::new()),
+        VisualizeNaming::Suffix("_a/b".to_string()),
+        VisualizeNaming::Subfolder(String::new()),
+        VisualizeNaming::Subfolder("../x".to_string()),
+        VisualizeNaming::Subfolder("a\\b".to_string()),
+    ] {
+        assert!(
+            matches!(
+                face_rec.set_visualize_naming(naming.clone()),
+                Err(FaceRecognitionError::InvalidConfig(_))
+            ),
+            "{naming:?}"
+        );
+    }
+
+    assert!(face_rec
+        .set_visualize_naming(VisualizeNaming::Subfolder("visualize".to_string()))
+        .is_ok());
+    assert!(face_rec
+        .set_visualize_naming(VisualizeNaming::default())
+        .is_ok());
+}
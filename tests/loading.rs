@@ -0,0 +1,121 @@
+//! Loading person folders from disk
+
+#![cfg(all(feature = "mock", unix))]
+
+mod common;
+
+use std::os::unix::fs::symlink;
+
+use common::{embedding, face, write_image};
+use facerust::{FaceRecognition, FaceRecognitionError, NameCollisionPolicy};
+
+/// An instance finding one face with the same embedding in every image
+fn face_recognition() -> FaceRecognition {
+    common::face_recognition(vec![face()], vec![embedding(0, 0.0)])
+}
+
+#[tokio::test]
+async fn skips_symlink_loops_and_duplicate_links() {
+    let db = tempfile::tempdir().unwrap();
+    let alice = db.path().join("Alice");
+    std::fs::create_dir(&alice).unwrap();
+    write_image(&alice.join("1.png"));
+    // A link back to the database root and a second link to Alice's folder
+    symlink(db.path(), db.path().join("Loop")).unwrap();
+    symlink(&alice, db.path().join("Alice2")).unwrap();
+
+    let mut face_rec = face_recognition();
+    let report = face_rec
+        .load_persons_db(db.path(), true, false)
+        .await
+        .unwrap();
+
+    assert_eq!(report.persons_loaded, 1);
+    assert_eq!(report.features_loaded, 1);
+    assert_eq!(report.skipped_dirs.len(), 2);
+    assert_eq!(face_rec.list_persons().await, vec!["Alice".to_string()]);
+}
+
+#[tokio::test]
+async fn keeps_archive_features_when_reembedding() {
+    let dir = tempfile::tempdir().unwrap();
+    let alice = dir.path().join("db").join("Alice");
+    std::fs::create_dir_all(&alice).unwrap();
+    write_image(&alice.join("1.png"));
+    let archive = dir.path().join("db.tar");
+    let mut builder = tar::Builder::new(std::fs::File::create(&archive).unwrap());
+    builder.append_dir_all("db", dir.path().join("db")).unwrap();
+    builder.finish().unwrap();
+    drop(builder);
+
+    let mut face_rec = face_recognition();
+    face_rec.load_persons_db_archive(&archive).await.unwrap();
+    // The extracted images are gone, so nothing can be re-read
+    let report = face_rec.reembed().await.unwrap();
+
+    assert_eq!(report.features_without_source, 1);
+    assert!(report.failed_images.is_empty());
+    assert_eq!(face_rec.person_feature_count("Alice").await, Some(1));
+}
+
+/// A database with the person folders `Alice` and `alice`, one image each
+fn case_colliding_db() -> tempfile::TempDir {
+    let db = tempfile::tempdir().unwrap();
+    for name in ["Alice", "alice"] {
+        let person = db.path().join(name);
+        std::fs::create_dir(&person).unwrap();
+        write_image(&person.join("1.png"));
+    }
+    db
+}
+
+#[tokio::test]
+async fn merges_person_folders_differing_by_case() {
+    let db = case_colliding_db();
+
+    let mut face_rec = face_recognition();
+    let report = face_rec
+        .load_persons_db(db.path(), true, false)
+        .await
+        .unwrap();
+
+    assert_eq!(report.persons_loaded, 1);
+    assert_eq!(face_rec.list_persons().await, vec!["Alice".to_string()]);
+    assert_eq!(face_rec.person_feature_count("Alice").await, Some(2));
+}
+
+#[tokio::test]
+async fn rejects_person_folders_differing_by_case() {
+    let db = case_colliding_db();
+
+    let mut face_rec = face_recognition();
+    face_rec.set_name_collision_policy(NameCollisionPolicy::Error);
+    let result = face_rec.load_persons_db(db.path(), true, false).await;
+
+    assert!(matches!(
+        result,
+        Err(FaceRecognitionError::NameCollision(_))
+    ));
+}
+
+#[tokio::test]
+async fn lists_truncated_images_as_failed() {
+    let db = tempfile::tempdir().unwrap();
+    let alice = db.path().join("Alice");
+    std::fs::create_dir(&alice).unwrap();
+    write_image(&alice.join("1.jpg"));
+    let bytes = std::fs::read(alice.join("1.jpg")).unwrap();
+    let truncated = alice.join("2.jpg");
+    // Cut off inside the headers: OpenCV decodes a JPEG cut off in its scan data with
+    // gray filling instead of failing
+    std::fs::write(&truncated, &bytes[..64]).unwrap();
+
+    let mut face_rec = face_recognition();
+    let report = face_rec
+        .load_persons_db(db.path(), true, false)
+        .await
+        .unwrap();
+
+    assert_eq!(report.failed_images, vec![truncated]);
+    assert_eq!(face_rec.person_feature_count("Alice").await, Some(1));
+}
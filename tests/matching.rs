@@ -0,0 +1,229 @@
+//! Matching through the full `run` pipeline
+
+#![cfg(feature = "mock")]
+
+mod common;
+
+use common::{embedding, face, face_recognition, frame, stored, DIM};
+use facerust::mock::ScriptedDetector;
+use facerust::opencv::{core::Mat, prelude::*};
+use facerust::{
+    FaceRecognition, FaceRecognitionError, FeatureStore, InMemoryStore, InvalidFeaturePolicy,
+    RecognitionMode, Recognizer, StoredFeature,
+};
+
+/// An instance seeing one face per frame with embedding `probe` and a database of
+/// `persons`
+async fn recognizer_with(probe: Vec<f32>, persons: &[(&str, Vec<f32>)]) -> FaceRecognition {
+    let features = persons
+        .iter()
+        .enumerate()
+        .map(|(sequence, (person, values))| (*person, stored(values, sequence as u64)))
+        .collect();
+    recognizer_with_features(probe, features).await
+}
+
+async fn recognizer_with_features(
+    probe: Vec<f32>,
+    features: Vec<(&str, StoredFeature)>,
+) -> FaceRecognition {
+    let mut face_rec = face_recognition(vec![face()], vec![probe]);
+    let mut store = InMemoryStore::new();
+    for (person, feature) in features {
+        store.insert(person, feature);
+    }
+    face_rec.set_feature_store(Box::new(store)).await.unwrap();
+    face_rec
+}
+
+#[tokio::test]
+async fn recognizes_the_closest_person() {
+    let mut face_rec = recognizer_with(
+        embedding(0, 0.1),
+        &[("Alice", embedding(0, 0.0)), ("Bob", embedding(5, 0.0))],
+    )
+    .await;
+
+    let results = face_rec.run(&mut frame(), 0.4, false).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "Alice");
+    assert!(!results[0].unmatched);
+    assert!(results[0].score > 0.99);
+}
+
+#[tokio::test]
+async fn reports_unknown_below_the_threshold() {
+    let mut face_rec = recognizer_with(embedding(10, 0.0), &[("Alice", embedding(0, 0.0))]).await;
+
+    let results = face_rec.run(&mut frame(), 0.4, false).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert!(results[0].is_unknown());
+}
+
+#[tokio::test]
+async fn recognizes_a_person_named_unknown() {
+    let persons = [("Unknown", embedding(0, 0.0)), ("Bob", embedding(5, 0.0))];
+    for mode in [RecognitionMode::OpenSet, RecognitionMode::ClosedSet] {
+        let mut face_rec = recognizer_with(embedding(0, 0.1), &persons).await;
+        face_rec.set_recognition_mode(mode);
+
+        let results = face_rec.run(&mut frame(), 0.4, false).await.unwrap();
+
+        assert_eq!(results[0].name, "Unknown");
+        assert!(!results[0].unmatched, "{:?}", mode);
+    }
+}
+
+#[tokio::test]
+async fn compact_features_match_like_f32() {
+    let compactions: [fn(&mut StoredFeature); 3] = [
+        |_| {},
+        |feature| feature.to_half().unwrap(),
+        |feature| feature.quantize().unwrap(),
+    ];
+    let mut scores = Vec::new();
+    for compact in compactions {
+        let features = [("Alice", embedding(0, 0.0)), ("Bob", embedding(0, -0.5))]
+            .into_iter()
+            .enumerate()
+            .map(|(sequence, (person, values))| {
+                let mut feature = stored(&values, sequence as u64);
+                compact(&mut feature);
+                (person, feature)
+            })
+            .collect();
+        let mut face_rec = recognizer_with_features(embedding(0, 0.3), features).await;
+
+        let results = face_rec.run(&mut frame(), 0.4, false).await.unwrap();
+
+        assert_eq!(results[0].name, "Alice");
+        scores.push(results[0].score);
+    }
+    assert!((scores[1] - scores[0]).abs() < 1e-3, "{:?}", scores);
+    assert!((scores[2] - scores[0]).abs() < 1e-2, "{:?}", scores);
+}
+
+/// Alice's valid reference next to an empty and a non-finite one of other persons
+fn malformed_features() -> Vec<(&'static str, StoredFeature)> {
+    let mut empty = stored(&embedding(0, 0.0), 1);
+    empty.feature = Mat::default();
+    let mut not_finite = embedding(0, 0.0);
+    not_finite[1] = f32::NAN;
+    vec![
+        ("Alice", stored(&embedding(0, 0.0), 0)),
+        ("Bob", empty),
+        ("Carol", stored(&not_finite, 2)),
+    ]
+}
+
+#[tokio::test]
+async fn drops_malformed_stored_features() {
+    let mut face_rec = recognizer_with_features(embedding(0, 0.1), malformed_features()).await;
+
+    let results = face_rec.run(&mut frame(), 0.4, false).await.unwrap();
+
+    assert_eq!(results.len(), 1);
+    assert_eq!(results[0].name, "Alice");
+    let candidates = face_rec
+        .find_top_matches(&embedding(0, 0.1), 3)
+        .await
+        .unwrap();
+    let names: Vec<_> = candidates.iter().map(|m| m.name.as_str()).collect();
+    assert_eq!(names, ["Alice"]);
+}
+
+#[tokio::test]
+async fn rejects_malformed_stored_features_under_the_error_policy() {
+    let mut face_rec = face_recognition(Vec::new(), Vec::new());
+    face_rec.set_invalid_feature_policy(InvalidFeaturePolicy::Error);
+    let mut store = InMemoryStore::new();
+    for (person, feature) in malformed_features() {
+        store.insert(person, feature);
+    }
+
+    let result = face_rec.set_feature_store(Box::new(store)).await;
+
+    assert!(matches!(
+        result,
+        Err(FaceRecognitionError::InvalidStoredFeature { person, .. }) if person == "Bob"
+    ));
+}
+
+/// Recognizer whose embedding is the direction of the eye line it aligns by, standing in
+/// for a model whose alignment does not compensate head tilt
+struct EyeLineRecognizer;
+
+impl Recognizer for EyeLineRecognizer {
+    fn align_crop(
+        &self,
+        _frame: &Mat,
+        face: &Mat,
+        aligned: &mut Mat,
+    ) -> facerust::opencv::Result<()> {
+        let row = face.data_typed::<f32>()?;
+        let angle = (row[7] - row[5]).atan2(row[6] - row[4]);
+        *aligned = Mat::from_slice(&[angle])?.try_clone()?;
+        Ok(())
+    }
+
+    fn feature(&mut self, aligned: &Mat, feature: &mut Mat) -> facerust::opencv::Result<()> {
+        let angle = *aligned.at::<f32>(0)?;
+        let mut values = vec![0.0; DIM];
+        values[0] = angle.cos();
+        values[1] = angle.sin();
+        *feature = Mat::from_slice(&values)?.try_clone()?;
+        Ok(())
+    }
+
+    fn match_(
+        &self,
+        feature1: &Mat,
+        feature2: &Mat,
+        _dis_type: i32,
+    ) -> facerust::opencv::Result<f64> {
+        let a = feature1.data_typed::<f32>()?;
+        let b = feature2.data_typed::<f32>()?;
+        Ok(a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>() as f64)
+    }
+}
+
+/// `face()` with its landmarks rotated by `degrees` around the box center
+fn tilted_face(degrees: f32) -> [f32; 15] {
+    let mut row = face();
+    let (sin, cos) = degrees.to_radians().sin_cos();
+    let (cx, cy) = (row[0] + row[2] / 2.0, row[1] + row[3] / 2.0);
+    for point in 0..5 {
+        let (x, y) = (row[4 + 2 * point] - cx, row[5 + 2 * point] - cy);
+        row[4 + 2 * point] = cx + cos * x - sin * y;
+        row[5 + 2 * point] = cy + sin * x + cos * y;
+    }
+    row
+}
+
+#[tokio::test]
+async fn eye_line_correction_levels_tilted_faces() {
+    let mut scores = Vec::new();
+    for correction in [false, true] {
+        let detector = ScriptedDetector::new(vec![tilted_face(30.0)]);
+        let mut face_rec =
+            FaceRecognition::with_backends(Box::new(detector), Box::new(EyeLineRecognizer), None);
+        face_rec.set_eye_line_correction(correction);
+        let mut store = InMemoryStore::new();
+        store.insert("Alice", stored(&embedding(0, 0.0), 0));
+        face_rec.set_feature_store(Box::new(store)).await.unwrap();
+
+        let results = face_rec.run(&mut frame(), 0.4, false).await.unwrap();
+
+        assert_eq!(results[0].name, "Alice");
+        scores.push(results[0].score);
+    }
+    // Without the correction the 30° tilt costs cos(30°) of the score
+    assert!(
+        (scores[0] - 30f32.to_radians().cos()).abs() < 1e-3,
+        "{:?}",
+        scores
+    );
+    assert!(scores[1] > 0.999, "{:?}", scores);
+}